@@ -0,0 +1,133 @@
+//! Memory used by the memory data store holding 1M entities, compared to the size of their JSON.
+//!
+//! Allocations are counted by a global allocator wrapping the system one, the memory used by the store is
+//! what remains allocated once the uploaded JSON has been dropped.
+//!
+//! Run with `cargo bench --bench data_store_memory`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::schemas::data as schemas;
+use cedar_agent::DataStore;
+use rocket::serde::json::serde_json::{json, to_vec, Value};
+
+const ENTITIES: usize = 1_000_000;
+const GROUPS: usize = 1_000;
+
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn allocated(size: usize) {
+    let now = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            allocated(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Users with a few attributes, each a member of one of the groups.
+fn entities() -> Value {
+    let groups = (0..GROUPS).map(|index| {
+        json!({"uid": {"type": "Group", "id": format!("group-{}", index)}, "attrs": {}, "parents": []})
+    });
+    let users = (0..ENTITIES - GROUPS).map(|index| {
+        json!({
+            "uid": {"type": "User", "id": format!("user-{}", index)},
+            "attrs": {
+                "name": format!("User {}", index),
+                "level": index % 10,
+                "active": index % 2 == 0,
+                "manager": {"__entity": {"type": "User", "id": format!("user-{}", index / 10)}},
+            },
+            "parents": [{"type": "Group", "id": format!("group-{}", index % GROUPS)}],
+        })
+    });
+    Value::Array(groups.chain(users).collect())
+}
+
+fn megabytes(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+fn reset_peak() -> usize {
+    let now = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(now, Ordering::Relaxed);
+    now
+}
+
+#[tokio::main(flavor = "multi_thread", worker_threads = 2)]
+async fn main() {
+    let store = MemoryDataStore::new();
+    let baseline = ALLOCATED.load(Ordering::Relaxed);
+
+    let json = entities();
+    let json_size = to_vec(&json).unwrap().len();
+    let entities: schemas::Entities = rocket::serde::json::serde_json::from_value(json).unwrap();
+    let before = reset_peak();
+    let start = Instant::now();
+    store.update_entities(entities, None).await.unwrap();
+    let elapsed = start.elapsed();
+    let stored = ALLOCATED.load(Ordering::Relaxed) - baseline;
+    println!("Loaded {} entities in {:.3?}", ENTITIES, elapsed);
+    println!("{:<24} {:>10.1} MiB", "JSON", megabytes(json_size));
+    println!(
+        "{:<24} {:>10.1} MiB, {:.2}x the JSON",
+        "stored",
+        megabytes(stored),
+        stored as f64 / json_size as f64
+    );
+    let peak = PEAK.load(Ordering::Relaxed) - before;
+    println!("{:<24} {:>10.1} MiB above the upload", "peak while loading", megabytes(peak));
+
+    let before = reset_peak();
+    let start = Instant::now();
+    let exported = store.get_entities().await;
+    println!(
+        "Exported {} entities in {:.3?}, {:.1} MiB at peak",
+        exported.len(),
+        start.elapsed(),
+        megabytes(PEAK.load(Ordering::Relaxed) - before)
+    );
+
+    // A change only copies the entities it touches, the other ones are shared with the previous entities.
+    let moved = json!([{
+        "uid": {"type": "User", "id": "user-1"},
+        "attrs": {},
+        "parents": [{"type": "Group", "id": "group-2"}],
+    }]);
+    let start = Instant::now();
+    store.upsert_entities(rocket::serde::json::serde_json::from_value(moved).unwrap(), None).await.unwrap();
+    println!("Upserted an entity in {:.3?}", start.elapsed());
+    let start = Instant::now();
+    let reachable = store.reachable_entities(&["User::\"user-12345\"".parse().unwrap()]).await;
+    println!("Took the {} entities a request reaches in {:.3?}", reachable.iter().count(), start.elapsed());
+}
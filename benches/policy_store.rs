@@ -0,0 +1,190 @@
+//! Latency of single policy changes in the memory policy store holding 10k policies.
+//!
+//! Each change is timed from the call to its return, and for the time it held the write lock. The hold time is
+//! measured by a logger between the store asking for the write lock and releasing it, nothing else contends
+//! for the lock while the changes run. The cedar set of all policies is built by the first read after a change,
+//! which isn't part of the changes.
+//!
+//! Run with `cargo bench --bench policy_store`.
+
+use std::fs::File;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::schemas::policies::{Policy, PolicyUpdate, ValidationSettings};
+use cedar_agent::PolicyStore;
+use cedar_policy::Schema;
+use log::{LevelFilter, Log, Metadata, Record};
+
+const POLICIES: usize = 10_000;
+const ROUNDS: usize = 200;
+
+/// Records how long the policy store held its write lock.
+struct LockLogger {
+    requested: Mutex<Option<Instant>>,
+    held: Mutex<Vec<Duration>>,
+}
+
+impl Log for LockLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        match record.args().as_str() {
+            Some("Trying to acquire write lock on policies") => {
+                *self.requested.lock().unwrap() = Some(Instant::now());
+            }
+            Some("Released write lock on policies") => {
+                if let Some(requested) = self.requested.lock().unwrap().take() {
+                    self.held.lock().unwrap().push(requested.elapsed());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: LockLogger = LockLogger {
+    requested: Mutex::new(None),
+    held: Mutex::new(Vec::new()),
+};
+
+fn policy(index: usize) -> Policy {
+    Policy {
+        id: format!("policy-{}", index),
+        content: policy_content(index),
+        ..Default::default()
+    }
+}
+
+fn policy_content(index: usize) -> String {
+    format!(
+        r#"permit(principal == User::"user-{}", action == Action::"get", resource == Document::"doc-{}");"#,
+        index, index
+    )
+}
+
+fn schema() -> Schema {
+    Schema::from_file(File::open("examples/schema.json").unwrap()).unwrap()
+}
+
+struct Latencies(Vec<Duration>);
+
+impl Latencies {
+    fn percentile(&self, percentile: usize) -> Duration {
+        self.0[(self.0.len() - 1) * percentile / 100]
+    }
+
+    fn mean(&self) -> Duration {
+        self.0.iter().sum::<Duration>() / self.0.len() as u32
+    }
+}
+
+impl From<Vec<Duration>> for Latencies {
+    fn from(mut latencies: Vec<Duration>) -> Self {
+        latencies.sort();
+        Self(latencies)
+    }
+}
+
+/// Run `operation` `ROUNDS` times, returns its latencies and the times it held the write lock.
+async fn measure<F, Fut>(operation: F) -> (Latencies, Latencies)
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    LOGGER.held.lock().unwrap().clear();
+    let mut latencies = Vec::with_capacity(ROUNDS);
+    for round in 0..ROUNDS {
+        let start = Instant::now();
+        operation(round).await;
+        latencies.push(start.elapsed());
+    }
+    let held = std::mem::take(&mut *LOGGER.held.lock().unwrap());
+    (latencies.into(), held.into())
+}
+
+fn report(name: &str, (latencies, held): (Latencies, Latencies)) {
+    for (kind, latencies) in [("call", latencies), ("write lock", held)] {
+        println!(
+            "{:<20} {:<12} mean {:>10.3?}, p50 {:>10.3?}, p99 {:>10.3?}, max {:>10.3?}",
+            name,
+            kind,
+            latencies.mean(),
+            latencies.percentile(50),
+            latencies.percentile(99),
+            latencies.0.last().unwrap()
+        );
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+
+    let schema = schema();
+    let store = MemoryPolicyStore::new();
+    let start = Instant::now();
+    store
+        .update_policies(
+            (0..POLICIES).map(policy).collect(),
+            Some(schema.clone()),
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    println!("Loaded {} policies in {:.3?}", POLICIES, start.elapsed());
+
+    let measurement = measure(|round| {
+        let (store, schema) = (&store, schema.clone());
+        async move {
+            store
+                .create_policy(&policy(POLICIES + round), Some(schema), ValidationSettings::default())
+                .await
+                .unwrap();
+        }
+    })
+    .await;
+    report("create_policy", measurement);
+
+    let measurement = measure(|round| {
+        let (store, schema) = (&store, schema.clone());
+        async move {
+            let update = PolicyUpdate {
+                content: policy_content(round + 1),
+                metadata: None,
+                schedule: None,
+            };
+            store
+                .update_policy(format!("policy-{}", round), update, Some(schema), ValidationSettings::default())
+                .await
+                .unwrap();
+        }
+    })
+    .await;
+    report("update_policy", measurement);
+
+    let measurement = measure(|round| {
+        let store = &store;
+        async move {
+            store.set_policy_enabled(&format!("policy-{}", round), false).await.unwrap();
+        }
+    })
+    .await;
+    report("set_policy_enabled", measurement);
+
+    let measurement = measure(|round| {
+        let store = &store;
+        async move {
+            store.delete_policy(&format!("policy-{}", POLICIES + round)).await.unwrap();
+        }
+    })
+    .await;
+    report("delete_policy", measurement);
+}
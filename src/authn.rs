@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use rocket::request::{FromRequest, Outcome};
 use rocket_okapi::gen::OpenApiGenerator;
 use rocket_okapi::okapi;
@@ -7,6 +9,8 @@ use rocket_okapi::okapi::openapi3::{
 use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
 
 use crate::config::Config;
+use crate::services::tenants::TenantStore;
+use crate::tenancy::RequestTenant;
 
 const AUTHENTICATION_HEADER: &'static str = "Authorization";
 
@@ -24,6 +28,13 @@ impl ApiKey {
             None => false,
         }
     }
+
+    /// The API key of the tenant addressed by the request, if it has one.
+    async fn tenant_key(request: &rocket::Request<'_>) -> Option<String> {
+        let name = RequestTenant::of(request).0.as_ref()?;
+        let tenant_store = request.rocket().state::<Arc<dyn TenantStore>>()?;
+        tenant_store.get_tenant(name).await.ok()?.api_key.clone()
+    }
 }
 
 #[rocket::async_trait]
@@ -35,6 +46,15 @@ impl<'r> FromRequest<'r> for ApiKey {
             .rocket()
             .state::<Config>()
             .map(|my_config| ApiKey(my_config.authentication.clone()));
+        if let Some(tenant_key) = Self::tenant_key(request).await {
+            if request.headers().get_one(AUTHENTICATION_HEADER) == Some(tenant_key.as_str()) {
+                return Outcome::Success(ApiKey(Some(tenant_key)));
+            }
+            // Without an agent token the tenant key is the only accepted one.
+            if !matches!(token, Some(ApiKey(Some(_)))) {
+                return Outcome::Error((rocket::http::Status::Unauthorized, ()));
+            }
+        }
         match token {
             Some(token) => {
                 if token.validate_matching_header(request) {
@@ -8,6 +8,10 @@ use log::LevelFilter;
 
 use serde::{Deserialize, Serialize};
 
+use crate::schemas::authorization::PolicyIndexMode;
+use crate::schemas::data::IntegrityMode;
+use crate::schemas::policies::{PolicyValidationMode, ValidationLevel, ValidationSettings};
+
 #[derive(Parser, Serialize, Deserialize, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Config {
@@ -25,6 +29,26 @@ pub struct Config {
     pub policies: Option<PathBuf>,
     #[arg(short, long)]
     pub schema: Option<PathBuf>,
+    #[arg(long)]
+    pub bundle: Option<PathBuf>,
+    #[arg(long, value_enum)]
+    pub validation: Option<ValidationLevel>,
+    #[arg(long, value_enum)]
+    pub validation_mode: Option<PolicyValidationMode>,
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub test_gate: Option<bool>,
+    #[arg(long, value_enum)]
+    pub policy_index: Option<PolicyIndexMode>,
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub reachable_entities: Option<bool>,
+    #[arg(long)]
+    pub policy_id_pattern: Option<String>,
+    #[arg(long, value_enum)]
+    pub integrity: Option<IntegrityMode>,
+    #[arg(long)]
+    pub data_sources: Option<PathBuf>,
+    #[arg(long)]
+    pub csv_mapping: Option<PathBuf>,
 }
 
 impl Into<rocket::figment::Figment> for &Config {
@@ -50,6 +74,9 @@ impl Into<rocket::figment::Figment> for &Config {
         if let Some(schema) = self.schema.borrow() {
             config = config.merge(("schema", schema));
         }
+        if let Some(bundle) = self.bundle.borrow() {
+            config = config.merge(("bundle", bundle));
+        }
 
         config
     }
@@ -64,7 +91,17 @@ impl Config {
             log_level: None,
             data: None,
             policies: None,
-            schema: None
+            schema: None,
+            bundle: None,
+            validation: None,
+            validation_mode: None,
+            test_gate: None,
+            policy_index: None,
+            reachable_entities: None,
+            policy_id_pattern: None,
+            integrity: None,
+            data_sources: None,
+            csv_mapping: None,
         }
     }
 
@@ -78,11 +115,46 @@ impl Config {
             config.data = c.data.or(config.data);
             config.policies = c.policies.or(config.policies);
             config.schema = c.schema.or(config.schema);
+            config.bundle = c.bundle.or(config.bundle);
+            config.validation = c.validation.or(config.validation);
+            config.validation_mode = c.validation_mode.or(config.validation_mode);
+            config.test_gate = c.test_gate.or(config.test_gate);
+            config.policy_index = c.policy_index.or(config.policy_index);
+            config.reachable_entities = c.reachable_entities.or(config.reachable_entities);
+            config.policy_id_pattern = c.policy_id_pattern.or(config.policy_id_pattern);
+            config.integrity = c.integrity.or(config.integrity);
+            config.data_sources = c.data_sources.or(config.data_sources);
+            config.csv_mapping = c.csv_mapping.or(config.csv_mapping);
         }
 
         config
     }
 
+    pub fn validation_settings(&self) -> ValidationSettings {
+        ValidationSettings {
+            validation: self.validation,
+            validation_mode: self.validation_mode,
+        }
+    }
+
+    /// Whether policy and entity changes are checked against the stored policy tests.
+    pub fn test_gate_enabled(&self) -> bool {
+        self.test_gate.unwrap_or(false)
+    }
+
+    pub fn policy_index_mode(&self) -> PolicyIndexMode {
+        self.policy_index.unwrap_or_default()
+    }
+
+    /// Whether requests are evaluated only on the stored entities they can reach.
+    pub fn reachable_entities_enabled(&self) -> bool {
+        self.reachable_entities.unwrap_or(false)
+    }
+
+    pub fn integrity_mode(&self) -> IntegrityMode {
+        self.integrity.unwrap_or_default()
+    }
+
     fn from_args() -> Self {
         Self::parse()
     }
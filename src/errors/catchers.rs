@@ -12,6 +12,8 @@ pub fn handle_500(status: Status, req: &Request<'_>) -> ErrorResponse {
         reason: format!("An error occurred during handling {req_url}"),
         description: "An unexpected error has occurred".to_owned(),
         code: status.code,
+        policy_errors: None,
+        failed_tests: None,
     };
 }
 
@@ -22,6 +24,8 @@ pub fn handle_400(_req: &Request<'_>) -> ErrorResponse {
             .to_owned(),
         reason: "The request content is not valid".to_owned(),
         code: 400,
+        policy_errors: None,
+        failed_tests: None,
     };
 }
 
@@ -32,5 +36,19 @@ pub fn handle_404(req: &Request<'_>) -> ErrorResponse {
         description: format!("The requested resource {req_url} was not found"),
         reason: "The requested resource was not found".to_owned(),
         code: 404,
+        policy_errors: None,
+        failed_tests: None,
     };
 }
+
+#[catch(413)]
+pub fn handle_413(req: &Request<'_>) -> ErrorResponse {
+    let req_url = req.uri();
+    ErrorResponse {
+        description: format!("The request body sent to {req_url} exceeds the tenant payload quota"),
+        reason: "The request content is too large".to_owned(),
+        code: 413,
+        policy_errors: None,
+        failed_tests: None,
+    }
+}
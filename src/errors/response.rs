@@ -16,6 +16,8 @@ use thiserror::Error;
 use schemas::{bad_request_response, unauthorized_response};
 
 use crate::errors::schemas;
+use crate::schemas::policies::PolicyError;
+use crate::schemas::policy_tests::PolicyTestResult;
 
 /// Error messages returned to user
 #[derive(Debug, Serialize, JsonSchema)]
@@ -26,6 +28,12 @@ pub struct ErrorResponse {
     pub description: String,
     // HTTP Status Code returned
     pub code: u16,
+    /// Problems found in the submitted policies, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy_errors: Option<Vec<PolicyError>>,
+    /// Stored policy tests failing with the requested change, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_tests: Option<Vec<PolicyTestResult>>,
 }
 
 impl<'r> Responder<'r, 'static> for ErrorResponse {
@@ -51,6 +59,20 @@ pub enum AgentError {
         reason
     )]
     BadRequest { reason: String },
+    #[error(
+        "The policies in the request are invalid: {}",
+        errors.iter().map(|e| e.to_string()).collect::<Vec<String>>().join("; ")
+    )]
+    InvalidPolicies { errors: Vec<PolicyError> },
+    #[error("{}", reason)]
+    QuotaExceeded { reason: String },
+    #[error("{}", reason)]
+    PayloadTooLarge { reason: String },
+    #[error(
+        "The change makes stored policy tests fail: {}",
+        results.iter().map(|r| r.id.clone()).collect::<Vec<String>>().join(", ")
+    )]
+    TestsFailed { results: Vec<PolicyTestResult> },
 }
 
 impl AgentError {
@@ -60,6 +82,10 @@ impl AgentError {
             NotFound { object: _, id: _ } => Status::NotFound,
             Duplicate { object: _, id: _ } => Status::Conflict,
             BadRequest { reason: _ } => Status::BadRequest,
+            InvalidPolicies { errors: _ } => Status::BadRequest,
+            QuotaExceeded { reason: _ } => Status::Forbidden,
+            PayloadTooLarge { reason: _ } => Status::PayloadTooLarge,
+            TestsFailed { results: _ } => Status::UnprocessableEntity,
         }
     }
 
@@ -76,6 +102,12 @@ impl AgentError {
             "The requested resource was not found".to_owned()
         } else if status == Status::Conflict {
             "The requested resource already exists".to_owned()
+        } else if status == Status::Forbidden {
+            "The request exceeds the tenant quota".to_owned()
+        } else if status == Status::PayloadTooLarge {
+            "The request content is too large".to_owned()
+        } else if status == Status::UnprocessableEntity {
+            "The request was rejected by the stored policy tests".to_owned()
         } else if status.code >= 400 && status.code < 500 {
             "An unexpected client error has occurred".to_owned()
         } else {
@@ -90,10 +122,20 @@ impl AgentError {
 
 impl<'r> Responder<'r, 'static> for AgentError {
     fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let code = self.status().code;
+        let reason = self.title();
+        let description = self.message();
+        let (policy_errors, failed_tests) = match self {
+            AgentError::InvalidPolicies { errors } => (Some(errors), None),
+            AgentError::TestsFailed { results } => (None, Some(results)),
+            _ => (None, None),
+        };
         let res = ErrorResponse {
-            code: self.status().code,
-            reason: self.title(),
-            description: self.message(),
+            code,
+            reason,
+            description,
+            policy_errors,
+            failed_tests,
         };
         // Convert object to json
         let body = serde_json::to_string(res.borrow()).unwrap();
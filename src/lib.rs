@@ -7,5 +7,6 @@ mod errors;
 mod routes;
 pub mod schemas;
 mod services;
+mod tenancy;
 
 pub use services::*;
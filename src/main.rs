@@ -3,6 +3,7 @@ extern crate rocket;
 
 use std::borrow::Borrow;
 use std::process::ExitCode;
+use std::sync::Arc;
 
 use log::{error, info};
 use rocket::catchers;
@@ -14,8 +15,12 @@ use crate::services::data::memory::MemoryDataStore;
 use crate::services::data::DataStore;
 use crate::services::policies::memory::MemoryPolicyStore;
 use crate::services::policies::PolicyStore;
+use crate::services::policy_tests::memory::MemoryPolicyTestStore;
+use crate::services::policy_tests::{PolicyTestStore, TestGate};
 use crate::services::schema::memory::MemorySchemaStore;
 use crate::services::schema::SchemaStore;
+use crate::services::tenants::memory::MemoryTenantStore;
+use crate::services::tenants::TenantStore;
 
 mod authn;
 mod common;
@@ -25,21 +30,38 @@ mod logger;
 mod routes;
 mod schemas;
 mod services;
+mod tenancy;
 
 #[rocket::main]
 async fn main() -> ExitCode {
     let config = config::init();
     logger::init(&config);
     let server_config: rocket::figment::Figment = config.borrow().into();
+    let policy_id_pattern = config.policy_id_pattern.clone();
+    let integrity = config.integrity_mode();
     let launch_result = rocket::custom(server_config)
         .attach(common::DefaultContentType::new(ContentType::JSON))
+        .attach(tenancy::TenantPrefix)
         .attach(services::schema::load_from_file::InitSchemaFairing)
         .attach(services::data::load_from_file::InitDataFairing)
         .attach(services::policies::load_from_file::InitPoliciesFairing)
+        .attach(services::bundle::load_from_file::InitBundleFairing)
+        .attach(services::policies::schedule::PolicyScheduleFairing)
+        .attach(services::data::expiry::EntityExpiryFairing)
+        .attach(services::data::sources::DataSourcesFairing)
         .manage(config)
-        .manage(Box::new(MemoryPolicyStore::new()) as Box<dyn PolicyStore>)
-        .manage(Box::new(MemoryDataStore::new()) as Box<dyn DataStore>)
-        .manage(Box::new(MemorySchemaStore::new()) as Box<dyn SchemaStore>)
+        .manage(Arc::new(MemoryPolicyStore::new().with_id_pattern(policy_id_pattern.clone())) as Arc<dyn PolicyStore>)
+        .manage(Arc::new(MemoryDataStore::new().with_integrity(integrity)) as Arc<dyn DataStore>)
+        .manage(Arc::new(MemorySchemaStore::new()) as Arc<dyn SchemaStore>)
+        .manage(
+            Arc::new(
+                MemoryTenantStore::new()
+                    .with_policy_id_pattern(policy_id_pattern)
+                    .with_integrity(integrity),
+            ) as Arc<dyn TenantStore>,
+        )
+        .manage(Arc::new(MemoryPolicyTestStore::new()) as Arc<dyn PolicyTestStore>)
+        .manage(Arc::new(TestGate::default()))
         .manage(cedar_policy::Authorizer::new())
         .register(
             "/",
@@ -47,6 +69,7 @@ async fn main() -> ExitCode {
                 errors::catchers::handle_500,
                 errors::catchers::handle_404,
                 errors::catchers::handle_400,
+                errors::catchers::handle_413,
             ],
         )
         .mount("/", openapi_get_routes![routes::health,])
@@ -56,18 +79,53 @@ async fn main() -> ExitCode {
             openapi_get_routes![
                 routes::health,
                 routes::policies::get_policies,
+                routes::policies::search_policies,
                 routes::policies::get_policy,
                 routes::policies::create_policy,
                 routes::policies::update_policies,
                 routes::policies::update_policy,
+                routes::policies::apply_policy_operations,
+                routes::policies::analyze_policy_set,
+                routes::policies::analyze_policy_impact,
+                routes::policies::analyze_policy_impact_lines,
+                routes::policies::get_invalid_policies,
+                routes::policies::get_policy_transitions,
+                routes::policies::disable_policy,
+                routes::policies::enable_policy,
                 routes::policies::delete_policy,
                 routes::data::get_entities,
+                routes::data::import_entities,
                 routes::data::update_entities,
+                routes::data::apply_entity_operations,
                 routes::data::delete_entities,
+                routes::data::check_integrity,
+                routes::data::get_data_sources,
+                routes::data::query_entities,
+                routes::data::get_entity,
+                routes::data::get_entity_ancestors,
+                routes::data::get_entity_descendants,
+                routes::data::get_entity_paths,
+                routes::data::get_entity_graph,
+                routes::data::update_entity,
+                routes::data::delete_entity,
+                routes::data::upsert_entities,
+                routes::data::delete_entities_by_uid,
                 routes::authorization::is_authorized,
                 routes::schema::get_schema,
                 routes::schema::update_schema,
-                routes::schema::delete_schema
+                routes::schema::delete_schema,
+                routes::bundle::get_bundle,
+                routes::bundle::update_bundle,
+                routes::policy_tests::get_tests,
+                routes::policy_tests::get_test,
+                routes::policy_tests::create_test,
+                routes::policy_tests::update_test,
+                routes::policy_tests::delete_test,
+                routes::policy_tests::run_stored_tests,
+                routes::tenants::get_tenants,
+                routes::tenants::get_tenant,
+                routes::tenants::create_tenant,
+                routes::tenants::delete_tenant
             ],
         )
         .mount(
@@ -1,26 +1,65 @@
-use cedar_policy::Authorizer;
+use cedar_policy::{Authorizer, Entities, Request};
 
-use log::info;
+use log::{error, info};
 
 use rocket::serde::json::Json;
 use rocket::{post, State};
 use rocket_okapi::openapi;
 
 use crate::authn::ApiKey;
+use crate::config::Config;
 use crate::errors::response::AgentError;
-use crate::schemas::authorization::{AuthorizationAnswer, AuthorizationCall, AuthorizationRequest};
-use crate::{DataStore, PolicyStore};
+use crate::services::policies::PolicyStore;
+use crate::tenancy::{Stores, TenantJson};
+use crate::schemas::authorization::{
+    AuthorizationAnswer, AuthorizationCall, AuthorizationRequest, PolicyIndexMode,
+};
+
+/// Evaluate the request against the policies selected by the index mode.
+/// `verify` compares the answer with the one of all policies on `all_entities`, every stored entity included.
+async fn evaluate(
+    authorizer: &Authorizer,
+    policy_store: &dyn PolicyStore,
+    mode: PolicyIndexMode,
+    request: &Request,
+    entities: &Entities,
+    all_entities: Option<&Entities>,
+) -> AuthorizationAnswer {
+    match mode {
+        PolicyIndexMode::Off => {
+            let policies = policy_store.policy_set().await;
+            authorizer.is_authorized(request, &policies, entities).into()
+        }
+        PolicyIndexMode::On => {
+            let policies = policy_store.matching_policy_set(request, entities).await;
+            authorizer.is_authorized(request, &policies, entities).into()
+        }
+        PolicyIndexMode::Verify => {
+            let matching = policy_store.matching_policy_set(request, entities).await;
+            let policies = policy_store.policy_set().await;
+            let indexed = AuthorizationAnswer::from(authorizer.is_authorized(request, &matching, entities));
+            let all_entities = all_entities.unwrap_or(entities);
+            let full = AuthorizationAnswer::from(authorizer.is_authorized(request, &policies, all_entities));
+            if indexed != full {
+                error!(
+                    "Indexed evaluation of {:?} answered {:?}, full evaluation answered {:?}",
+                    request, indexed, full
+                );
+            }
+            full
+        }
+    }
+}
 
 #[openapi]
 #[post("/is_authorized", format = "json", data = "<authorization_call>")]
 pub async fn is_authorized(
     _auth: ApiKey,
-    policy_store: &State<Box<dyn PolicyStore>>,
-    data_store: &State<Box<dyn DataStore>>,
+    stores: Stores<'_>,
+    config: &State<Config>,
     authorizer: &State<Authorizer>,
-    authorization_call: Json<AuthorizationCall>,
+    authorization_call: TenantJson<AuthorizationCall>,
 ) -> Result<Json<AuthorizationAnswer>, AgentError> {
-    let policies = policy_store.policy_set().await;
     let query: AuthorizationRequest = match authorization_call.into_inner().try_into() {
         Ok(query) => query,
         Err(err) => {
@@ -30,9 +69,31 @@ pub async fn is_authorized(
         }
     };
 
-    // Temporary solution to override fetching entities from the datastore by directly passing it to the REST body.
-    // Eventually this logic will be replaced in favor of performing live patch updates
-    let stored_entities = data_store.entities().await;
+    // Entities passed in the request body are only used for this request,
+    // the stored entities are changed with `PATCH /v1/data`.
+    // With `reachable_entities` only the stored entities the request can reach are evaluated, policies may name
+    // more in their conditions.
+    let mode = config.policy_index_mode();
+    let reduced = config.reachable_entities_enabled() && query.entity_roots().is_some();
+    let stored_entities = match query.entity_roots() {
+        Some(mut roots) if reduced => {
+            roots.extend(stores.policy_store().condition_entities().await);
+            stores.data_store().reachable_entities(&roots).await
+        }
+        Some(_) => stores.data_store().entities().await,
+        None => Entities::empty(),
+    };
+    let all_entities = match mode {
+        PolicyIndexMode::Verify if reduced => match query.request_entities(stores.data_store().entities().await) {
+            Ok(entities) => Some(entities),
+            Err(err) => {
+                return Err(AgentError::BadRequest {
+                    reason: err.to_string(),
+                })
+            }
+        },
+        _ => None,
+    };
     let (request, entities) = match query.get_request_entities(stored_entities) {
         Ok(result) => result,
         Err(err)=> {
@@ -43,6 +104,14 @@ pub async fn is_authorized(
     };
 
     info!("Querying cedar using {:?}", &request);
-    let answer = authorizer.is_authorized(&request, &policies, &entities);
-    Ok(Json::from(AuthorizationAnswer::from(answer)))
+    let answer = evaluate(
+        authorizer,
+        stores.policy_store(),
+        mode,
+        &request,
+        &entities,
+        all_entities.as_ref(),
+    )
+    .await;
+    Ok(Json::from(answer))
 }
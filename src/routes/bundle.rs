@@ -0,0 +1,65 @@
+use rocket::serde::json::Json;
+use rocket::{get, put, State};
+use rocket_okapi::openapi;
+
+use crate::authn::ApiKey;
+use crate::config::Config;
+use crate::errors::response::AgentError;
+use crate::routes::policy_tests::{candidate_policy_set, check_tests, hold_gate};
+use crate::schemas::bundle::Bundle;
+use crate::services::bundle::errors::BundleError;
+use crate::services::bundle::{export_bundle, import_bundle};
+use crate::tenancy::{Stores, TenantJson};
+
+#[openapi]
+#[get("/bundle")]
+pub async fn get_bundle(
+    _auth: ApiKey,
+    stores: Stores<'_>,
+) -> Result<Json<Bundle>, AgentError> {
+    Ok(Json::from(
+        export_bundle(stores.policy_store(), stores.data_store(), stores.schema_store()).await,
+    ))
+}
+
+#[openapi]
+#[put("/bundle", format = "json", data = "<bundle>")]
+pub async fn update_bundle(
+    _auth: ApiKey,
+    stores: Stores<'_>,
+    config: &State<Config>,
+    bundle: TenantJson<Bundle>,
+) -> Result<Json<Bundle>, AgentError> {
+    let bundle = bundle.into_inner();
+    let _gate = hold_gate(&stores, config).await;
+    if config.test_gate_enabled() {
+        let schema: Option<cedar_policy::Schema> = if bundle.schema.is_empty() {
+            None
+        } else {
+            Some(bundle.schema.clone().try_into().map_err(|err: cedar_policy::SchemaError| AgentError::BadRequest {
+                reason: BundleError::from(err).to_string(),
+            })?)
+        };
+        let entities = bundle.entities.convert_to_cedar_entities(&schema).map_err(|err| AgentError::BadRequest {
+            reason: BundleError::InvalidEntities(err.to_string()).to_string(),
+        })?;
+        check_tests(&stores, Some(candidate_policy_set(&bundle.policies)?), Some(entities)).await?;
+    }
+
+    let imported = import_bundle(
+        stores.policy_store(),
+        stores.data_store(),
+        stores.schema_store(),
+        bundle,
+        config.validation_settings(),
+    )
+    .await;
+    match imported {
+        Ok(bundle) => Ok(Json::from(bundle)),
+        Err(BundleError::InvalidPolicies(errors)) => Err(AgentError::InvalidPolicies { errors }),
+        Err(BundleError::QuotaExceeded(reason)) => Err(AgentError::QuotaExceeded { reason }),
+        Err(err) => Err(AgentError::BadRequest {
+            reason: err.to_string(),
+        }),
+    }
+}
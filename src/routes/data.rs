@@ -1,47 +1,415 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use cedar_policy::EntityUid;
+use rocket::data::{ByteUnit, Data, Limits, ToByteUnit};
+use rocket::futures::stream::{BoxStream, StreamExt};
+use rocket::http::ContentType;
 use rocket::response::status;
+use rocket::response::stream::ByteStream;
+use rocket::tokio::io::BufReader;
 
 use rocket::serde::json::Json;
-use rocket::{delete, get, put, State};
+use rocket::{delete, get, patch, post, put, Either, State};
 use rocket_okapi::openapi;
 
 use crate::authn::ApiKey;
+use crate::config::Config;
 use crate::errors::response::AgentError;
+use crate::tenancy::{Stores, TenantJson};
+use crate::routes::policy_tests::{check_tests, hold_gate};
 use crate::schemas::data as schemas;
-use crate::{DataStore, SchemaStore};
+use crate::services::data::errors::{DataImportError, DataStoreError};
+use crate::services::data::hierarchy;
+use crate::services::data::import::{self, CsvReader, EntityReader, NdjsonReader};
+use crate::services::data::sources::DataSources;
+use crate::services::data::CandidateCheck;
+use crate::services::policy_tests::runner::entities_check;
+use crate::services::policies::search;
+
+/// The size of an import body when no `import` limit is configured.
+const DEFAULT_IMPORT_LIMIT: ByteUnit = ByteUnit::Gibibyte(1);
+
+fn data_error_response(err: Box<dyn Error>) -> AgentError {
+    // Errors of the route's own checks are passed through the store as they are.
+    let err = match err.downcast::<AgentError>() {
+        Ok(err) => return *err,
+        Err(err) => err,
+    };
+    match err.downcast_ref::<DataStoreError>() {
+        Some(DataStoreError::QuotaExceeded(_)) => AgentError::QuotaExceeded {
+            reason: err.to_string(),
+        },
+        _ if matches!(err.downcast_ref::<DataImportError>(), Some(DataImportError::TooLarge(_))) => {
+            AgentError::PayloadTooLarge {
+                reason: err.to_string(),
+            }
+        }
+        _ => AgentError::BadRequest {
+            reason: err.to_string(),
+        },
+    }
+}
+
+fn entity_uid(reference: &schemas::EntityReference) -> Result<EntityUid, AgentError> {
+    EntityUid::try_from(reference).map_err(|err| AgentError::BadRequest {
+        reason: err.to_string(),
+    })
+}
+
+fn entity_not_found(uid: &EntityUid) -> AgentError {
+    AgentError::NotFound {
+        id: uid.to_string(),
+        object: "entity",
+    }
+}
 
+/// Upsert and remove entities, rejecting the change when it makes a stored test fail.
+async fn change_entities(
+    stores: &Stores<'_>,
+    config: &Config,
+    entities: schemas::Entities,
+    removed: &[EntityUid],
+) -> Result<Option<schemas::Entities>, AgentError> {
+    let schema = stores.schema_store().get_validator_schema().await;
+    let _gate = hold_gate(stores, config).await;
+    if config.test_gate_enabled() {
+        let candidate = stores
+            .data_store()
+            .candidate_entities(&entities, removed, schema.clone())
+            .await
+            .map_err(data_error_response)?;
+        check_tests(stores, None, Some(candidate)).await?;
+    }
+    if !removed.is_empty() {
+        let removed = stores.data_store().remove_entities(removed).await;
+        removed.map_err(data_error_response)?;
+    }
+    if entities.len() == 0 {
+        return Ok(None);
+    }
+    let upserted = stores.data_store().upsert_entities(entities, schema).await;
+    upserted.map(Some).map_err(data_error_response)
+}
+
+/// The stored entities as a JSON array, or streamed one per line with `format=ndjson`.
 #[openapi]
-#[get("/data")]
+#[get("/data?<format>")]
 pub async fn get_entities(
     _auth: ApiKey,
-    data_store: &State<Box<dyn DataStore>>,
-) -> Result<Json<schemas::Entities>, AgentError> {
-    Ok(Json::from(data_store.get_entities().await))
+    format: Option<schemas::ExportFormat>,
+    stores: Stores<'_>,
+) -> Result<Either<Json<schemas::Entities>, (ContentType, ByteStream<BoxStream<'static, Vec<u8>>>)>, AgentError> {
+    match format.unwrap_or_default() {
+        schemas::ExportFormat::Json => Ok(Either::Left(Json::from(stores.data_store().get_entities().await))),
+        schemas::ExportFormat::Ndjson => {
+            let chunks = stores.data_store().export_entities().await;
+            let ndjson = ContentType::new("application", "x-ndjson");
+            Ok(Either::Right((ndjson, ByteStream(chunks.map(|chunk| import::to_ndjson(&chunk)).boxed()))))
+        }
+    }
+}
+
+/// Import entities streamed as NDJSON or CSV, upserting them or replacing all the stored entities with them.
+/// The import is rejected when the imported entities make a stored test fail.
+#[openapi]
+#[post("/data/import?<format>&<replace>", data = "<data>")]
+pub async fn import_entities(
+    _auth: ApiKey,
+    format: schemas::ImportFormat,
+    replace: Option<bool>,
+    stores: Stores<'_>,
+    config: &State<Config>,
+    limits: &Limits,
+    data: Data<'_>,
+) -> Result<Json<schemas::ImportSummary>, AgentError> {
+    let mut limit = limits.get("import").unwrap_or(DEFAULT_IMPORT_LIMIT);
+    if let Some(max) = stores.payload_limit() {
+        limit = limit.min(max.bytes());
+    }
+    // One more byte is read to tell a body of exactly the limit from a larger one.
+    let body = BufReader::new(data.open(limit + 1.bytes()));
+    let mut reader: Box<dyn EntityReader + '_> = match format {
+        schemas::ImportFormat::Ndjson => Box::new(NdjsonReader::new(body).with_limit(limit.as_u64())),
+        schemas::ImportFormat::Csv => {
+            let path = config.csv_mapping.clone().ok_or_else(|| AgentError::BadRequest {
+                reason: "no CSV mapping is configured".to_string(),
+            })?;
+            let mapping = import::load_csv_mapping(path).await.map_err(data_error_response)?;
+            let reader = CsvReader::new(body, mapping).map_err(|err| data_error_response(err.into()))?;
+            Box::new(reader.with_limit(limit.as_u64()))
+        }
+    };
+    let schema = stores.schema_store().get_validator_schema().await;
+    let _gate = hold_gate(&stores, config).await;
+    let check = match config.test_gate_enabled() {
+        true => entities_check(stores.test_store(), stores.policy_store()).await,
+        false => None,
+    };
+    let check = check.map(|check| {
+        move |entities: &cedar_policy::Entities| {
+            check(entities).map_err(|results| Box::from(AgentError::TestsFailed { results }))
+        }
+    });
+    let check = check.as_ref().map(|check| check as &CandidateCheck);
+    let replace = replace.unwrap_or(false);
+    let imported = stores.data_store().import_entities(reader.as_mut(), replace, schema, check).await;
+    imported.map(|imported| Json::from(schemas::ImportSummary { imported })).map_err(data_error_response)
 }
 
 #[openapi]
 #[put("/data", format = "json", data = "<entities>")]
 pub async fn update_entities(
     _auth: ApiKey,
-    data_store: &State<Box<dyn DataStore>>,
-    schema_store: &State<Box<dyn SchemaStore>>,
-    entities: Json<schemas::Entities>,
+    stores: Stores<'_>,
+    config: &State<Config>,
+    entities: TenantJson<schemas::Entities>,
 ) -> Result<Json<schemas::Entities>, AgentError> {
-    let schema = schema_store.get_cedar_schema().await;
+    let _gate = hold_gate(&stores, config).await;
+    if config.test_gate_enabled() {
+        let schema = stores.schema_store().get_cedar_schema().await;
+        let candidate = entities.convert_to_cedar_entities(&schema).map_err(|err| data_error_response(err.into()))?;
+        check_tests(&stores, None, Some(candidate)).await?;
+    }
 
-    match data_store.update_entities(entities.into_inner(), schema).await {
-        Ok(entities) => Ok(Json::from(entities)),
-        Err(err) => Err(AgentError::BadRequest {
-            reason: err.to_string(),
-        }),
+    let schema = stores.schema_store().get_validator_schema().await;
+    let updated = stores.data_store().update_entities(entities.into_inner(), schema).await;
+    updated.map(Json::from).map_err(data_error_response)
+}
+
+/// Apply a list of entity operations, either all of them or none.
+/// The operations are rejected when they make a stored test fail.
+#[openapi]
+#[patch("/data", format = "json", data = "<operations>")]
+pub async fn apply_entity_operations(
+    _auth: ApiKey,
+    stores: Stores<'_>,
+    config: &State<Config>,
+    operations: TenantJson<Vec<schemas::EntityOperation>>,
+) -> Result<Json<schemas::DataRevision>, AgentError> {
+    let schema = stores.schema_store().get_validator_schema().await;
+    let _gate = hold_gate(&stores, config).await;
+    if config.test_gate_enabled() {
+        let candidate = stores
+            .data_store()
+            .candidate_operations(&operations, schema.clone())
+            .await
+            .map_err(data_error_response)?;
+        check_tests(&stores, None, Some(candidate)).await?;
     }
+    let revision = stores.data_store().apply_entity_operations(operations.into_inner(), schema).await;
+    revision.map(Json::from).map_err(data_error_response)
 }
 
+/// Delete every stored entity, rejected when no entities make a stored test fail.
 #[openapi]
 #[delete("/data")]
 pub async fn delete_entities(
     _auth: ApiKey,
-    data_store: &State<Box<dyn DataStore>>,
+    stores: Stores<'_>,
+    config: &State<Config>,
+) -> Result<status::NoContent, AgentError> {
+    let _gate = hold_gate(&stores, config).await;
+    if config.test_gate_enabled() {
+        check_tests(&stores, None, Some(cedar_policy::Entities::empty())).await?;
+    }
+    stores.data_store().delete_entities().await;
+    Ok(status::NoContent)
+}
+
+/// The dangling references and cycles among the stored entities, and the policies referring to missing entities.
+#[openapi]
+#[get("/data/integrity")]
+pub async fn check_integrity(
+    _auth: ApiKey,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::IntegrityReport>, AgentError> {
+    let schema = stores.schema_store().get_validator_schema().await;
+    let mut report = stores.data_store().check_integrity(schema).await;
+    let entities = stores.data_store().get_entities().await;
+    let stored: HashSet<EntityUid> = entities.iter().filter_map(|entity| entity.uid()).collect();
+    let policies = stores.policy_store().get_policies().await;
+    report.policies = search::missing_entity_references(&policies, |uid| stored.contains(uid));
+    Ok(Json::from(report))
+}
+
+/// The sources the entities are fetched from, with the outcome of their last fetch.
+#[openapi]
+#[get("/data/sources")]
+pub async fn get_data_sources(
+    _auth: ApiKey,
+    stores: Stores<'_>,
+    sources: &State<Arc<DataSources>>,
+) -> Result<Json<Vec<schemas::DataSourceStatus>>, AgentError> {
+    // Sources fill the agent data store, tenants have none.
+    match stores {
+        Stores::Agent { .. } => Ok(Json::from(sources.status().await)),
+        Stores::Tenant(_) => Ok(Json::from(Vec::new())),
+    }
+}
+
+/// Stored entities filtered by type, ancestor and attribute, one page at a time.
+#[openapi]
+#[get("/data/entities?<query..>")]
+pub async fn query_entities(
+    _auth: ApiKey,
+    query: schemas::EntityQuery,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::EntityPage>, AgentError> {
+    let page = stores.data_store().query_entities(&query).await;
+    page.map(Json::from).map_err(data_error_response)
+}
+
+#[openapi]
+#[get("/data/entities/<entity_type>/<id>")]
+pub async fn get_entity(
+    _auth: ApiKey,
+    entity_type: String,
+    id: String,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::Entity>, AgentError> {
+    let reference = schemas::EntityReference { entity_type, id };
+    let uid = entity_uid(&reference)?;
+    let entity = stores.data_store().get_entity(&uid).await;
+    entity.map(Json::from).ok_or_else(|| entity_not_found(&uid))
+}
+
+/// The parents and ancestors of an entity.
+#[openapi]
+#[get("/data/entities/<entity_type>/<id>/ancestors")]
+pub async fn get_entity_ancestors(
+    _auth: ApiKey,
+    entity_type: String,
+    id: String,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::EntityRelations>, AgentError> {
+    let uid = entity_uid(&schemas::EntityReference { entity_type, id })?;
+    let ancestors = stores.data_store().entity_ancestors(&uid).await;
+    ancestors.map(Json::from).ok_or_else(|| entity_not_found(&uid))
+}
+
+/// The children and descendants of an entity.
+#[openapi]
+#[get("/data/entities/<entity_type>/<id>/descendants")]
+pub async fn get_entity_descendants(
+    _auth: ApiKey,
+    entity_type: String,
+    id: String,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::EntityRelations>, AgentError> {
+    let uid = entity_uid(&schemas::EntityReference { entity_type, id })?;
+    let descendants = stores.data_store().entity_descendants(&uid).await;
+    descendants.map(Json::from).ok_or_else(|| entity_not_found(&uid))
+}
+
+/// The chains of parents making an entity a member of its ancestor `to`, e.g. `Role::"Admin"`.
+#[openapi]
+#[get("/data/entities/<entity_type>/<id>/paths?<to>")]
+pub async fn get_entity_paths(
+    _auth: ApiKey,
+    entity_type: String,
+    id: String,
+    to: String,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::EntityPaths>, AgentError> {
+    let uid = entity_uid(&schemas::EntityReference { entity_type, id })?;
+    let to = EntityUid::from_str(&to).map_err(|err| AgentError::BadRequest {
+        reason: err.to_string(),
+    })?;
+    let paths = stores.data_store().entity_paths(&uid, &to).await;
+    paths.map(Json::from).ok_or_else(|| entity_not_found(&uid))
+}
+
+/// The hierarchy around an entity, its ancestors and descendants, rendered as DOT or Mermaid.
+#[openapi]
+#[get("/data/entities/<entity_type>/<id>/graph?<format>")]
+pub async fn get_entity_graph(
+    _auth: ApiKey,
+    entity_type: String,
+    id: String,
+    format: Option<schemas::GraphFormat>,
+    stores: Stores<'_>,
+) -> Result<String, AgentError> {
+    let uid = entity_uid(&schemas::EntityReference { entity_type, id })?;
+    match stores.data_store().entity_graph(&uid).await {
+        Some(graph) => Ok(hierarchy::render(&graph, format.unwrap_or_default())),
+        None => Err(entity_not_found(&uid)),
+    }
+}
+
+/// Add or replace a single entity, its uid is taken from the path.
+#[openapi]
+#[put("/data/entities/<entity_type>/<id>", format = "json", data = "<entity>")]
+pub async fn update_entity(
+    _auth: ApiKey,
+    entity_type: String,
+    id: String,
+    stores: Stores<'_>,
+    config: &State<Config>,
+    entity: TenantJson<schemas::Entity>,
+) -> Result<Json<schemas::Entity>, AgentError> {
+    let reference = schemas::EntityReference { entity_type, id };
+    let uid = entity_uid(&reference)?;
+    let mut entity = entity.into_inner();
+    match entity.uid() {
+        None => entity.set_uid(&reference),
+        Some(entity_uid) if entity_uid == uid => {}
+        Some(entity_uid) => {
+            return Err(AgentError::BadRequest {
+                reason: format!("Entity uid {} does not match the path {}", entity_uid, uid),
+            })
+        }
+    }
+    let upserted = change_entities(&stores, config, vec![entity].into(), &[]).await?;
+    match upserted.and_then(|entities| entities.iter().next().cloned()) {
+        Some(entity) => Ok(Json::from(entity)),
+        None => Err(entity_not_found(&uid)),
+    }
+}
+
+#[openapi]
+#[delete("/data/entities/<entity_type>/<id>")]
+pub async fn delete_entity(
+    _auth: ApiKey,
+    entity_type: String,
+    id: String,
+    stores: Stores<'_>,
+    config: &State<Config>,
+) -> Result<status::NoContent, AgentError> {
+    let reference = schemas::EntityReference { entity_type, id };
+    let uid = entity_uid(&reference)?;
+    if stores.data_store().get_entity(&uid).await.is_none() {
+        return Err(entity_not_found(&uid));
+    }
+    change_entities(&stores, config, schemas::Entities::default(), &[uid]).await?;
+    Ok(status::NoContent)
+}
+
+/// Add the entities or replace the stored ones with the same uid, other stored entities are kept.
+#[openapi]
+#[post("/data/entities", format = "json", data = "<entities>")]
+pub async fn upsert_entities(
+    _auth: ApiKey,
+    stores: Stores<'_>,
+    config: &State<Config>,
+    entities: TenantJson<schemas::Entities>,
+) -> Result<Json<schemas::Entities>, AgentError> {
+    let upserted = change_entities(&stores, config, entities.into_inner(), &[]).await?;
+    Ok(Json::from(upserted.unwrap_or_default()))
+}
+
+/// Remove the entities with the given uids, unknown uids are ignored.
+#[openapi]
+#[post("/data/entities/delete", format = "json", data = "<uids>")]
+pub async fn delete_entities_by_uid(
+    _auth: ApiKey,
+    stores: Stores<'_>,
+    config: &State<Config>,
+    uids: TenantJson<Vec<schemas::EntityReference>>,
 ) -> Result<status::NoContent, AgentError> {
-    data_store.delete_entities().await;
+    let uids = uids.iter().map(entity_uid).collect::<Result<Vec<EntityUid>, AgentError>>()?;
+    change_entities(&stores, config, schemas::Entities::default(), &uids).await?;
     Ok(status::NoContent)
 }
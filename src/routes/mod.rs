@@ -2,9 +2,12 @@ use rocket::get;
 use rocket_okapi::openapi;
 
 pub mod authorization;
+pub mod bundle;
 pub mod data;
 pub mod policies;
+pub mod policy_tests;
 pub mod schema;
+pub mod tenants;
 
 #[openapi]
 #[get("/")]
@@ -1,24 +1,86 @@
 use std::borrow::Borrow;
+use std::error::Error;
 
+use cedar_policy::PolicySetError;
+
+use rocket::data::{ByteUnit, Data, Limits, ToByteUnit};
 use rocket::response::status;
 use rocket::serde::json::Json;
+use rocket::tokio::io::BufReader;
 use rocket::{delete, get, post, put, State};
 use rocket_okapi::openapi;
 
 use crate::authn::ApiKey;
+use crate::config::Config;
 use crate::errors::response::AgentError;
+use crate::tenancy::{Stores, TenantJson};
+use crate::schemas::analysis::{ImpactReport, ImpactRequest, PolicyAnalysis};
+use crate::schemas::authorization::AuthorizationCall;
 use crate::schemas::policies as schemas;
+use crate::services::policies::analysis::analyze_policies;
+use crate::services::policies::search;
+use crate::services::policies::impact::{
+    analyze_impact, corpus, parse_policies, parse_policy_set, proposed_policies, read_proposal_lines,
+};
+use crate::routes::policy_tests::{candidate_policy_set, check_tests, hold_gate};
 use crate::services::policies::errors::PolicyStoreError;
-use crate::services::policies::PolicyStore;
-use crate::services::schema::SchemaStore;
+
+/// The size of a JSON lines impact proposal when no `impact` limit is configured.
+const DEFAULT_IMPACT_LIMIT: ByteUnit = ByteUnit::Mebibyte(64);
+
+/// Report parse and validation problems in a structured way, any other error as a bad request.
+fn policy_error_response(err: Box<dyn Error>) -> AgentError {
+    match err.downcast::<PolicyStoreError>() {
+        Ok(err) => match *err {
+            PolicyStoreError::PolicyInvalid(_, errors) => AgentError::InvalidPolicies { errors },
+            err @ PolicyStoreError::QuotaExceeded(_) => AgentError::QuotaExceeded {
+                reason: err.to_string(),
+            },
+            err @ PolicyStoreError::ProposalTooLarge(_) => AgentError::PayloadTooLarge {
+                reason: err.to_string(),
+            },
+            err => AgentError::BadRequest {
+                reason: err.to_string(),
+            },
+        },
+        Err(err) => AgentError::BadRequest {
+            reason: err.to_string(),
+        },
+    }
+}
+
+/// Reject enabling or disabling a policy when it makes a stored test fail.
+async fn check_enabled_tests(stores: &Stores<'_>, id: &str, enabled: bool) -> Result<(), AgentError> {
+    let mut policies = stores.policy_store().get_policies().await;
+    policies.iter_mut().filter(|p| p.id == id).for_each(|p| p.enabled = enabled);
+    check_tests(stores, Some(candidate_policy_set(&policies)?), None).await
+}
 
 #[openapi]
-#[get("/policies")]
+#[get("/policies?<filter..>")]
 pub async fn get_policies(
     _auth: ApiKey,
-    policy_store: &State<Box<dyn PolicyStore>>,
+    filter: schemas::PolicyFilter,
+    stores: Stores<'_>,
 ) -> Result<Json<Vec<schemas::Policy>>, AgentError> {
-    Ok(Json::from(policy_store.get_policies().await))
+    Ok(Json::from(stores.policy_store().find_policies(&filter).await))
+}
+
+/// Find the policies referencing an entity, entity type, action or attribute.
+#[openapi]
+#[get("/policies/search?<search..>")]
+pub async fn search_policies(
+    _auth: ApiKey,
+    search: schemas::PolicySearch,
+    stores: Stores<'_>,
+) -> Result<Json<Vec<schemas::PolicySearchMatch>>, AgentError> {
+    let policies = stores.policy_store().get_policies().await;
+    match search::search_policies(&policies, &search) {
+        Ok(matches) => Ok(Json::from(matches)),
+        Err(err) => Err(AgentError::BadRequest {
+            reason: err.to_string(),
+        }),
+    }
 }
 
 #[openapi]
@@ -26,9 +88,9 @@ pub async fn get_policies(
 pub async fn get_policy(
     _auth: ApiKey,
     id: String,
-    policy_store: &State<Box<dyn PolicyStore>>,
+    stores: Stores<'_>,
 ) -> Result<Json<schemas::Policy>, AgentError> {
-    match policy_store.get_policy(id.borrow()).await {
+    match stores.policy_store().get_policy(id.borrow()).await {
         Ok(policy) => Ok(Json::from(policy)),
         Err(_) => Err(AgentError::NotFound {
             id,
@@ -38,77 +100,163 @@ pub async fn get_policy(
 }
 
 #[openapi]
-#[post("/policies", format = "json", data = "<policy>")]
+#[post("/policies?<validation..>", format = "json", data = "<policy>")]
 pub async fn create_policy(
     _auth: ApiKey,
-    policy: Json<schemas::Policy>,
-    policy_store: &State<Box<dyn PolicyStore>>,
-    schema_store: &State<Box<dyn SchemaStore>>,
+    policy: TenantJson<schemas::Policy>,
+    validation: schemas::ValidationSettings,
+    config: &State<Config>,
+    stores: Stores<'_>,
 ) -> Result<Json<schemas::Policy>, AgentError> {
     let policy = policy.into_inner();
-    let schema = schema_store.get_cedar_schema().await;
+    let schema = stores.schema_store().get_cedar_schema().await;
+    let validation = validation.or(config.validation_settings());
+    let _gate = hold_gate(&stores, config).await;
+    if config.test_gate_enabled() {
+        let mut policies = stores.policy_store().get_policies().await;
+        policies.push(policy.clone());
+        check_tests(&stores, Some(candidate_policy_set(&policies)?), None).await?;
+    }
 
-    let added_policy = policy_store.create_policy(policy.borrow(), schema).await;
+    let added_policy = stores.policy_store().create_policy(policy.borrow(), schema, validation).await;
     match added_policy {
         Ok(p) => Ok(Json::from(p)),
         Err(e) => {
-            if let Some(PolicyStoreError::PolicyInvalid(_, reason)) = e.downcast_ref::<PolicyStoreError>() {
-                Err(AgentError::BadRequest {
-                    reason: reason.clone()
-                })
-            } else {
+            if e.downcast_ref::<PolicySetError>().is_some() {
                 Err(AgentError::Duplicate {
                     id: policy.id,
                     object: "policy",
                 })
+            } else {
+                Err(policy_error_response(e))
             }
         },
     }
 }
 
 #[openapi]
-#[put("/policies", format = "json", data = "<policy>")]
+#[put("/policies?<validation..>", format = "json", data = "<policy>")]
 pub async fn update_policies(
     _auth: ApiKey,
-    policy: Json<Vec<schemas::Policy>>,
-    policy_store: &State<Box<dyn PolicyStore>>,
-    schema_store: &State<Box<dyn SchemaStore>>,
+    policy: TenantJson<Vec<schemas::Policy>>,
+    validation: schemas::ValidationSettings,
+    config: &State<Config>,
+    stores: Stores<'_>,
 ) -> Result<Json<Vec<schemas::Policy>>, AgentError> {
-    let schema = schema_store.get_cedar_schema().await;
+    let schema = stores.schema_store().get_cedar_schema().await;
+    let _gate = hold_gate(&stores, config).await;
+    if config.test_gate_enabled() {
+        check_tests(&stores, Some(candidate_policy_set(&policy)?), None).await?;
+    }
 
-    let updated_policy = policy_store.update_policies(
+    let updated_policy = stores.policy_store().update_policies(
         policy.into_inner(),
-        schema
+        schema,
+        validation.or(config.validation_settings())
     ).await;
     match updated_policy {
         Ok(p) => Ok(Json::from(p)),
-        Err(e) => Err(AgentError::BadRequest {
-            reason: e.to_string(),
-        }),
+        Err(e) => Err(policy_error_response(e)),
     }
 }
 
 #[openapi]
-#[put("/policies/<id>", format = "json", data = "<policy>")]
+#[post("/policies/transaction?<validation..>", format = "json", data = "<operations>")]
+pub async fn apply_policy_operations(
+    _auth: ApiKey,
+    operations: TenantJson<Vec<schemas::PolicyOperation>>,
+    validation: schemas::ValidationSettings,
+    config: &State<Config>,
+    stores: Stores<'_>,
+) -> Result<Json<Vec<schemas::PolicyOperationResult>>, AgentError> {
+    let schema = stores.schema_store().get_cedar_schema().await;
+    let _gate = hold_gate(&stores, config).await;
+    if config.test_gate_enabled() {
+        let candidate = stores.policy_store().candidate_policy_operations(&operations).await;
+        check_tests(&stores, Some(candidate.map_err(policy_error_response)?), None).await?;
+    }
+
+    let results = stores.policy_store().apply_policy_operations(
+        operations.into_inner(),
+        schema,
+        validation.or(config.validation_settings())
+    ).await;
+    match results {
+        Ok(r) => Ok(Json::from(r)),
+        Err(err) => Err(policy_error_response(err)),
+    }
+}
+
+#[openapi]
+#[put("/policies/<id>?<validation..>", format = "json", data = "<policy>")]
 pub async fn update_policy(
     _auth: ApiKey,
     id: String,
-    policy: Json<schemas::PolicyUpdate>,
-    policy_store: &State<Box<dyn PolicyStore>>,
-    schema_store: &State<Box<dyn SchemaStore>>,
+    policy: TenantJson<schemas::PolicyUpdate>,
+    validation: schemas::ValidationSettings,
+    config: &State<Config>,
+    stores: Stores<'_>,
 ) -> Result<Json<schemas::Policy>, AgentError> {
-    let schema = schema_store.get_cedar_schema().await;
+    let schema = stores.schema_store().get_cedar_schema().await;
+    let _gate = hold_gate(&stores, config).await;
+    if config.test_gate_enabled() {
+        let mut policies = stores.policy_store().get_policies().await;
+        let existing = policies.iter().position(|p| p.id == id).map(|index| policies.swap_remove(index));
+        policies.push(schemas::Policy::updated(existing, id.clone(), policy.clone().into_inner()));
+        check_tests(&stores, Some(candidate_policy_set(&policies)?), None).await?;
+    }
 
-    let updated_policy = policy_store.update_policy(
+    let updated_policy = stores.policy_store().update_policy(
         id,
         policy.into_inner(),
-        schema
+        schema,
+        validation.or(config.validation_settings())
     ).await;
 
     match updated_policy {
         Ok(p) => Ok(Json::from(p)),
-        Err(err) => Err(AgentError::BadRequest {
-            reason: err.to_string(),
+        Err(err) => Err(policy_error_response(err)),
+    }
+}
+
+#[openapi]
+#[post("/policies/<id>/disable")]
+pub async fn disable_policy(
+    _auth: ApiKey,
+    id: String,
+    config: &State<Config>,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::Policy>, AgentError> {
+    let _gate = hold_gate(&stores, config).await;
+    if config.test_gate_enabled() {
+        check_enabled_tests(&stores, &id, false).await?;
+    }
+    match stores.policy_store().set_policy_enabled(&id, false).await {
+        Ok(policy) => Ok(Json::from(policy)),
+        Err(_) => Err(AgentError::NotFound {
+            id,
+            object: "policy",
+        }),
+    }
+}
+
+#[openapi]
+#[post("/policies/<id>/enable")]
+pub async fn enable_policy(
+    _auth: ApiKey,
+    id: String,
+    config: &State<Config>,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::Policy>, AgentError> {
+    let _gate = hold_gate(&stores, config).await;
+    if config.test_gate_enabled() {
+        check_enabled_tests(&stores, &id, true).await?;
+    }
+    match stores.policy_store().set_policy_enabled(&id, true).await {
+        Ok(policy) => Ok(Json::from(policy)),
+        Err(_) => Err(AgentError::NotFound {
+            id,
+            object: "policy",
         }),
     }
 }
@@ -118,9 +266,16 @@ pub async fn update_policy(
 pub async fn delete_policy(
     _auth: ApiKey,
     id: String,
-    policy_store: &State<Box<dyn PolicyStore>>,
+    config: &State<Config>,
+    stores: Stores<'_>,
 ) -> Result<status::NoContent, AgentError> {
-    match policy_store.delete_policy(id.borrow()).await {
+    let _gate = hold_gate(&stores, config).await;
+    if config.test_gate_enabled() {
+        let mut policies = stores.policy_store().get_policies().await;
+        policies.retain(|p| p.id != id);
+        check_tests(&stores, Some(candidate_policy_set(&policies)?), None).await?;
+    }
+    match stores.policy_store().delete_policy(id.borrow()).await {
         Ok(_p) => Ok(status::NoContent),
         Err(_err) => Err(AgentError::NotFound {
             id,
@@ -128,3 +283,95 @@ pub async fn delete_policy(
         }),
     }
 }
+
+/// Static analysis of the stored policies, disabled and scheduled ones included, validating against the schema in
+/// the configured mode unless `validation_mode` is given.
+#[openapi]
+#[get("/analysis/policies?<validation_mode>")]
+pub async fn analyze_policy_set(
+    _auth: ApiKey,
+    validation_mode: Option<schemas::PolicyValidationMode>,
+    config: &State<Config>,
+    stores: Stores<'_>,
+) -> Result<Json<PolicyAnalysis>, AgentError> {
+    let mode = validation_mode.unwrap_or_else(|| config.validation_settings().mode());
+    let schema = stores.schema_store().get_cedar_schema().await;
+    let policy_set = match parse_policies(&stores.policy_store().get_policies().await) {
+        Ok(policy_set) => policy_set,
+        Err(err) => return Err(policy_error_response(err.into())),
+    };
+    Ok(Json::from(analyze_policies(&policy_set, &schema, mode.into())))
+}
+
+/// Evaluate the inline requests of the proposal and the given ones against the stored and the proposed policies.
+async fn impact_report(
+    stores: &Stores<'_>,
+    config: &Config,
+    proposal: ImpactRequest,
+    lines: Vec<Result<AuthorizationCall, String>>,
+) -> Result<Json<ImpactReport>, AgentError> {
+    let stored = stores.policy_store().get_policies().await;
+    let proposed = proposed_policies(stored, &proposal, config.policy_id_pattern.as_deref());
+    let proposed = match parse_policy_set(&proposed) {
+        Ok(policy_set) => policy_set,
+        Err(err) => return Err(policy_error_response(err.into())),
+    };
+    let current = stores.policy_store().policy_set().await;
+    let entities = stores.data_store().entities().await;
+    Ok(Json::from(analyze_impact(&current, &proposed, &entities, corpus(&proposal, lines))))
+}
+
+/// Compare the decisions of the stored and the proposed policies on a corpus of requests.
+/// Nothing is stored.
+#[openapi]
+#[post("/analysis/impact", format = "json", data = "<proposal>")]
+pub async fn analyze_policy_impact(
+    _auth: ApiKey,
+    proposal: TenantJson<ImpactRequest>,
+    config: &State<Config>,
+    stores: Stores<'_>,
+) -> Result<Json<ImpactReport>, AgentError> {
+    impact_report(&stores, config, proposal.into_inner(), Vec::new()).await
+}
+
+/// Like `POST /analysis/impact`, with the proposal on the first line of a JSON lines body and one request per
+/// following line, for corpora too large for a single JSON document.
+#[openapi]
+#[post("/analysis/impact/ndjson", data = "<data>")]
+pub async fn analyze_policy_impact_lines(
+    _auth: ApiKey,
+    config: &State<Config>,
+    stores: Stores<'_>,
+    limits: &Limits,
+    data: Data<'_>,
+) -> Result<Json<ImpactReport>, AgentError> {
+    let mut limit = limits.get("impact").unwrap_or(DEFAULT_IMPACT_LIMIT);
+    if let Some(max) = stores.payload_limit() {
+        limit = limit.min(max.bytes());
+    }
+    // One more byte is read to tell a body of exactly the limit from a larger one.
+    let body = BufReader::new(data.open(limit + 1.bytes()));
+    let read = read_proposal_lines(body, limit.as_u64()).await;
+    let (proposal, lines) = read.map_err(|err| policy_error_response(err.into()))?;
+    impact_report(&stores, config, proposal, lines).await
+}
+
+/// Upcoming scheduled activations and expirations of enabled policies.
+#[openapi]
+#[get("/schedule/policies")]
+pub async fn get_policy_transitions(
+    _auth: ApiKey,
+    stores: Stores<'_>,
+) -> Result<Json<Vec<schemas::PolicyTransition>>, AgentError> {
+    Ok(Json::from(stores.policy_store().get_policy_transitions().await))
+}
+
+/// Policies accepted in `warn` validation mode that still fail validation.
+#[openapi]
+#[get("/validation/policies")]
+pub async fn get_invalid_policies(
+    _auth: ApiKey,
+    stores: Stores<'_>,
+) -> Result<Json<Vec<schemas::Policy>>, AgentError> {
+    Ok(Json::from(stores.policy_store().get_invalid_policies().await))
+}
@@ -0,0 +1,163 @@
+use std::str::FromStr;
+
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, post, put};
+use rocket_okapi::openapi;
+
+use async_lock::MutexGuard;
+use cedar_policy::{Entities, PolicyId, PolicySet};
+use chrono::Utc;
+
+use crate::authn::ApiKey;
+use crate::config::Config;
+use crate::errors::response::AgentError;
+use crate::schemas::policies::{Policy, PolicyError};
+use crate::schemas::policy_tests as schemas;
+use crate::services::policy_tests::runner::run_tests;
+use crate::tenancy::{Stores, TenantJson};
+
+/// The policy set the given policies would make up.
+/// A policy that does not parse or a duplicate id rejects the change, the stored policies are never checked instead.
+pub(crate) fn candidate_policy_set(policies: &[Policy]) -> Result<PolicySet, AgentError> {
+    let mut policy_set = PolicySet::new();
+    let now = Utc::now();
+    for (index, policy) in policies.iter().enumerate().filter(|(_, policy)| policy.is_active(now)) {
+        let parsed: Result<cedar_policy::Policy, _> = policy.try_into();
+        let mut cedar_policy = parsed.map_err(|err| AgentError::InvalidPolicies {
+            errors: PolicyError::from_parse_errors(&policy.id, &policy.content, &err),
+        })?;
+        // Ids of policies submitted without one are generated by the store, keep them apart meanwhile.
+        if policy.id.is_empty() {
+            cedar_policy = cedar_policy.new_id(PolicyId::from_str(&format!("unnamed-{}", index)).unwrap());
+        }
+        policy_set.add(cedar_policy).map_err(|_| AgentError::Duplicate {
+            id: policy.id.clone(),
+            object: "policy",
+        })?;
+    }
+    Ok(policy_set)
+}
+
+/// Hold the test gate while a change is checked and stored, `None` when the gate is disabled.
+/// The candidate has to be taken from the stores after the gate is held.
+pub(crate) async fn hold_gate<'a>(stores: &'a Stores<'_>, config: &Config) -> Option<MutexGuard<'a, ()>> {
+    match config.test_gate_enabled() {
+        true => Some(stores.test_gate().hold().await),
+        false => None,
+    }
+}
+
+/// Reject a change when a stored test fails against the resulting policies and entities.
+/// Unset values are taken from the stores.
+pub(crate) async fn check_tests(
+    stores: &Stores<'_>,
+    policy_set: Option<PolicySet>,
+    entities: Option<Entities>,
+) -> Result<(), AgentError> {
+    let tests = stores.test_store().get_tests().await;
+    if tests.is_empty() {
+        return Ok(());
+    }
+    let policy_set = match policy_set {
+        Some(policy_set) => policy_set,
+        None => stores.policy_store().policy_set().await,
+    };
+    let entities = match entities {
+        Some(entities) => entities,
+        None => stores.data_store().entities().await,
+    };
+    let failures = run_tests(&tests, &policy_set, &entities).failures();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(AgentError::TestsFailed { results: failures })
+    }
+}
+
+#[openapi]
+#[get("/tests")]
+pub async fn get_tests(
+    _auth: ApiKey,
+    stores: Stores<'_>,
+) -> Result<Json<Vec<schemas::PolicyTest>>, AgentError> {
+    Ok(Json::from(stores.test_store().get_tests().await))
+}
+
+#[openapi]
+#[get("/tests/<id>")]
+pub async fn get_test(
+    _auth: ApiKey,
+    id: String,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::PolicyTest>, AgentError> {
+    match stores.test_store().get_test(&id).await {
+        Ok(test) => Ok(Json::from(test)),
+        Err(_) => Err(AgentError::NotFound {
+            id,
+            object: "test",
+        }),
+    }
+}
+
+#[openapi]
+#[post("/tests", format = "json", data = "<test>")]
+pub async fn create_test(
+    _auth: ApiKey,
+    test: TenantJson<schemas::PolicyTest>,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::PolicyTest>, AgentError> {
+    let test = test.into_inner();
+    let id = test.id.clone();
+    match stores.test_store().create_test(test).await {
+        Ok(test) => Ok(Json::from(test)),
+        Err(_) => Err(AgentError::Duplicate {
+            id,
+            object: "test",
+        }),
+    }
+}
+
+#[openapi]
+#[put("/tests/<id>", format = "json", data = "<test>")]
+pub async fn update_test(
+    _auth: ApiKey,
+    id: String,
+    test: TenantJson<schemas::PolicyTest>,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::PolicyTest>, AgentError> {
+    match stores.test_store().update_test(id, test.into_inner()).await {
+        Ok(test) => Ok(Json::from(test)),
+        Err(err) => Err(AgentError::BadRequest {
+            reason: err.to_string(),
+        }),
+    }
+}
+
+#[openapi]
+#[delete("/tests/<id>")]
+pub async fn delete_test(
+    _auth: ApiKey,
+    id: String,
+    stores: Stores<'_>,
+) -> Result<status::NoContent, AgentError> {
+    match stores.test_store().delete_test(&id).await {
+        Ok(_) => Ok(status::NoContent),
+        Err(_) => Err(AgentError::NotFound {
+            id,
+            object: "test",
+        }),
+    }
+}
+
+#[openapi]
+#[post("/tests/run")]
+pub async fn run_stored_tests(
+    _auth: ApiKey,
+    stores: Stores<'_>,
+) -> Result<Json<schemas::PolicyTestReport>, AgentError> {
+    let tests = stores.test_store().get_tests().await;
+    let policy_set = stores.policy_store().policy_set().await;
+    let entities = stores.data_store().entities().await;
+    Ok(Json::from(run_tests(&tests, &policy_set, &entities)))
+}
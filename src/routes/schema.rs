@@ -4,54 +4,60 @@ use rocket::serde::json::Json;
 use rocket_okapi::openapi;
 
 use crate::authn::ApiKey;
+use crate::config::Config;
 use crate::errors::response::AgentError;
+use crate::tenancy::{Stores, TenantJson};
 use cedar_policy::Schema as CedarSchema;
+use cedar_policy_validator::ValidatorSchema;
 use log::error;
+use ref_cast::RefCast;
 use crate::schemas::schema::Schema as InternalSchema;
-use crate::services::{schema::SchemaStore, policies::PolicyStore, data::DataStore};
 
 #[openapi]
 #[get("/schema")]
 pub async fn get_schema(
     _auth: ApiKey,
-    schema_store: &State<Box<dyn SchemaStore>>
+    stores: Stores<'_>
 ) -> Result<Json<InternalSchema>, AgentError> {
-    Ok(Json::from(schema_store.get_internal_schema().await))
+    Ok(Json::from(stores.schema_store().get_internal_schema().await))
 }
 
 #[openapi]
 #[put("/schema", format = "json", data = "<schema>")]
 pub async fn update_schema(
     _auth: ApiKey,
-    schema_store: &State<Box<dyn SchemaStore>>,
-    policy_store: &State<Box<dyn PolicyStore>>,
-    data_store: &State<Box<dyn DataStore>>,
-    schema: Json<InternalSchema>
+    stores: Stores<'_>,
+    config: &State<Config>,
+    schema: TenantJson<InternalSchema>
 ) -> Result<Json<InternalSchema>, AgentError> {
-    let cedar_schema: CedarSchema = match schema.clone().into_inner().try_into() {
+    let validator_schema: ValidatorSchema = match schema.clone().into_inner().try_into() {
         Ok(schema) => schema,
         Err(err) => return Err(AgentError::BadRequest {
             reason: err.to_string(),
         })
     };
 
-    let current_policies = policy_store.get_policies().await;
-    match policy_store.update_policies(current_policies, Some(cedar_schema.clone())).await {
+    let current_policies = stores.policy_store().get_policies().await;
+    match stores.policy_store().update_policies(
+        current_policies,
+        Some(CedarSchema::ref_cast(&validator_schema).clone()),
+        config.validation_settings()
+    ).await {
         Ok(_) => {},
         Err(err) => return Err(AgentError::BadRequest {
             reason: format!("Existing policies invalid with the new schema: {}", err.to_string()),
         })
     }
 
-    let current_entities = data_store.get_entities().await;
-    match data_store.update_entities(current_entities, Some(cedar_schema)).await {
+    let current_entities = stores.data_store().get_entities().await;
+    match stores.data_store().update_entities(current_entities, Some(validator_schema)).await {
         Ok(_) => {},
         Err(err) => return Err(AgentError::BadRequest {
             reason: format!("Existing entities invalid with the new schema: {}", err.to_string()),
         })
     }
 
-    match schema_store.update_schema(schema.into_inner()).await {
+    match stores.schema_store().update_schema(schema.into_inner()).await {
         Ok(schema) => Ok(Json::from(schema)),
         Err(err) => return Err(AgentError::BadRequest {
             reason: err.to_string(),
@@ -63,8 +69,14 @@ pub async fn update_schema(
 #[delete("/schema")]
 pub async fn delete_schema(
     _auth: ApiKey,
-    schema_store: &State<Box<dyn SchemaStore>>
+    stores: Stores<'_>,
+    config: &State<Config>
 ) -> Result<status::NoContent, AgentError> {
-    schema_store.delete_schema().await;
+    stores.schema_store().delete_schema().await;
+    // Without a schema nothing fails validation, clear the errors of policies accepted in warn mode.
+    let current_policies = stores.policy_store().get_policies().await;
+    if let Err(err) = stores.policy_store().update_policies(current_policies, None, config.validation_settings()).await {
+        error!("Failed to revalidate policies after deleting the schema: {}", err);
+    }
     Ok(status::NoContent)
 }
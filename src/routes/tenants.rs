@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, post, State};
+use rocket_okapi::openapi;
+
+use crate::authn::ApiKey;
+use crate::errors::response::AgentError;
+use crate::schemas::tenants as schemas;
+use crate::services::tenants::errors::TenantStoreError;
+use crate::services::tenants::TenantStore;
+
+#[openapi]
+#[get("/tenants")]
+pub async fn get_tenants(
+    _auth: ApiKey,
+    tenant_store: &State<Arc<dyn TenantStore>>,
+) -> Result<Json<Vec<schemas::Tenant>>, AgentError> {
+    Ok(Json::from(tenant_store.get_tenants().await))
+}
+
+#[openapi]
+#[get("/tenants/<name>")]
+pub async fn get_tenant(
+    _auth: ApiKey,
+    name: String,
+    tenant_store: &State<Arc<dyn TenantStore>>,
+) -> Result<Json<schemas::Tenant>, AgentError> {
+    let tenant = match tenant_store.get_tenant(&name).await {
+        Ok(tenant) => tenant,
+        Err(_) => return Err(AgentError::NotFound {
+            id: name,
+            object: "tenant",
+        }),
+    };
+    Ok(Json::from(tenant.tenant().await))
+}
+
+#[openapi]
+#[post("/tenants", format = "json", data = "<tenant>")]
+pub async fn create_tenant(
+    _auth: ApiKey,
+    tenant: Json<schemas::TenantCreate>,
+    tenant_store: &State<Arc<dyn TenantStore>>,
+) -> Result<Json<schemas::Tenant>, AgentError> {
+    let tenant = tenant.into_inner();
+    let name = tenant.name.clone();
+    match tenant_store.create_tenant(tenant).await {
+        Ok(tenant) => Ok(Json::from(tenant)),
+        Err(err) => match err.downcast_ref::<TenantStoreError>() {
+            Some(TenantStoreError::TenantAlreadyExists(_)) => Err(AgentError::Duplicate {
+                id: name,
+                object: "tenant",
+            }),
+            _ => Err(AgentError::BadRequest {
+                reason: err.to_string(),
+            }),
+        },
+    }
+}
+
+#[openapi]
+#[delete("/tenants/<name>")]
+pub async fn delete_tenant(
+    _auth: ApiKey,
+    name: String,
+    tenant_store: &State<Arc<dyn TenantStore>>,
+) -> Result<status::NoContent, AgentError> {
+    match tenant_store.delete_tenant(&name).await {
+        Ok(_) => Ok(status::NoContent),
+        Err(_) => Err(AgentError::NotFound {
+            id: name,
+            object: "tenant",
+        }),
+    }
+}
@@ -0,0 +1,114 @@
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::authorization::{AuthorizationCall, DecisionRef};
+use crate::schemas::policies::Policy;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingSeverity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    /// The policy scope can never match a request allowed by the schema.
+    UnreachableScope,
+    /// A permit that can never take effect because an unconditional forbid covers its scope.
+    ShadowedPermit,
+    /// Policies with the same effect, scope and conditions.
+    DuplicatePolicy,
+    /// Policies with the same effect and scope but different conditions.
+    DuplicateScope,
+    /// The policy references an entity type, action or attribute missing from the schema.
+    UnknownReference,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct PolicyFinding {
+    pub kind: FindingKind,
+    pub severity: FindingSeverity,
+    /// Ids of the policies involved, the policy the finding is about comes first.
+    pub policies: Vec<String>,
+    pub explanation: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct PolicyAnalysis {
+    /// Number of analyzed policies.
+    pub policies: usize,
+    /// Whether schema based checks were performed.
+    pub schema_checked: bool,
+    pub findings: Vec<PolicyFinding>,
+}
+
+/// Whether the proposed policies replace the stored ones or are applied on top of them.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalMode {
+    /// The proposed policies are the complete new policy set.
+    #[default]
+    Full,
+    /// The proposed policies are created or replace the stored ones with the same id.
+    Partial,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct ImpactRequest {
+    pub policies: Vec<Policy>,
+    #[serde(default)]
+    pub mode: ProposalMode,
+    /// Ids of stored policies removed by a partial proposal.
+    #[serde(default)]
+    pub removed: Vec<String>,
+    /// The requests to evaluate.
+    #[serde(default)]
+    pub requests: Vec<AuthorizationCall>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct ImpactDecision {
+    pub decision: DecisionRef,
+    /// Ids of the policies that determined the decision.
+    pub reasons: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct ImpactChange {
+    /// Position of the request in the corpus, inline requests come first.
+    pub index: usize,
+    pub request: AuthorizationCall,
+    pub current: ImpactDecision,
+    pub proposed: ImpactDecision,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct ImpactError {
+    pub index: usize,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+pub struct ImpactSummary {
+    pub evaluated: usize,
+    pub unchanged: usize,
+    /// Requests whose decision or determining policies differ.
+    pub changed: usize,
+    pub allow_to_deny: usize,
+    pub deny_to_allow: usize,
+    /// Requests with the same decision but different determining policies.
+    pub reasons_changed: usize,
+    /// Requests that could not be evaluated.
+    pub errors: usize,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct ImpactReport {
+    pub summary: ImpactSummary,
+    pub changes: Vec<ImpactChange>,
+    pub errors: Vec<ImpactError>,
+}
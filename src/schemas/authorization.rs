@@ -8,12 +8,13 @@ use cedar_policy_core::authorizer::Decision;
 use cedar_policy_core::parser::err::ParseErrors;
 use cedar_policy_core::entities::EntitiesError;
 
+use clap::ValueEnum;
 use rocket::serde::json::serde_json;
 use rocket_okapi::okapi::schemars;
 use rocket_okapi::okapi::schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AuthorizationCall {
     principal: Option<String>,
     action: Option<String>,
@@ -34,6 +35,8 @@ pub struct AuthorizationRequest {
     request: Request,
     entities: Option<Entities>,
     additional_entities: Option<Entities>,
+    /// The entities the context and the additional entities refer to.
+    referenced: Vec<EntityUid>,
 }
 
 impl AuthorizationRequest {
@@ -46,28 +49,63 @@ impl AuthorizationRequest {
             request,
             entities,
             additional_entities,
+            referenced: Vec::new(),
         }
     }
 
+    /// Set the entities the context and the additional entities refer to.
+    pub fn with_referenced(mut self, referenced: Vec<EntityUid>) -> Self {
+        self.referenced = referenced;
+        self
+    }
+
+    /// The entities the stored entities needed to evaluate the request are reached from,
+    /// `None` when the request brings all of its entities.
+    pub fn entity_roots(&self) -> Option<Vec<EntityUid>> {
+        if self.entities.is_some() {
+            return None;
+        }
+        let scope = [self.request.principal(), self.request.action(), self.request.resource()];
+        let mut roots: Vec<EntityUid> = scope.into_iter().flatten().cloned().collect();
+        roots.extend(self.referenced.iter().cloned());
+        Some(roots)
+    }
+
     pub fn get_entities(self) -> Option<Entities> {
         self.entities
     }
 
     pub fn get_request_entities(self, stored_entities: Entities) -> Result<(Request, Entities), EntitiesError> {
-        let request_entities = match self.entities {
+        let patched_entities = self.request_entities(stored_entities)?;
+        Ok((self.request, patched_entities))
+    }
+
+    /// The entities the request is evaluated on, given the stored ones.
+    pub fn request_entities(&self, stored_entities: Entities) -> Result<Entities, EntitiesError> {
+        let request_entities = match &self.entities {
             None => stored_entities,
             Some(ents) => ents.clone()
         };
-        let patched_entities = match self.additional_entities {
-            None => request_entities,
-            Some(ents) => {
-                match Entities::from_entities(request_entities.iter().chain(ents.iter()).cloned()) {
-                    Ok(entities) => entities,
-                    Err(err) => return Err(err)
-                }
-            }
-        };
-        Ok((self.request, patched_entities))
+        match &self.additional_entities {
+            None => Ok(request_entities),
+            Some(ents) => Entities::from_entities(request_entities.iter().chain(ents.iter()).cloned()),
+        }
+    }
+}
+
+/// The entities a context or entities in JSON form refer to, explicitly with `__entity` or as parents.
+fn referenced_entities(json: &serde_json::Value, uids: &mut Vec<EntityUid>) {
+    match json {
+        serde_json::Value::Object(object) if object.contains_key("__entity") => {
+            uids.extend(EntityUid::from_json(json.clone()).ok());
+        }
+        serde_json::Value::Object(object) => {
+            let parents = object.get("parents").and_then(serde_json::Value::as_array).into_iter().flatten();
+            uids.extend(parents.filter_map(|parent| EntityUid::from_json(parent.clone()).ok()));
+            object.values().for_each(|value| referenced_entities(value, uids));
+        }
+        serde_json::Value::Array(values) => values.iter().for_each(|value| referenced_entities(value, uids)),
+        _ => {}
     }
 }
 
@@ -128,6 +166,11 @@ impl TryInto<AuthorizationRequest> for AuthorizationCall {
             },
             None => None,
         };
+        let mut referenced = Vec::new();
+        self.context
+            .iter()
+            .chain(&self.additional_entities)
+            .for_each(|json| referenced_entities(json, &mut referenced));
         let additional_entities = match self.additional_entities {
             Some(et) => match Entities::from_json_value(et, None) {
                 Ok(et) => Some(et),
@@ -146,11 +189,12 @@ impl TryInto<AuthorizationRequest> for AuthorizationCall {
             Request::new(principal, action, resource, context),
             entities,
             additional_entities,
-        ))
+        )
+        .with_referenced(referenced))
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum DecisionRef {
     Allow,
     /// The `Authorizer` determined that the query should be denied.
@@ -160,7 +204,7 @@ pub enum DecisionRef {
     Deny,
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct DiagnosticsRef {
     /// `PolicyId`s of the policies that contributed to the decision.
     /// If no policies applied to the query, this set will be empty.
@@ -169,7 +213,7 @@ pub struct DiagnosticsRef {
     errors: HashSet<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct AuthorizationAnswer {
     decision: DecisionRef,
     diagnostics: DiagnosticsRef,
@@ -209,3 +253,16 @@ impl From<Response> for AuthorizationAnswer {
         }
     }
 }
+
+/// How the scope index of the policy store is used to evaluate authorization requests.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyIndexMode {
+    /// Requests are evaluated against all policies.
+    #[default]
+    Off,
+    /// Requests are evaluated against the policies whose scope could match.
+    On,
+    /// Requests are evaluated both ways, mismatches are logged and the full evaluation is answered.
+    Verify,
+}
@@ -0,0 +1,19 @@
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::data::Entities;
+use crate::schemas::policies::Policy;
+use crate::schemas::schema::Schema;
+
+/// The full state of the stores, the schema, the policies with their metadata and the entities.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Bundle {
+    /// An empty schema removes the stored one.
+    #[serde(default = "Schema::empty")]
+    pub schema: Schema,
+    #[serde(default)]
+    pub policies: Vec<Policy>,
+    #[serde(default)]
+    pub entities: Entities,
+}
@@ -1,29 +1,149 @@
 use std::error::Error;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 use cedar_policy_core::entities::{
     EntitiesError, EntityJSON, EntityJsonParser, NoEntitiesSchema, TCComputation,
 };
 use cedar_policy_core::extensions::Extensions;
 use cedar_policy_core::{ast, entities};
-use cedar_policy::Schema;
+use cedar_policy::{EntityId, EntityTypeName, EntityUid, Schema};
+use cedar_policy_validator::{CoreSchema, ValidatorSchema};
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
 use log::debug;
-use rocket::serde::json::serde_json::{from_str, json, to_string};
+use rocket::serde::json::serde_json::{from_slice, json, to_value};
 use rocket::serde::json::Value;
 
+use rocket::{FromForm, FromFormField};
 use rocket_okapi::okapi::schemars;
 use rocket_okapi::okapi::schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::common::EmptyError;
+use crate::schemas::policies::PolicySearchMatch;
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+/// The field of an entity giving the number of seconds it is stored for.
+const TTL_FIELD: &str = "ttl";
+/// The field of an entity giving when it expires, in RFC 3339 format.
+const EXPIRES_AT_FIELD: &str = "expires_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Entity(Value);
 
-impl From<ast::Entity> for Entity {
-    fn from(value: ast::Entity) -> Self {
-        let entity_json = EntityJSON::from_entity(&value).unwrap();
-        let json_string = to_string(&entity_json).unwrap();
-        Self(from_str(&json_string).unwrap())
+impl Entity {
+    pub fn uid(&self) -> Option<EntityUid> {
+        EntityUid::from_json(self.0.get("uid")?.clone()).ok()
+    }
+
+    pub fn attribute_names(&self) -> Vec<String> {
+        let attrs = self.0.get("attrs").and_then(Value::as_object);
+        attrs.map(|attrs| attrs.keys().cloned().collect()).unwrap_or_default()
+    }
+
+    /// The parents the entity declares.
+    pub fn parents(&self) -> Vec<EntityUid> {
+        let parents = self.0.get("parents").and_then(Value::as_array).into_iter().flatten();
+        parents.filter_map(|parent| EntityUid::from_json(parent.clone()).ok()).collect()
+    }
+
+    /// Whether the entity is an action, of an `Action` type in any namespace.
+    pub fn is_action(&self) -> bool {
+        self.uid().is_some_and(|uid| uid.type_name().basename() == "Action")
+    }
+
+    /// Keep only the given attributes.
+    pub fn project(&mut self, attributes: &[&str]) {
+        if let Some(attrs) = self.0.get_mut("attrs").and_then(Value::as_object_mut) {
+            attrs.retain(|name, _| attributes.contains(&name.as_str()));
+        }
+    }
+
+    fn parents_mut(&mut self) -> Option<&mut Vec<Value>> {
+        let entity = self.0.as_object_mut()?;
+        entity.entry("parents").or_insert_with(|| json!([])).as_array_mut()
+    }
+
+    pub fn set_attribute(&mut self, attribute: String, value: Value) {
+        if let Some(entity) = self.0.as_object_mut() {
+            if let Some(attrs) = entity.entry("attrs").or_insert_with(|| json!({})).as_object_mut() {
+                attrs.insert(attribute, value);
+            }
+        }
+    }
+
+    /// Returns whether the entity had the attribute.
+    pub fn unset_attribute(&mut self, attribute: &str) -> bool {
+        let attrs = self.0.get_mut("attrs").and_then(Value::as_object_mut);
+        attrs.is_some_and(|attrs| attrs.remove(attribute).is_some())
+    }
+
+    /// Returns whether the parent was added, it's not when already declared.
+    pub fn add_parent(&mut self, parent: &EntityReference) -> Result<bool, Box<dyn Error>> {
+        let uid = EntityUid::try_from(parent)?;
+        let parents = self.parents_mut().ok_or(EmptyError)?;
+        if parents.iter().any(|p| EntityUid::from_json(p.clone()).ok().as_ref() == Some(&uid)) {
+            return Ok(false);
+        }
+        parents.push(json!(parent));
+        Ok(true)
+    }
+
+    /// Returns whether the parent was declared.
+    pub fn remove_parent(&mut self, parent: &EntityReference) -> Result<bool, Box<dyn Error>> {
+        let uid = EntityUid::try_from(parent)?;
+        let parents = self.parents_mut().ok_or(EmptyError)?;
+        let count = parents.len();
+        parents.retain(|p| EntityUid::from_json(p.clone()).ok().as_ref() != Some(&uid));
+        Ok(parents.len() < count)
+    }
+
+    /// When the entity expires, given either as a `ttl` in seconds from `now` or as an `expires_at` timestamp.
+    pub fn expires_at(&self, now: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, String> {
+        match (self.0.get(TTL_FIELD), self.0.get(EXPIRES_AT_FIELD)) {
+            (None, None) => Ok(None),
+            (Some(_), Some(_)) => Err(format!("{} and {} are exclusive", TTL_FIELD, EXPIRES_AT_FIELD)),
+            (Some(ttl), None) => {
+                let seconds = ttl.as_u64().and_then(|ttl| Duration::try_seconds(i64::try_from(ttl).ok()?));
+                match seconds.and_then(|seconds| now.checked_add_signed(seconds)) {
+                    Some(expires_at) => Ok(Some(expires_at)),
+                    None => Err(format!("{} must be a number of seconds, got {}", TTL_FIELD, ttl)),
+                }
+            }
+            (None, Some(expires_at)) => match expires_at.as_str().map(DateTime::parse_from_rfc3339) {
+                Some(Ok(expires_at)) => Ok(Some(expires_at.with_timezone(&Utc))),
+                _ => Err(format!("{} must be an RFC 3339 timestamp, got {}", EXPIRES_AT_FIELD, expires_at)),
+            },
+        }
+    }
+
+    /// Set when the entity expires, in place of the `ttl` it was submitted with.
+    pub fn set_expires_at(&mut self, expires_at: DateTime<Utc>) {
+        if let Some(entity) = self.0.as_object_mut() {
+            entity.remove(TTL_FIELD);
+            entity.insert(EXPIRES_AT_FIELD.to_string(), json!(expires_at));
+        }
+    }
+
+    /// Set the uid of an entity submitted without one.
+    pub fn set_uid(&mut self, uid: &EntityReference) {
+        if let Some(entity) = self.0.as_object_mut() {
+            entity.insert("uid".to_string(), json!(uid));
+        }
+    }
+}
+
+impl From<&cedar_policy::Entity> for Entity {
+    fn from(value: &cedar_policy::Entity) -> Self {
+        let entities = cedar_policy::Entities::from_entities([value.clone()]).unwrap();
+        Entities::from(&entities).0.remove(0)
+    }
+}
+
+impl From<&ast::Entity> for Entity {
+    fn from(value: &ast::Entity) -> Self {
+        let entity_json = EntityJSON::from_entity(value).unwrap();
+        Self(to_value(entity_json).unwrap())
     }
 }
 
@@ -45,7 +165,7 @@ impl TryInto<ast::Entity> for Entity {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Entities(Vec<Entity>);
 
 impl Entities {
@@ -53,6 +173,37 @@ impl Entities {
         self.0.len()
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Entity> {
+        self.0.iter()
+    }
+
+    /// Parse the entities into ast format in a single pass, checked against the schema when there is one.
+    /// Entities get just the parents they declare as ancestors, actions get those of the schema hierarchy
+    /// they have to match.
+    pub fn into_declared_entities(self, schema: Option<&ValidatorSchema>) -> Result<Vec<ast::Entity>, EntitiesError> {
+        debug!("Parsing entities into ast format");
+        let schema = match schema {
+            Some(schema) => schema,
+            None => {
+                let parser: EntityJsonParser<NoEntitiesSchema> =
+                    EntityJsonParser::new(None, Extensions::all_available(), TCComputation::AssumeAlreadyComputed);
+                let entities = Value::Array(self.0.into_iter().map(|entity| entity.0).collect());
+                return Ok(parser.from_json_value(entities)?.into_iter().collect());
+            }
+        };
+        let (actions, entities): (Vec<Entity>, Vec<Entity>) = self.0.into_iter().partition(Entity::is_action);
+        let parse = |entities: Vec<Entity>, tc_computation| {
+            let schema = Some(CoreSchema::new(schema));
+            let parser = EntityJsonParser::new(schema, Extensions::all_available(), tc_computation);
+            parser.from_json_value(Value::Array(entities.into_iter().map(|entity| entity.0).collect()))
+        };
+        let mut parsed: Vec<ast::Entity> = parse(entities, TCComputation::AssumeAlreadyComputed)?.into_iter().collect();
+        if !actions.is_empty() {
+            parsed.extend(parse(actions, TCComputation::ComputeNow)?);
+        }
+        Ok(parsed)
+    }
+
     // Custom conversion function in place of a TryInto implementation
     // This is due to the extra optional argument (schema)
     pub fn convert_to_cedar_entities(&self, schema: &Option<Schema>) -> Result<cedar_policy::Entities, EntitiesError> {
@@ -61,9 +212,23 @@ impl Entities {
     }
 }
 
-impl From<entities::Entities> for Entities {
-    fn from(value: entities::Entities) -> Self {
-        Self(value.iter().map(|v| Entity::from(v.clone())).collect())
+impl From<Vec<Entity>> for Entities {
+    fn from(value: Vec<Entity>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&cedar_policy::Entities> for Entities {
+    fn from(value: &cedar_policy::Entities) -> Self {
+        let mut json = Vec::new();
+        value.write_to_json(&mut json).unwrap();
+        from_slice(&json).unwrap()
+    }
+}
+
+impl From<&entities::Entities> for Entities {
+    fn from(value: &entities::Entities) -> Self {
+        Self(value.iter().map(Entity::from).collect())
     }
 }
 
@@ -77,3 +242,285 @@ impl TryInto<entities::Entities> for Entities {
         parser.from_json_value(json!(self.0))
     }
 }
+
+/// An entity uid in its JSON form, `{"type": "User", "id": "alice"}`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct EntityReference {
+    #[serde(rename = "type")]
+    pub entity_type: String,
+    pub id: String,
+}
+
+impl TryFrom<&EntityReference> for EntityUid {
+    type Error = Box<dyn Error>;
+
+    fn try_from(value: &EntityReference) -> Result<Self, Self::Error> {
+        let entity_type = EntityTypeName::from_str(&value.entity_type)?;
+        Ok(EntityUid::from_type_name_and_id(entity_type, EntityId::from_str(&value.id)?))
+    }
+}
+
+/// A single change applied as part of a data patch.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum EntityOperation {
+    /// Add a new entity, failing if the uid is already taken.
+    Add { entity: Entity },
+    /// Replace an existing entity, failing if the uid is unknown.
+    Replace { entity: Entity },
+    /// Remove an existing entity, failing if the uid is unknown.
+    Remove { uid: EntityReference },
+    /// Set an attribute of an existing entity, the value is given in the entity JSON format.
+    SetAttribute {
+        uid: EntityReference,
+        attribute: String,
+        value: Value,
+    },
+    /// Remove an attribute of an existing entity, failing if it isn't set.
+    UnsetAttribute { uid: EntityReference, attribute: String },
+    /// Add a parent to an existing entity.
+    AddParent { uid: EntityReference, parent: EntityReference },
+    /// Remove a parent of an existing entity, failing if it isn't declared.
+    RemoveParent { uid: EntityReference, parent: EntityReference },
+}
+
+/// Query parameters used to look up stored entities.
+/// Every given filter has to match for an entity to be returned.
+#[derive(FromForm, Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+pub struct EntityQuery {
+    /// Only return entities of this type, e.g. `User`.
+    #[field(name = "type")]
+    #[serde(rename = "type")]
+    pub entity_type: Option<String>,
+    /// Only return entities with this ancestor, e.g. `Group::"eng"`.
+    pub ancestor: Option<String>,
+    /// Only return entities having this attribute.
+    pub attribute: Option<String>,
+    /// Only return entities whose `attribute` has this value in Cedar syntax, e.g. `"sales"`, `3` or `true`.
+    /// A value that isn't valid Cedar is taken as a string.
+    pub value: Option<String>,
+    /// Comma separated attributes to return, every attribute is returned when unset.
+    pub fields: Option<String>,
+    /// Return the entities following this cursor, the `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// The maximum number of entities to return. Defaults to 100.
+    pub limit: Option<usize>,
+}
+
+/// A page of entities ordered by uid.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct EntityPage {
+    pub entities: Entities,
+    /// The cursor of the next page, unset on the last page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Counter of the changes applied to the stored entities.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+pub struct DataRevision {
+    pub revision: u64,
+}
+
+/// Entities related to an entity through the hierarchy, as uids in Cedar syntax.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct EntityRelations {
+    pub uid: String,
+    /// The parents, or children, of the entity.
+    pub direct: Vec<String>,
+    /// The ancestors, or descendants, of the entity, including the direct ones.
+    pub transitive: Vec<String>,
+}
+
+/// The chains of parents leading from an entity to one of its ancestors.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct EntityPaths {
+    pub from: String,
+    pub to: String,
+    /// Every path starts with `from` and ends with `to`, each entity is a parent of the one before it.
+    pub paths: Vec<Vec<String>>,
+}
+
+/// A child to parent edge of the entity hierarchy.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct EntityEdge {
+    pub child: String,
+    pub parent: String,
+}
+
+/// The part of the entity hierarchy an entity belongs to: its ancestors, its descendants and the edges between them.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct EntityGraph {
+    pub nodes: Vec<String>,
+    pub edges: Vec<EntityEdge>,
+}
+
+#[derive(FromFormField, Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphFormat {
+    #[default]
+    Dot,
+    Mermaid,
+}
+
+/// What happens when a change leaves entities referring to entities that aren't stored.
+/// Changes introducing a cycle in the entity hierarchy are always rejected.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityMode {
+    /// The change is applied and the dangling references are logged.
+    #[default]
+    Warn,
+    /// The change is rejected.
+    Reject,
+}
+
+/// A reference from an entity to an entity that isn't stored.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct DanglingReference {
+    pub entity: String,
+    /// The attribute holding the reference, unset for parents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribute: Option<String>,
+    pub reference: String,
+}
+
+/// Problems found in the references between the stored entities and from the stored policies.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct IntegrityReport {
+    pub dangling_parents: Vec<DanglingReference>,
+    pub dangling_attributes: Vec<DanglingReference>,
+    /// Chains of parents leading back to the entity they start with.
+    pub cycles: Vec<Vec<String>>,
+    /// The stored policies referencing entities that aren't stored, with the references to them.
+    pub policies: Vec<PolicySearchMatch>,
+}
+
+impl IntegrityReport {
+    pub fn is_empty(&self) -> bool {
+        self.dangling_parents.is_empty()
+            && self.dangling_attributes.is_empty()
+            && self.cycles.is_empty()
+            && self.policies.is_empty()
+    }
+}
+
+fn default_poll_interval() -> u64 {
+    60
+}
+
+/// A source the entities of a partition are fetched from, either an HTTP(S) `url` or a local `file`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DataSourceConfig {
+    /// The name of the source, its entities are stored in the partition of that name.
+    pub name: String,
+    pub url: Option<String>,
+    pub file: Option<PathBuf>,
+    /// Seconds between two fetches. Defaults to 60.
+    #[serde(default = "default_poll_interval")]
+    pub interval: u64,
+    /// The path to the array of entities in the fetched JSON, e.g. `.data.entities`. Defaults to the whole document.
+    pub path: Option<String>,
+}
+
+impl DataSourceConfig {
+    /// The url or the file the source is fetched from.
+    pub fn location(&self) -> String {
+        match (&self.url, &self.file) {
+            (Some(url), _) => url.clone(),
+            (None, Some(file)) => file.display().to_string(),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// The outcome of the fetches of a data source.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DataSourceStatus {
+    pub name: String,
+    pub location: String,
+    pub interval: u64,
+    /// When the source was last fetched, successfully or not.
+    #[schemars(with = "Option<String>")]
+    pub last_fetch: Option<DateTime<Utc>>,
+    /// When the entities of the source were last stored.
+    #[schemars(with = "Option<String>")]
+    pub last_update: Option<DateTime<Utc>>,
+    /// The error of the last fetch, none when it succeeded.
+    pub last_error: Option<String>,
+    /// The number of entities of the source as last stored.
+    pub entities: usize,
+}
+
+/// The formats entities are imported from, one entity at a time.
+#[derive(FromFormField, Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    /// One entity in JSON form per line.
+    Ndjson,
+    /// One entity per row, mapped by a `CsvMapping`.
+    Csv,
+}
+
+/// The formats the stored entities are exported in.
+#[derive(FromFormField, Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// A single JSON array.
+    #[default]
+    Json,
+    /// One entity in JSON form per line, streamed.
+    Ndjson,
+}
+
+fn default_parents_delimiter() -> String {
+    "|".to_string()
+}
+
+/// How the rows of a CSV file map to entities, columns are named by the header row.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CsvMapping {
+    /// The type of the entities, e.g. `User`.
+    #[serde(rename = "type")]
+    pub entity_type: String,
+    /// The column holding the id of the entities.
+    pub uid_column: String,
+    /// The columns holding attributes, an empty cell leaves the attribute unset.
+    #[serde(default)]
+    pub attributes: Vec<CsvAttribute>,
+    /// The column holding the parents as uids in Cedar syntax, e.g. `Group::"eng"`.
+    pub parents_column: Option<String>,
+    /// What separates the parents in their column. Defaults to `|`.
+    #[serde(default = "default_parents_delimiter")]
+    pub parents_delimiter: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CsvAttribute {
+    pub column: String,
+    /// The name of the attribute. Defaults to the name of the column.
+    pub name: Option<String>,
+    #[serde(rename = "type", default)]
+    pub value_type: CsvValueType,
+}
+
+/// How a cell is read: as it is, as a number, `true` or `false`, an entity uid in Cedar syntax,
+/// or the argument of the `ip` or `decimal` extension function.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvValueType {
+    #[default]
+    String,
+    Long,
+    Boolean,
+    Entity,
+    Ip,
+    Decimal,
+}
+
+/// The outcome of an import.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct ImportSummary {
+    /// The number of entities read.
+    pub imported: usize,
+}
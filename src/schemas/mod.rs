@@ -1,4 +1,8 @@
+pub mod analysis;
 pub mod authorization;
+pub mod bundle;
 pub mod data;
 pub mod policies;
+pub mod policy_tests;
 pub mod schema;
+pub mod tenants;
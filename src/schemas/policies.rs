@@ -1,12 +1,100 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use cedar_policy::{Effect, PrincipalConstraint, ResourceConstraint, ValidationError, ValidationErrorKind};
+use clap::ValueEnum;
 use cedar_policy_core::parser::err::ParseErrors;
 use log::debug;
+use miette::Diagnostic;
+use rocket::{FromForm, FromFormField};
 use rocket_okapi::okapi::schemars;
 use rocket_okapi::okapi::schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Descriptive information stored alongside a policy.
+/// It has no effect on authorization decisions.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct PolicyMetadata {
+    /// The team or person responsible for the policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// A link to the ticket or change request that introduced the policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// The time window in which a policy takes part in authorization decisions.
+/// Policies without bounds are always active.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct PolicySchedule {
+    /// The policy is inactive before this time, in RFC 3339 format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub not_before: Option<DateTime<Utc>>,
+    /// The policy is inactive from this time on, in RFC 3339 format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+impl PolicySchedule {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        !matches!(self.not_before, Some(not_before) if now < not_before)
+            && !matches!(self.not_after, Some(not_after) if now >= not_after)
+    }
+
+    /// A window ending before it starts would never be active.
+    pub fn is_valid(&self) -> bool {
+        match (self.not_before, self.not_after) {
+            (Some(not_before), Some(not_after)) => not_before < not_after,
+            _ => true,
+        }
+    }
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub struct Policy {
+    /// Generated by the store when left out on create and bulk replace.
+    #[serde(default)]
     pub id: String,
     pub content: String,
+    #[serde(default)]
+    pub metadata: PolicyMetadata,
+    /// Disabled policies are kept in the store but left out of authorization decisions.
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub schedule: PolicySchedule,
+    /// Annotations declared in the policy content, such as `@id("...")`.
+    /// They are derived from the content and ignored on input.
+    #[serde(default, skip_deserializing)]
+    pub annotations: BTreeMap<String, String>,
+    /// Validation errors of a policy accepted in `warn` validation mode.
+    /// The list is empty for valid policies and ignored on input.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    pub validation_errors: Vec<PolicyError>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            id: String::new(),
+            content: String::new(),
+            metadata: PolicyMetadata::default(),
+            enabled: true,
+            schedule: PolicySchedule::default(),
+            annotations: BTreeMap::new(),
+            validation_errors: Vec::new(),
+        }
+    }
 }
 
 impl From<cedar_policy::Policy> for Policy {
@@ -14,6 +102,14 @@ impl From<cedar_policy::Policy> for Policy {
         Policy {
             id: policy.id().to_string(),
             content: policy.to_string(),
+            metadata: PolicyMetadata::default(),
+            enabled: true,
+            schedule: PolicySchedule::default(),
+            annotations: policy
+                .annotations()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            validation_errors: Vec::new(),
         }
     }
 }
@@ -32,11 +128,470 @@ impl Policy {
         Policy {
             id,
             content: policy_update.content,
+            metadata: policy_update.metadata.unwrap_or_default(),
+            enabled: true,
+            schedule: policy_update.schedule.unwrap_or_default(),
+            annotations: BTreeMap::new(),
+            validation_errors: Vec::new(),
         }
     }
+
+    /// The policy `existing` becomes with the update applied, it keeps what the update leaves out.
+    /// A new policy is enabled.
+    pub fn updated(existing: Option<Policy>, id: String, policy_update: PolicyUpdate) -> Self {
+        let existing = match existing {
+            Some(existing) => existing,
+            None => return Policy::from_policy_update(id, policy_update),
+        };
+        Policy {
+            id,
+            content: policy_update.content,
+            metadata: policy_update.metadata.unwrap_or(existing.metadata),
+            enabled: existing.enabled,
+            schedule: policy_update.schedule.unwrap_or(existing.schedule),
+            annotations: BTreeMap::new(),
+            validation_errors: Vec::new(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: PolicyMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Whether the policy takes part in authorization decisions at the given time.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.enabled && self.schedule.is_active(now)
+    }
 }
 
-#[derive(Serialize, Deserialize, JsonSchema)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
 pub struct PolicyUpdate {
     pub content: String,
+    /// Replaces the stored metadata when present, otherwise the stored metadata is kept.
+    #[serde(default)]
+    pub metadata: Option<PolicyMetadata>,
+    /// Replaces the stored validity window when present, otherwise the stored window is kept.
+    #[serde(default)]
+    pub schedule: Option<PolicySchedule>,
+}
+
+#[derive(FromFormField, Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyEffect {
+    Permit,
+    Forbid,
+}
+
+impl From<Effect> for PolicyEffect {
+    fn from(effect: Effect) -> Self {
+        match effect {
+            Effect::Permit => PolicyEffect::Permit,
+            Effect::Forbid => PolicyEffect::Forbid,
+        }
+    }
+}
+
+/// Query parameters used to narrow down a policy listing.
+/// Every given parameter has to match for a policy to be listed.
+#[derive(FromForm, Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+pub struct PolicyFilter {
+    /// Only list policies carrying this tag.
+    pub tag: Option<String>,
+    /// Only list policies with this owner.
+    pub owner: Option<String>,
+    /// Only list `permit` or `forbid` policies.
+    pub effect: Option<PolicyEffect>,
+    /// Only list policies whose principal scope names an entity of this type.
+    pub principal_type: Option<String>,
+    /// Only list policies whose resource scope names an entity of this type.
+    pub resource_type: Option<String>,
+    /// Only list policies declaring this annotation.
+    pub annotation_key: Option<String>,
+    /// Only list policies whose `annotation_key` annotation has this value.
+    pub annotation_value: Option<String>,
+}
+
+impl PolicyFilter {
+    pub fn matches(&self, policy: &cedar_policy::Policy, metadata: &PolicyMetadata) -> bool {
+        if let Some(tag) = &self.tag {
+            if !metadata.tags.contains(tag) {
+                return false;
+            }
+        }
+        if self.owner.is_some() && self.owner != metadata.owner {
+            return false;
+        }
+        if let Some(effect) = self.effect {
+            if effect != PolicyEffect::from(policy.effect()) {
+                return false;
+            }
+        }
+        if let Some(principal_type) = &self.principal_type {
+            let scope_type = match policy.principal_constraint() {
+                PrincipalConstraint::Any => None,
+                PrincipalConstraint::In(uid) | PrincipalConstraint::Eq(uid) => {
+                    Some(uid.type_name().to_string())
+                }
+            };
+            if scope_type.as_ref() != Some(principal_type) {
+                return false;
+            }
+        }
+        if let Some(resource_type) = &self.resource_type {
+            let scope_type = match policy.resource_constraint() {
+                ResourceConstraint::Any => None,
+                ResourceConstraint::In(uid) | ResourceConstraint::Eq(uid) => {
+                    Some(uid.type_name().to_string())
+                }
+            };
+            if scope_type.as_ref() != Some(resource_type) {
+                return false;
+            }
+        }
+        if let Some(key) = &self.annotation_key {
+            match policy.annotation(key) {
+                Some(value) => {
+                    if let Some(expected) = &self.annotation_value {
+                        if value != expected {
+                            return false;
+                        }
+                    }
+                }
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Query parameters of a structural search over the stored policies.
+/// Every given parameter has to match for a policy to be returned.
+#[derive(FromForm, Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+pub struct PolicySearch {
+    /// Only return policies referencing this entity, e.g. `User::"alice"`.
+    pub entity: Option<String>,
+    /// Only return policies referencing an entity of this type, e.g. `User`.
+    pub entity_type: Option<String>,
+    /// Only return policies referencing this action, e.g. `Action::"delete"` or just `delete`.
+    pub action: Option<String>,
+    /// Only return policies reading or testing this attribute, e.g. `owner` or `resource.owner`.
+    pub attribute: Option<String>,
+    /// Only return `permit` or `forbid` policies.
+    pub effect: Option<PolicyEffect>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyReferenceKind {
+    Entity,
+    Action,
+    Attribute,
+}
+
+/// A place in a policy where a searched entity, action or attribute is referenced.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct PolicyReference {
+    pub kind: PolicyReferenceKind,
+    /// The referenced entity uid, or the attribute name.
+    pub value: String,
+    /// Where the reference is in the policy content.
+    pub span: Option<SourceSpan>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct PolicySearchMatch {
+    pub id: String,
+    pub references: Vec<PolicyReference>,
+}
+
+/// Deserialize a field that may be `null` into `Some`, to tell a field set to `null` from a field left out.
+fn explicit<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Option<Option<T>>, D::Error> {
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// A policy added or replacing the stored one by a policy transaction.
+/// The enabled flag and the schedule bounds it leaves out are kept from the replaced policy, a bound set to `null`
+/// is removed.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct PolicyUpsert {
+    #[serde(default)]
+    pub id: String,
+    pub content: String,
+    #[serde(default)]
+    pub metadata: PolicyMetadata,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default, deserialize_with = "explicit", skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub not_before: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "explicit", skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub not_after: Option<Option<DateTime<Utc>>>,
+}
+
+impl PolicyUpsert {
+    /// The policy replacing `existing`, a new policy is enabled and always active unless set otherwise.
+    pub fn into_policy(self, existing: Option<&Policy>) -> Policy {
+        let (enabled, schedule) = match existing {
+            Some(existing) => (existing.enabled, existing.schedule.clone()),
+            None => (true, PolicySchedule::default()),
+        };
+        Policy {
+            id: self.id,
+            content: self.content,
+            metadata: self.metadata,
+            enabled: self.enabled.unwrap_or(enabled),
+            schedule: PolicySchedule {
+                not_before: self.not_before.unwrap_or(schedule.not_before),
+                not_after: self.not_after.unwrap_or(schedule.not_after),
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// An upsert setting everything, as it is set in the policy.
+impl From<Policy> for PolicyUpsert {
+    fn from(policy: Policy) -> Self {
+        PolicyUpsert {
+            id: policy.id,
+            content: policy.content,
+            metadata: policy.metadata,
+            enabled: Some(policy.enabled),
+            not_before: Some(policy.schedule.not_before),
+            not_after: Some(policy.schedule.not_after),
+        }
+    }
+}
+
+/// A single change applied as part of a policy transaction.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PolicyOperation {
+    /// Add a new policy, failing if the id is already taken.
+    Create(Policy),
+    /// Add a new policy or replace the existing one with the same id.
+    Upsert(PolicyUpsert),
+    /// Remove an existing policy, failing if the id is unknown.
+    Delete { id: String },
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyOperationStatus {
+    Created,
+    Updated,
+    Deleted,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct PolicyOperationResult {
+    pub id: String,
+    pub status: PolicyOperationStatus,
+    /// The stored policy after a create or upsert, or the removed policy after a delete.
+    pub policy: Policy,
+}
+
+/// A location inside the policy content.
+/// Offsets are in bytes, lines and columns start at 1 and count characters.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct SourceSpan {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl SourceSpan {
+    pub fn new(content: &str, start: usize, end: usize) -> Self {
+        let (line, column) = Self::line_column(content, start);
+        let (end_line, end_column) = Self::line_column(content, end);
+        Self {
+            start,
+            end,
+            line,
+            column,
+            end_line,
+            end_column,
+        }
+    }
+
+    fn line_column(content: &str, offset: usize) -> (usize, usize) {
+        let mut line = 1;
+        let mut column = 1;
+        for (index, c) in content.char_indices() {
+            if index >= offset {
+                break;
+            }
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        (line, column)
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyErrorKind {
+    /// The policy content is not valid Cedar syntax.
+    ParseError,
+    UnrecognizedEntityType,
+    UnrecognizedActionId,
+    InvalidActionApplication,
+    TypeError,
+    UnspecifiedEntity,
+    /// Any other validation error reported by Cedar.
+    ValidationError,
+}
+
+impl From<&ValidationErrorKind> for PolicyErrorKind {
+    fn from(kind: &ValidationErrorKind) -> Self {
+        match kind {
+            ValidationErrorKind::UnrecognizedEntityType(_) => PolicyErrorKind::UnrecognizedEntityType,
+            ValidationErrorKind::UnrecognizedActionId(_) => PolicyErrorKind::UnrecognizedActionId,
+            ValidationErrorKind::InvalidActionApplication(_) => PolicyErrorKind::InvalidActionApplication,
+            ValidationErrorKind::TypeError(_) => PolicyErrorKind::TypeError,
+            ValidationErrorKind::UnspecifiedEntity(_) => PolicyErrorKind::UnspecifiedEntity,
+            _ => PolicyErrorKind::ValidationError,
+        }
+    }
+}
+
+/// A parse or validation problem found in a single policy.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct PolicyError {
+    pub policy_id: String,
+    pub kind: PolicyErrorKind,
+    pub message: String,
+    /// Where in the policy content the problem was found, when Cedar reports it.
+    pub span: Option<SourceSpan>,
+}
+
+impl std::fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "policy {}", self.policy_id)?;
+        if let Some(span) = &self.span {
+            write!(f, " at line {} column {}", span.line, span.column)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl PolicyError {
+    pub fn from_parse_errors(policy_id: &str, content: &str, errors: &ParseErrors) -> Vec<Self> {
+        errors
+            .iter()
+            .map(|err| PolicyError {
+                policy_id: policy_id.to_string(),
+                kind: PolicyErrorKind::ParseError,
+                message: err.to_string(),
+                span: err
+                    .labels()
+                    .and_then(|mut labels| labels.next())
+                    .map(|label| SourceSpan::new(content, label.offset(), label.offset() + label.len())),
+            })
+            .collect()
+    }
+
+    pub fn from_validation_error(content: &str, error: &ValidationError) -> Self {
+        let location = error.location();
+        PolicyError {
+            policy_id: location.policy_id().to_string(),
+            kind: PolicyErrorKind::from(error.error_kind()),
+            message: error.error_kind().to_string(),
+            span: match (location.range_start(), location.range_end()) {
+                (Some(start), Some(end)) => Some(SourceSpan::new(content, start, end)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// What happens to policies failing schema validation.
+#[derive(ValueEnum, FromFormField, Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationLevel {
+    /// Policies are not validated.
+    Off,
+    /// Invalid policies are accepted and flagged with their validation errors.
+    Warn,
+    /// Invalid policies are rejected.
+    #[default]
+    Enforce,
+}
+
+#[derive(ValueEnum, FromFormField, Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyValidationMode {
+    /// Cedar's strict validation, rejecting some type-correct but hard to analyze policies.
+    #[default]
+    Strict,
+    /// Cedar's permissive validation, only checking for type errors.
+    Permissive,
+}
+
+impl From<PolicyValidationMode> for cedar_policy::ValidationMode {
+    fn from(mode: PolicyValidationMode) -> Self {
+        match mode {
+            PolicyValidationMode::Strict => cedar_policy::ValidationMode::Strict,
+            PolicyValidationMode::Permissive => cedar_policy::ValidationMode::Permissive,
+        }
+    }
+}
+
+/// How policies are validated against the schema.
+/// Used both for the agent configuration and as per-request query parameters,
+/// unset values fall back to the configuration and then to `enforce` and `strict`.
+#[derive(FromForm, Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default)]
+pub struct ValidationSettings {
+    pub validation: Option<ValidationLevel>,
+    pub validation_mode: Option<PolicyValidationMode>,
+}
+
+impl ValidationSettings {
+    pub fn new(validation: ValidationLevel, validation_mode: PolicyValidationMode) -> Self {
+        Self {
+            validation: Some(validation),
+            validation_mode: Some(validation_mode),
+        }
+    }
+
+    /// Fill the unset values from `fallback`.
+    pub fn or(self, fallback: ValidationSettings) -> Self {
+        Self {
+            validation: self.validation.or(fallback.validation),
+            validation_mode: self.validation_mode.or(fallback.validation_mode),
+        }
+    }
+
+    pub fn level(&self) -> ValidationLevel {
+        self.validation.unwrap_or_default()
+    }
+
+    pub fn mode(&self) -> PolicyValidationMode {
+        self.validation_mode.unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyTransitionKind {
+    Activation,
+    Expiration,
+}
+
+/// An upcoming change of the active policies, caused by a policy schedule.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct PolicyTransition {
+    pub policy_id: String,
+    pub kind: PolicyTransitionKind,
+    #[schemars(with = "String")]
+    pub at: DateTime<Utc>,
 }
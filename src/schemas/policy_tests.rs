@@ -0,0 +1,44 @@
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::authorization::{AuthorizationCall, DecisionRef};
+
+/// An authorization request with the answer the stored policies are expected to give.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct PolicyTest {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub request: AuthorizationCall,
+    pub decision: DecisionRef,
+    /// The ids of the policies expected to determine the decision, not checked when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasons: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct PolicyTestResult {
+    pub id: String,
+    pub passed: bool,
+    pub expected_decision: DecisionRef,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_reasons: Option<Vec<String>>,
+    /// The decision of the stored policies, unset when the request could not be evaluated.
+    pub decision: Option<DecisionRef>,
+    pub reasons: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct PolicyTestReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub results: Vec<PolicyTestResult>,
+}
+
+impl PolicyTestReport {
+    pub fn failures(self) -> Vec<PolicyTestResult> {
+        self.results.into_iter().filter(|result| !result.passed).collect()
+    }
+}
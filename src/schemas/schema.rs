@@ -1,4 +1,5 @@
 use cedar_policy;
+use cedar_policy_validator::ValidatorSchema;
 use log::debug;
 use serde::{Deserialize, Serialize};
 
@@ -30,3 +31,12 @@ impl TryInto<cedar_policy::Schema> for Schema {
         cedar_policy::Schema::from_json_value(self.0)
     }
 }
+
+impl TryInto<ValidatorSchema> for Schema {
+    type Error = cedar_policy::SchemaError;
+
+    fn try_into(self) -> Result<ValidatorSchema, Self::Error> {
+        debug!("Parsing schema");
+        Ok(ValidatorSchema::from_json_value(self.0)?)
+    }
+}
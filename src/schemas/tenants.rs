@@ -0,0 +1,36 @@
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Limits applied to the stores of a tenant, unset limits are not enforced.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct TenantQuotas {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_policies: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_entities: Option<usize>,
+    /// The largest request body accepted by the tenant routes, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_payload_bytes: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+pub struct TenantCreate {
+    pub name: String,
+    #[serde(default)]
+    pub quotas: TenantQuotas,
+    /// API key granting access to the tenant routes only.
+    /// The agent authentication token is accepted as well.
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct Tenant {
+    pub name: String,
+    pub quotas: TenantQuotas,
+    /// Number of stored policies.
+    pub policies: usize,
+    /// Number of stored entities.
+    pub entities: usize,
+}
@@ -0,0 +1,47 @@
+use std::error::Error;
+
+use thiserror::Error;
+
+use crate::schemas::policies::PolicyError;
+use crate::services::data::errors::DataStoreError;
+use crate::services::policies::errors::PolicyStoreError;
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum BundleError {
+    /// Reference to SchemaError.
+    #[error("Invalid schema: {0}")]
+    InvalidSchema(#[from] cedar_policy::SchemaError),
+    /// Parsing or validation returned errors for one or more policies.
+    #[error("Invalid policies: {}", .0.iter().map(|e| e.to_string()).collect::<Vec<String>>().join("; "))]
+    InvalidPolicies(Vec<PolicyError>),
+    /// The entities don't parse or don't conform to the schema.
+    #[error("Invalid entities: {0}")]
+    InvalidEntities(String),
+    /// Applying the bundle would exceed a store quota.
+    #[error("{0}")]
+    QuotaExceeded(String),
+    /// A store rejected its part of the bundle.
+    #[error("Unable to apply the bundle: {0}")]
+    Rejected(String),
+}
+
+impl BundleError {
+    /// Convert an error returned by the policy or data store.
+    pub fn from_store_error(err: Box<dyn Error>) -> Self {
+        let err = match err.downcast::<PolicyStoreError>() {
+            Ok(err) => {
+                return match *err {
+                    PolicyStoreError::PolicyInvalid(_, errors) => BundleError::InvalidPolicies(errors),
+                    err @ PolicyStoreError::QuotaExceeded(_) => BundleError::QuotaExceeded(err.to_string()),
+                    err => BundleError::Rejected(err.to_string()),
+                }
+            }
+            Err(err) => err,
+        };
+        match err.downcast::<DataStoreError>() {
+            Ok(err) => BundleError::QuotaExceeded(err.to_string()),
+            Err(err) => BundleError::Rejected(err.to_string()),
+        }
+    }
+}
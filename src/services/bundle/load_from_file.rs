@@ -0,0 +1,99 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use log::{error, info};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Build, Rocket};
+
+use crate::config;
+use crate::schemas::bundle::Bundle;
+use crate::services::bundle::import_bundle;
+use crate::services::data::DataStore;
+use crate::services::policies::PolicyStore;
+use crate::services::schema::SchemaStore;
+
+pub struct InitBundleFairing;
+
+pub(crate) async fn init(
+    conf: &config::Config,
+    policy_store: &dyn PolicyStore,
+    data_store: &dyn DataStore,
+    schema_store: &dyn SchemaStore,
+) {
+    let file_path = match &conf.bundle {
+        Some(file_path) => file_path,
+        None => return,
+    };
+    let bundle = match load_bundle_from_file(file_path.to_path_buf()).await {
+        Ok(bundle) => bundle,
+        Err(err) => {
+            error!("Failed to load bundle from file: {}", err);
+            return;
+        }
+    };
+
+    match import_bundle(policy_store, data_store, schema_store, bundle, conf.validation_settings()).await {
+        Ok(bundle) => info!(
+            "Successfully imported bundle from file {}: {} policies, {} entities",
+            file_path.display(),
+            bundle.policies.len(),
+            bundle.entities.len()
+        ),
+        Err(err) => error!("Failed to import bundle: {}", err),
+    }
+}
+
+pub async fn load_bundle_from_file(path: PathBuf) -> Result<Bundle, Box<dyn Error>> {
+    if !path.try_exists().unwrap_or(false) || !path.is_file() {
+        return Err("File does not exist".into());
+    }
+
+    if path.extension().unwrap_or_default() != "json" {
+        return Err("File is not a json file".into());
+    }
+
+    let mut file = match File::open(&path) {
+        Ok(file) => file,
+        Err(err) => return Err(format!("Failed to open file: {}", err).into()),
+    };
+
+    let mut contents = String::new();
+    if let Err(err) = file.read_to_string(&mut contents) {
+        return Err(format!("Failed to read file: {}", err).into());
+    }
+
+    match rocket::serde::json::from_str(&contents) {
+        Ok(bundle) => Ok(bundle),
+        Err(err) => Err(format!("Failed to deserialize JSON: {}", err).into()),
+    }
+}
+
+#[async_trait::async_trait]
+impl Fairing for InitBundleFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Init Bundle",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> Result<Rocket<Build>, Rocket<Build>> {
+        let config = match rocket.state::<config::Config>() {
+            Some(config) => config,
+            None => return Ok(rocket),
+        };
+
+        init(
+            config,
+            rocket.state::<Arc<dyn PolicyStore>>().unwrap().as_ref(),
+            rocket.state::<Arc<dyn DataStore>>().unwrap().as_ref(),
+            rocket.state::<Arc<dyn SchemaStore>>().unwrap().as_ref(),
+        )
+        .await;
+
+        Ok(rocket)
+    }
+}
@@ -0,0 +1,78 @@
+use cedar_policy::Schema as CedarSchema;
+use cedar_policy_validator::ValidatorSchema;
+use log::info;
+use ref_cast::RefCast;
+
+use crate::schemas::bundle::Bundle;
+use crate::schemas::policies::ValidationSettings;
+use crate::services::bundle::errors::BundleError;
+use crate::services::data::DataStore;
+use crate::services::policies::PolicyStore;
+use crate::services::schema::SchemaStore;
+use crate::services::staged::StagedChange;
+
+pub mod errors;
+pub mod load_from_file;
+
+/// Read the full state of the stores.
+pub async fn export_bundle(
+    policy_store: &dyn PolicyStore,
+    data_store: &dyn DataStore,
+    schema_store: &dyn SchemaStore,
+) -> Bundle {
+    Bundle {
+        schema: schema_store.get_internal_schema().await,
+        policies: policy_store.get_policies().await,
+        entities: data_store.get_entities().await,
+    }
+}
+
+/// Replace the state of the stores with the bundle, all at once or not at all.
+/// Every store checks its part first, then the three stores are locked together and their parts swapped in,
+/// so neither a reader nor a writer sees some of the stores changed and not the others.
+pub async fn import_bundle(
+    policy_store: &dyn PolicyStore,
+    data_store: &dyn DataStore,
+    schema_store: &dyn SchemaStore,
+    bundle: Bundle,
+    validation: ValidationSettings,
+) -> Result<Bundle, BundleError> {
+    info!("Importing bundle");
+    let validator_schema: Option<ValidatorSchema> = if bundle.schema.is_empty() {
+        None
+    } else {
+        Some(bundle.schema.clone().try_into()?)
+    };
+    let schema = validator_schema.as_ref().map(|schema| CedarSchema::ref_cast(schema).clone());
+    if let Err(err) = bundle.entities.convert_to_cedar_entities(&schema) {
+        return Err(BundleError::InvalidEntities(err.to_string()));
+    }
+
+    let mut staged: Vec<Box<dyn StagedChange + '_>> = Vec::with_capacity(3);
+    staged.push(schema_store.stage_schema(bundle.schema.clone()).await?);
+    let (policies, staged_policies) = policy_store
+        .stage_policies(bundle.policies, schema, validation)
+        .await
+        .map_err(BundleError::from_store_error)?;
+    staged.push(staged_policies);
+    let (entities, staged_entities) = data_store
+        .stage_entities(bundle.entities, validator_schema)
+        .await
+        .map_err(BundleError::from_store_error)?;
+    staged.push(staged_entities);
+
+    // Always locked in the same order, the locks are released together once every part is stored.
+    for change in staged.iter_mut() {
+        change.lock().await;
+    }
+    for change in staged.iter_mut() {
+        change.apply();
+    }
+    drop(staged);
+
+    Ok(Bundle {
+        schema: bundle.schema,
+        policies,
+        entities,
+    })
+}
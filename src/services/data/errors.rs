@@ -0,0 +1,67 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum DataStoreError {
+    /// The change would store more entities than allowed.
+    #[error("Entity quota exceeded, at most {0} entities can be stored")]
+    QuotaExceeded(usize),
+    /// An operation of a data patch could not be applied.
+    #[error("Failed applying entity operation {0}: {1}")]
+    EntityOperationFailed(usize, String),
+    /// An entity query parameter is invalid.
+    #[error("Invalid entity query: {0}")]
+    InvalidQuery(String),
+    /// The change would make an entity its own ancestor.
+    #[error("Entity hierarchy has a cycle: {0}")]
+    Cycle(String),
+    /// The change leaves entities referring to entities that aren't stored, and such changes are rejected.
+    #[error("Entity integrity violated: {0}")]
+    IntegrityViolation(String),
+    /// An entity has a `ttl` or `expires_at` that can't be read.
+    #[error("Invalid entity expiry: {0}")]
+    InvalidExpiry(String),
+}
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum DataSourceError {
+    /// The configuration of a source is invalid.
+    #[error("Invalid data source: {0}")]
+    InvalidConfig(String),
+    /// The source couldn't be read.
+    #[error("Failed fetching {0}: {1}")]
+    Fetch(String, String),
+    /// The source answered with an error status.
+    #[error("Fetching {0} returned status {1}")]
+    Status(String, u16),
+    /// The fetched document doesn't hold entities at the configured path.
+    #[error("Invalid entities from {0}: {1}")]
+    InvalidEntities(String, String),
+    /// The fetched entities make stored policy tests fail.
+    #[error("Entities from {0} make stored policy tests fail: {1}")]
+    TestsFailed(String, String),
+}
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum DataImportError {
+    /// The imported data couldn't be read.
+    #[error("Failed reading entities: {0}")]
+    Read(String),
+    /// The imported data is larger than allowed.
+    #[error("Imported data exceeds the limit of {0} bytes")]
+    TooLarge(u64),
+    /// A line of NDJSON isn't an entity.
+    #[error("Invalid entity on line {0}: {1}")]
+    InvalidLine(usize, String),
+    /// A CSV row doesn't match the mapping.
+    #[error("Invalid CSV row {0}: {1}")]
+    InvalidRow(usize, String),
+    /// The CSV mapping doesn't match the header row.
+    #[error("Invalid CSV mapping: {0}")]
+    InvalidMapping(String),
+    /// The same entity is imported twice.
+    #[error("Entity {0} is imported twice")]
+    Duplicate(String),
+}
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::info;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::tokio::time::{interval, MissedTickBehavior};
+use rocket::{tokio, Orbit, Rocket};
+
+use crate::services::data::DataStore;
+use crate::services::tenants::TenantStore;
+
+/// How often expired entities are swept.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Removes the expired entities of the agent and of every tenant.
+pub struct EntityExpiryFairing;
+
+async fn sweep(data_store: &Arc<dyn DataStore>, tenant_store: Option<&Arc<dyn TenantStore>>) {
+    data_store.remove_expired_entities().await;
+    if let Some(tenant_store) = tenant_store {
+        for tenant in tenant_store.get_tenant_stores().await {
+            tenant.data_store.remove_expired_entities().await;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Fairing for EntityExpiryFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Entity Expiry",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let data_store = match rocket.state::<Arc<dyn DataStore>>() {
+            Some(data_store) => data_store.clone(),
+            None => return,
+        };
+        let tenant_store = rocket.state::<Arc<dyn TenantStore>>().cloned();
+        let mut shutdown = rocket.shutdown();
+
+        info!("Starting entity expiry task");
+        tokio::spawn(async move {
+            let mut ticks = interval(SWEEP_INTERVAL);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => sweep(&data_store, tenant_store.as_ref()).await,
+                    _ = &mut shutdown => break,
+                }
+            }
+            info!("Stopped entity expiry task");
+        });
+    }
+}
@@ -0,0 +1,45 @@
+use crate::schemas::data::{EntityGraph, GraphFormat};
+
+/// The maximum number of paths returned between two entities.
+pub const MAX_PATHS: usize = 100;
+
+fn dot_id(uid: &str) -> String {
+    format!("\"{}\"", uid.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn mermaid_label(uid: &str) -> String {
+    uid.replace('"', "#quot;")
+}
+
+/// Render the graph in the DOT language of Graphviz, edges point from child to parent.
+pub fn to_dot(graph: &EntityGraph) -> String {
+    let mut dot = String::from("digraph entities {\n");
+    for node in &graph.nodes {
+        dot.push_str(&format!("    {};\n", dot_id(node)));
+    }
+    for edge in &graph.edges {
+        dot.push_str(&format!("    {} -> {};\n", dot_id(&edge.child), dot_id(&edge.parent)));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Render the graph as a Mermaid flowchart, edges point from child to parent.
+pub fn to_mermaid(graph: &EntityGraph) -> String {
+    let mut mermaid = String::from("flowchart BT\n");
+    for (index, node) in graph.nodes.iter().enumerate() {
+        mermaid.push_str(&format!("    n{}[\"{}\"]\n", index, mermaid_label(node)));
+    }
+    let id = |uid: &String| graph.nodes.iter().position(|node| node == uid).unwrap_or_default();
+    for edge in &graph.edges {
+        mermaid.push_str(&format!("    n{} --> n{}\n", id(&edge.child), id(&edge.parent)));
+    }
+    mermaid
+}
+
+pub fn render(graph: &EntityGraph, format: GraphFormat) -> String {
+    match format {
+        GraphFormat::Dot => to_dot(graph),
+        GraphFormat::Mermaid => to_mermaid(graph),
+    }
+}
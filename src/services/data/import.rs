@@ -0,0 +1,281 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use cedar_policy::{EntityTypeName, EntityUid};
+use csv_core::ReadRecordResult;
+use rocket::serde::json::serde_json::{from_slice, from_str, from_value, to_writer, Map};
+use rocket::serde::json::{json, Value};
+use rocket::tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::schemas::data as schemas;
+use crate::services::data::errors::DataImportError;
+
+/// The number of entities read before they are parsed, bounding the JSON held at once.
+const IMPORT_CHUNK: usize = 10_000;
+
+/// Entities read from a stream a chunk at a time.
+#[async_trait]
+pub trait EntityReader: Send {
+    /// The next entities, `None` once all were read.
+    async fn next_chunk(&mut self) -> Result<Option<schemas::Entities>, DataImportError>;
+}
+
+/// The bytes read so far, failing past the limit.
+#[derive(Default)]
+struct ReadBytes {
+    read: u64,
+    limit: Option<u64>,
+}
+
+impl ReadBytes {
+    fn add(&mut self, bytes: usize) -> Result<(), DataImportError> {
+        self.read += bytes as u64;
+        match self.limit {
+            Some(limit) if self.read > limit => Err(DataImportError::TooLarge(limit)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn read_error(err: std::io::Error) -> DataImportError {
+    DataImportError::Read(err.to_string())
+}
+
+/// Reads one entity in JSON form per line, blank lines are skipped.
+pub struct NdjsonReader<R> {
+    reader: R,
+    line: Vec<u8>,
+    number: usize,
+    bytes: ReadBytes,
+}
+
+impl<R: AsyncBufRead + Unpin + Send> NdjsonReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: Vec::new(),
+            number: 0,
+            bytes: ReadBytes::default(),
+        }
+    }
+
+    /// Fail once more than `limit` bytes are read.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.bytes.limit = Some(limit);
+        self
+    }
+}
+
+#[async_trait]
+impl<R: AsyncBufRead + Unpin + Send> EntityReader for NdjsonReader<R> {
+    async fn next_chunk(&mut self) -> Result<Option<schemas::Entities>, DataImportError> {
+        let mut entities = Vec::new();
+        while entities.len() < IMPORT_CHUNK {
+            self.line.clear();
+            let read = self.reader.read_until(b'\n', &mut self.line).await.map_err(read_error)?;
+            if read == 0 {
+                break;
+            }
+            self.bytes.add(read)?;
+            self.number += 1;
+            let line = self.line.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+            let entity = from_slice(line).map_err(|err| DataImportError::InvalidLine(self.number, err.to_string()))?;
+            entities.push(entity);
+        }
+        Ok(Some(entities).filter(|entities| !entities.is_empty()).map(schemas::Entities::from))
+    }
+}
+
+/// The positions of the mapped columns in the header row.
+struct CsvColumns {
+    uid: usize,
+    attributes: Vec<(usize, String, schemas::CsvValueType)>,
+    parents: Option<usize>,
+}
+
+/// An entity uid in Cedar syntax turned into its JSON form.
+fn uid_json(uid: &str) -> Result<Value, String> {
+    let uid = EntityUid::from_str(uid).map_err(|err| format!("invalid entity uid {}: {}", uid, err))?;
+    Ok(json!({"type": uid.type_name().to_string(), "id": uid.id().as_ref()}))
+}
+
+fn csv_value(cell: &str, value_type: schemas::CsvValueType) -> Result<Value, String> {
+    match value_type {
+        schemas::CsvValueType::String => Ok(json!(cell)),
+        schemas::CsvValueType::Long => cell.parse::<i64>().map(|long| json!(long)).map_err(|err| err.to_string()),
+        schemas::CsvValueType::Boolean => match cell {
+            "true" => Ok(json!(true)),
+            "false" => Ok(json!(false)),
+            _ => Err(format!("{} is neither true nor false", cell)),
+        },
+        schemas::CsvValueType::Entity => Ok(json!({ "__entity": uid_json(cell)? })),
+        schemas::CsvValueType::Ip => Ok(json!({"__extn": {"fn": "ip", "arg": cell}})),
+        schemas::CsvValueType::Decimal => Ok(json!({"__extn": {"fn": "decimal", "arg": cell}})),
+    }
+}
+
+/// Reads one entity per CSV row, as mapped from the columns named by the header row.
+pub struct CsvReader<R> {
+    reader: R,
+    csv: csv_core::Reader,
+    mapping: schemas::CsvMapping,
+    columns: Option<CsvColumns>,
+    /// The fields of the current row and where each of them ends.
+    fields: Vec<u8>,
+    ends: Vec<usize>,
+    /// The number of the current row, the header being the first one.
+    row: usize,
+    bytes: ReadBytes,
+}
+
+impl<R: AsyncBufRead + Unpin + Send> CsvReader<R> {
+    pub fn new(reader: R, mapping: schemas::CsvMapping) -> Result<Self, DataImportError> {
+        if let Err(err) = EntityTypeName::from_str(&mapping.entity_type) {
+            let invalid = format!("invalid entity type {}: {}", mapping.entity_type, err);
+            return Err(DataImportError::InvalidMapping(invalid));
+        }
+        if mapping.parents_delimiter.is_empty() {
+            return Err(DataImportError::InvalidMapping("the parents delimiter is empty".to_string()));
+        }
+        Ok(Self {
+            reader,
+            csv: csv_core::Reader::new(),
+            mapping,
+            columns: None,
+            fields: vec![0; 1024],
+            ends: vec![0; 32],
+            row: 0,
+            bytes: ReadBytes::default(),
+        })
+    }
+
+    /// Fail once more than `limit` bytes are read.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.bytes.limit = Some(limit);
+        self
+    }
+
+    /// The cells of the next row, `None` at the end of the data.
+    async fn next_row(&mut self) -> Result<Option<Vec<String>>, DataImportError> {
+        let (mut output, mut ends) = (0, 0);
+        loop {
+            let input = self.reader.fill_buf().await.map_err(read_error)?;
+            let (result, read, written, ended) =
+                self.csv.read_record(input, &mut self.fields[output..], &mut self.ends[ends..]);
+            self.reader.consume(read);
+            self.bytes.add(read)?;
+            output += written;
+            ends += ended;
+            match result {
+                ReadRecordResult::InputEmpty => {}
+                ReadRecordResult::OutputFull => self.fields.resize(self.fields.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::End => return Ok(None),
+                ReadRecordResult::Record => {
+                    self.row += 1;
+                    let mut start = 0;
+                    let mut cells = Vec::with_capacity(ends);
+                    for &end in &self.ends[..ends] {
+                        let cell = String::from_utf8(self.fields[start..end].to_vec());
+                        cells.push(cell.map_err(|err| DataImportError::InvalidRow(self.row, err.to_string()))?);
+                        start = end;
+                    }
+                    return Ok(Some(cells));
+                }
+            }
+        }
+    }
+
+    fn columns(&self, header: &[String]) -> Result<CsvColumns, DataImportError> {
+        // Spreadsheets may start the file with a byte order mark.
+        let header: Vec<&str> = header.iter().map(|cell| cell.trim_start_matches('\u{feff}').trim()).collect();
+        let position = |column: &str| {
+            let position = header.iter().position(|cell| *cell == column);
+            position.ok_or_else(|| DataImportError::InvalidMapping(format!("no column {} in the header", column)))
+        };
+        let attributes = self.mapping.attributes.iter().map(|attribute| {
+            let name = attribute.name.clone().unwrap_or_else(|| attribute.column.clone());
+            Ok((position(&attribute.column)?, name, attribute.value_type))
+        });
+        Ok(CsvColumns {
+            uid: position(&self.mapping.uid_column)?,
+            attributes: attributes.collect::<Result<_, DataImportError>>()?,
+            parents: self.mapping.parents_column.as_deref().map(position).transpose()?,
+        })
+    }
+
+    fn entity(&self, columns: &CsvColumns, row: &[String]) -> Result<schemas::Entity, String> {
+        let cell = |index: usize| row.get(index).map(|cell| cell.trim()).unwrap_or_default();
+        let id = cell(columns.uid);
+        if id.is_empty() {
+            return Err(format!("no id in column {}", self.mapping.uid_column));
+        }
+        let mut attrs = Map::new();
+        for (index, name, value_type) in &columns.attributes {
+            if !cell(*index).is_empty() {
+                let value = csv_value(cell(*index), *value_type).map_err(|err| format!("attribute {}: {}", name, err))?;
+                attrs.insert(name.clone(), value);
+            }
+        }
+        let parents = columns.parents.map(cell).unwrap_or_default();
+        let parents = parents.split(self.mapping.parents_delimiter.as_str()).map(str::trim);
+        let parents = parents.filter(|parent| !parent.is_empty()).map(uid_json);
+        let entity = json!({
+            "uid": {"type": self.mapping.entity_type, "id": id},
+            "attrs": attrs,
+            "parents": parents.collect::<Result<Vec<Value>, String>>()?,
+        });
+        from_value(entity).map_err(|err| err.to_string())
+    }
+}
+
+#[async_trait]
+impl<R: AsyncBufRead + Unpin + Send> EntityReader for CsvReader<R> {
+    async fn next_chunk(&mut self) -> Result<Option<schemas::Entities>, DataImportError> {
+        let columns = match self.columns.take() {
+            Some(columns) => columns,
+            None => match self.next_row().await? {
+                Some(header) => self.columns(&header)?,
+                None => return Ok(None),
+            },
+        };
+        let mut entities = Vec::new();
+        while entities.len() < IMPORT_CHUNK {
+            let row = match self.next_row().await? {
+                Some(row) => row,
+                None => break,
+            };
+            if row.iter().all(|cell| cell.trim().is_empty()) {
+                continue;
+            }
+            let entity = self.entity(&columns, &row).map_err(|err| DataImportError::InvalidRow(self.row, err))?;
+            entities.push(entity);
+        }
+        self.columns = Some(columns);
+        Ok(Some(entities).filter(|entities| !entities.is_empty()).map(schemas::Entities::from))
+    }
+}
+
+/// The entities as NDJSON, one entity per line.
+pub fn to_ndjson(entities: &schemas::Entities) -> Vec<u8> {
+    let mut ndjson = Vec::new();
+    for entity in entities.iter() {
+        // Entities are JSON values, writing them can't fail.
+        to_writer(&mut ndjson, entity).unwrap();
+        ndjson.push(b'\n');
+    }
+    ndjson
+}
+
+pub async fn load_csv_mapping(path: PathBuf) -> Result<schemas::CsvMapping, Box<dyn Error>> {
+    let contents = rocket::tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|err| format!("Failed to read file {}: {}", path.display(), err))?;
+    let mapping = from_str(&contents).map_err(|err| format!("Failed to deserialize JSON: {}", err))?;
+    Ok(mapping)
+}
@@ -0,0 +1,272 @@
+use std::collections::HashSet;
+use std::ops::Bound;
+use std::str::FromStr;
+
+use cedar_policy::EntityUid;
+use cedar_policy_core::ast;
+use im::{OrdMap, Vector};
+use ref_cast::RefCast;
+
+/// The key an attribute value is indexed by, its Cedar representation.
+fn value_key(value: &ast::RestrictedExpr) -> String {
+    value.to_string()
+}
+
+/// The key of a queried value written in Cedar syntax, anything else is taken as a string.
+pub fn query_value_key(value: &str) -> String {
+    match ast::RestrictedExpr::from_str(value) {
+        Ok(value) => value_key(&value),
+        Err(_) => value_key(&ast::RestrictedExpr::val(value)),
+    }
+}
+
+/// The sets of the indexes hold entities by slot rather than by uid, most of them hold a single entity.
+type Slot = u32;
+
+/// The size up to which a set of slots is kept in a vector.
+const FEW_SLOTS: usize = 32;
+
+/// A set of slots, kept in a vector while it is small and in a persistent set once it grows, so that small sets
+/// stay compact and copies of large ones share their nodes.
+#[derive(Clone)]
+enum Slots {
+    Few(Vec<Slot>),
+    Many(im::OrdSet<Slot>),
+}
+
+impl Default for Slots {
+    fn default() -> Self {
+        Slots::Few(Vec::new())
+    }
+}
+
+impl Slots {
+    fn insert(&mut self, slot: Slot) {
+        match self {
+            Slots::Few(slots) if slots.contains(&slot) => {}
+            Slots::Few(slots) if slots.len() < FEW_SLOTS => slots.push(slot),
+            Slots::Few(slots) => *self = Slots::Many(slots.iter().cloned().chain([slot]).collect()),
+            Slots::Many(slots) => {
+                slots.insert(slot);
+            }
+        }
+    }
+
+    fn remove(&mut self, slot: &Slot) {
+        match self {
+            Slots::Few(slots) => slots.retain(|other| other != slot),
+            Slots::Many(slots) => {
+                slots.remove(slot);
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Slots::Few(slots) => slots.is_empty(),
+            Slots::Many(slots) => slots.is_empty(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Slots {
+    type Item = &'a Slot;
+    type IntoIter = Box<dyn Iterator<Item = &'a Slot> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            Slots::Few(slots) => Box::new(slots.iter()),
+            Slots::Many(slots) => Box::new(slots.iter()),
+        }
+    }
+}
+
+/// Indexes over the stored entities, used to answer queries without scanning all of them.
+/// The collections are persistent, a copy shares what it doesn't change with the index it was taken from.
+#[derive(Default, Clone)]
+pub struct EntityIndex {
+    /// The uid of the entity in every slot, the slots of removed entities are reused.
+    uids: Vector<Option<EntityUid>>,
+    slots: OrdMap<EntityUid, Slot>,
+    free: Vector<Slot>,
+    /// Every entity ordered by uid, used for pagination.
+    order: OrdMap<String, Slot>,
+    /// Entities by type name.
+    types: OrdMap<String, Slots>,
+    /// Entities by parent.
+    children: OrdMap<EntityUid, Slots>,
+    /// Entities by ancestor, inherited ancestors included.
+    descendants: OrdMap<EntityUid, Slots>,
+    /// Entities by the entities their attributes refer to.
+    referrers: OrdMap<EntityUid, Slots>,
+    /// Entities by attribute name and value.
+    attributes: OrdMap<String, OrdMap<String, Slots>>,
+}
+
+/// Entities matching an entity query filter.
+pub enum Filter<'a> {
+    Type(&'a str),
+    Ancestor(&'a EntityUid),
+    Attribute(&'a str),
+    Value(&'a str, String),
+}
+
+fn remove_from<K: Ord + Clone>(sets: &mut OrdMap<K, Slots>, key: &K, slot: Slot) {
+    if let Some(set) = sets.get_mut(key) {
+        set.remove(&slot);
+        if set.is_empty() {
+            sets.remove(key);
+        }
+    }
+}
+
+impl EntityIndex {
+    /// Index an entity given with all of its ancestors, the parents it declares, the names of its attributes
+    /// and the entities they refer to.
+    pub fn insert(
+        &mut self,
+        uid: &EntityUid,
+        entity: &ast::Entity,
+        parents: &[ast::EntityUID],
+        attributes: &[String],
+        references: &[(String, EntityUid)],
+    ) {
+        let slot = match self.free.pop_back() {
+            Some(slot) => {
+                self.uids.set(slot as usize, Some(uid.clone()));
+                slot
+            }
+            None => {
+                self.uids.push_back(Some(uid.clone()));
+                (self.uids.len() - 1) as Slot
+            }
+        };
+        self.slots.insert(uid.clone(), slot);
+        self.order.insert(uid.to_string(), slot);
+        self.types.entry(uid.type_name().to_string()).or_default().insert(slot);
+        for parent in parents {
+            let parent = EntityUid::ref_cast(parent).clone();
+            self.children.entry(parent).or_default().insert(slot);
+        }
+        for ancestor in entity.ancestors() {
+            let ancestor = EntityUid::ref_cast(ancestor).clone();
+            self.descendants.entry(ancestor).or_default().insert(slot);
+        }
+        for (_, referenced) in references {
+            self.referrers.entry(referenced.clone()).or_default().insert(slot);
+        }
+        for attribute in attributes {
+            if let Some(value) = entity.get(attribute) {
+                self.attributes
+                    .entry(attribute.clone())
+                    .or_default()
+                    .entry(value_key(value))
+                    .or_default()
+                    .insert(slot);
+            }
+        }
+    }
+
+    /// Remove an entity, given as it was indexed.
+    pub fn remove(
+        &mut self,
+        uid: &EntityUid,
+        entity: &ast::Entity,
+        parents: &[ast::EntityUID],
+        attributes: &[String],
+        references: &[(String, EntityUid)],
+    ) {
+        let slot = match self.slots.remove(uid) {
+            Some(slot) => slot,
+            None => return,
+        };
+        self.uids.set(slot as usize, None);
+        self.free.push_back(slot);
+        self.order.remove(&uid.to_string());
+        remove_from(&mut self.types, &uid.type_name().to_string(), slot);
+        for parent in parents {
+            remove_from(&mut self.children, EntityUid::ref_cast(parent), slot);
+        }
+        for ancestor in entity.ancestors() {
+            remove_from(&mut self.descendants, EntityUid::ref_cast(ancestor), slot);
+        }
+        for (_, referenced) in references {
+            remove_from(&mut self.referrers, referenced, slot);
+        }
+        for attribute in attributes {
+            if let (Some(value), Some(values)) = (entity.get(attribute), self.attributes.get_mut(attribute)) {
+                remove_from(values, &value_key(value), slot);
+                if values.is_empty() {
+                    self.attributes.remove(attribute);
+                }
+            }
+        }
+    }
+
+    fn uid(&self, slot: &Slot) -> &EntityUid {
+        self.uids[*slot as usize].as_ref().unwrap()
+    }
+
+    fn uids<'a>(&'a self, slots: Option<&'a Slots>) -> impl Iterator<Item = &'a EntityUid> {
+        slots.into_iter().flatten().map(|slot| self.uid(slot))
+    }
+
+    pub fn children(&self, uid: &EntityUid) -> impl Iterator<Item = &EntityUid> {
+        self.uids(self.children.get(uid))
+    }
+
+    pub fn descendants(&self, uid: &EntityUid) -> impl Iterator<Item = &EntityUid> {
+        self.uids(self.descendants.get(uid))
+    }
+
+    /// The entities whose attributes refer to `uid`.
+    pub fn referrers(&self, uid: &EntityUid) -> impl Iterator<Item = &EntityUid> {
+        self.uids(self.referrers.get(uid))
+    }
+
+    fn matching(&self, filter: &Filter) -> HashSet<Slot> {
+        let slots: Box<dyn Iterator<Item = &Slot>> = match filter {
+            Filter::Type(entity_type) => Box::new(self.types.get(*entity_type).into_iter().flatten()),
+            Filter::Ancestor(ancestor) => Box::new(self.descendants.get(*ancestor).into_iter().flatten()),
+            Filter::Attribute(attribute) => {
+                Box::new(self.attributes.get(*attribute).into_iter().flat_map(|values| values.values().flatten()))
+            }
+            Filter::Value(attribute, value) => Box::new(
+                self.attributes
+                    .get(*attribute)
+                    .and_then(|values| values.get(value))
+                    .into_iter()
+                    .flatten(),
+            ),
+        };
+        slots.cloned().collect()
+    }
+
+    /// Up to `limit` entities matching every filter, ordered by uid and following `cursor`.
+    /// The second value tells whether more entities match.
+    pub fn query(&self, filters: &[Filter], cursor: Option<&str>, limit: usize) -> (Vec<&EntityUid>, bool) {
+        let mut uids: Vec<&EntityUid> = if filters.is_empty() {
+            let following = match cursor {
+                Some(cursor) => Box::new(self.order.range::<_, str>((Bound::Excluded(cursor), Bound::Unbounded)))
+                    as Box<dyn Iterator<Item = (&String, &Slot)>>,
+                None => Box::new(self.order.iter()),
+            };
+            following.map(|(_, slot)| self.uid(slot)).take(limit + 1).collect()
+        } else {
+            let mut matching: Vec<HashSet<Slot>> = filters.iter().map(|filter| self.matching(filter)).collect();
+            matching.sort_by_key(|slots| slots.len());
+            let (smallest, others) = matching.split_first().unwrap();
+            let mut uids: Vec<(String, &EntityUid)> = smallest
+                .iter()
+                .filter(|slot| others.iter().all(|other| other.contains(*slot)))
+                .map(|slot| (self.uid(slot).to_string(), self.uid(slot)))
+                .filter(|(key, _)| !matches!(cursor, Some(cursor) if key.as_str() <= cursor))
+                .collect();
+            uids.sort_by(|a, b| a.0.cmp(&b.0));
+            uids.into_iter().map(|(_, uid)| uid).take(limit + 1).collect()
+        };
+        let more = uids.len() > limit;
+        uids.truncate(limit);
+        (uids, more)
+    }
+}
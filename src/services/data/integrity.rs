@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+
+use cedar_policy::EntityUid;
+
+use crate::schemas::data::{DanglingReference, IntegrityReport};
+
+/// The references an entity holds to other entities.
+pub struct EntityLinks<'a> {
+    pub uid: &'a EntityUid,
+    /// The parents the entity declares.
+    pub parents: Vec<&'a EntityUid>,
+    /// The entities referenced by its attributes, with the attribute holding them.
+    pub references: &'a [(String, EntityUid)],
+}
+
+/// Add the references of `links` to entities for which `exists` is false to the report.
+pub fn check_references<'a>(
+    links: impl Iterator<Item = EntityLinks<'a>>,
+    exists: impl Fn(&EntityUid) -> bool,
+    report: &mut IntegrityReport,
+) {
+    for links in links {
+        for parent in links.parents.into_iter().filter(|parent| !exists(parent)) {
+            report.dangling_parents.push(DanglingReference {
+                entity: links.uid.to_string(),
+                attribute: None,
+                reference: parent.to_string(),
+            });
+        }
+        for (attribute, reference) in links.references.iter().filter(|(_, reference)| !exists(reference)) {
+            report.dangling_attributes.push(DanglingReference {
+                entity: links.uid.to_string(),
+                attribute: Some(attribute.clone()),
+                reference: reference.to_string(),
+            });
+        }
+    }
+    let key = |reference: &DanglingReference| (reference.entity.clone(), reference.reference.clone());
+    report.dangling_parents.sort_by_key(key);
+    report.dangling_attributes.sort_by_key(key);
+}
+
+/// A chain of parents reachable from one of the `start` entities that leads back to the entity it starts with.
+/// The hierarchy is walked without recursion, it may be arbitrarily deep.
+pub fn find_cycle<'a, P>(
+    start: impl Iterator<Item = &'a EntityUid>,
+    parents: impl Fn(&EntityUid) -> P,
+) -> Option<Vec<EntityUid>>
+where
+    P: Iterator<Item = &'a EntityUid>,
+{
+    // Entities whose ancestors have all been walked without finding a cycle.
+    let mut done: HashSet<&EntityUid> = HashSet::new();
+    for root in start {
+        if done.contains(root) {
+            continue;
+        }
+        let mut path = vec![(root, parents(root))];
+        let mut on_path: HashSet<&EntityUid> = HashSet::from([root]);
+        while let Some((_, next)) = path.last_mut() {
+            match next.next() {
+                Some(parent) if on_path.contains(parent) => {
+                    let start = path.iter().position(|(uid, _)| *uid == parent).unwrap();
+                    let mut cycle: Vec<EntityUid> = path[start..].iter().map(|(uid, _)| (*uid).clone()).collect();
+                    cycle.push(parent.clone());
+                    return Some(cycle);
+                }
+                Some(parent) if done.contains(parent) => {}
+                Some(parent) => {
+                    on_path.insert(parent);
+                    path.push((parent, parents(parent)));
+                }
+                None => {
+                    let (uid, _) = path.pop().unwrap();
+                    on_path.remove(uid);
+                    done.insert(uid);
+                }
+            }
+        }
+    }
+    None
+}
+
+pub fn describe_cycle(cycle: &[EntityUid]) -> String {
+    cycle.iter().map(EntityUid::to_string).collect::<Vec<String>>().join(" -> ")
+}
+
+/// A short description of the dangling references in the report, listing the first few of them.
+pub fn describe_dangling(report: &IntegrityReport) -> String {
+    const LISTED: usize = 10;
+    let references: Vec<String> = report
+        .dangling_parents
+        .iter()
+        .map(|dangling| format!("{} has missing parent {}", dangling.entity, dangling.reference))
+        .chain(report.dangling_attributes.iter().map(|dangling| {
+            format!(
+                "{} refers to missing entity {} in attribute {}",
+                dangling.entity,
+                dangling.reference,
+                dangling.attribute.as_deref().unwrap_or_default()
+            )
+        }))
+        .collect();
+    let mut description = references.iter().take(LISTED).cloned().collect::<Vec<String>>().join(", ");
+    if references.len() > LISTED {
+        description.push_str(&format!(" and {} more", references.len() - LISTED));
+    }
+    description
+}
@@ -2,12 +2,17 @@ use std::path::PathBuf;
 use std::error::Error;
 use std::fs::File;
 use std::io::Read;
+use std::sync::Arc;
 use log::{error, info};
 
 use rocket::fairing::{Fairing, Info, Kind};
 use rocket::Rocket;
 use rocket::Build;
 
+use cedar_policy_validator::ValidatorSchema;
+use rocket::tokio::io::BufReader;
+
+use crate::services::data::import::{load_csv_mapping, CsvReader, EntityReader, NdjsonReader};
 use crate::services::data::DataStore;
 use crate::services::schema::SchemaStore;
 use crate::config;
@@ -17,8 +22,8 @@ pub struct InitDataFairing;
 
 pub(crate) async fn init(
     conf: &config::Config,
-    data_store: &Box<dyn DataStore>,
-    schema_store: &Box<dyn SchemaStore>
+    data_store: &Arc<dyn DataStore>,
+    schema_store: &Arc<dyn SchemaStore>
 ) {
 
     if conf.data.is_none() {
@@ -26,6 +31,17 @@ pub(crate) async fn init(
     }
 
     let file_path = conf.data.clone().unwrap();
+    // Line delimited and CSV files are imported as they are read.
+    if matches!(file_path.extension().and_then(|extension| extension.to_str()), Some("ndjson" | "jsonl" | "csv")) {
+        let schema = schema_store.get_validator_schema().await;
+        match import_entities_from_file(file_path.clone(), conf.csv_mapping.clone(), data_store, schema).await {
+            Ok(imported) => {
+                info!("Successfully imported entities from file {}: {} entities", file_path.display(), imported);
+            }
+            Err(err) => error!("Failed to import entities from file: {}", err),
+        }
+        return;
+    }
     let entities_file_path = &file_path;
     let entities = match load_entities_from_file(entities_file_path.to_path_buf()).await {
         Ok(entities) => entities,
@@ -35,7 +51,7 @@ pub(crate) async fn init(
         }
     };
 
-    let schema = schema_store.get_cedar_schema().await;
+    let schema = schema_store.get_validator_schema().await;
     match data_store.update_entities(entities, schema).await {
         Ok(entities) => {
             info!("Successfully updated entities from file {}: {} entities", &file_path.display(), entities.len());
@@ -75,6 +91,27 @@ pub async fn load_entities_from_file(path: PathBuf) -> Result<Entities, Box<dyn
     Ok(entities)
 }
 
+/// Replace the stored entities with those of an NDJSON file, or a CSV file read with the mapping.
+pub async fn import_entities_from_file(
+    path: PathBuf,
+    csv_mapping: Option<PathBuf>,
+    data_store: &Arc<dyn DataStore>,
+    schema: Option<ValidatorSchema>,
+) -> Result<usize, Box<dyn Error>> {
+    let file = rocket::tokio::fs::File::open(&path)
+        .await
+        .map_err(|err| format!("Failed to open file {}: {}", path.display(), err))?;
+    let file = BufReader::new(file);
+    let mut reader: Box<dyn EntityReader> = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("csv") => {
+            let mapping = csv_mapping.ok_or("A CSV mapping is required to import a CSV file")?;
+            Box::new(CsvReader::new(file, load_csv_mapping(mapping).await?)?)
+        }
+        _ => Box::new(NdjsonReader::new(file)),
+    };
+    data_store.import_entities(reader.as_mut(), true, schema, None).await
+}
+
 #[async_trait::async_trait]
 impl Fairing for InitDataFairing {
     fn info(&self) -> Info {
@@ -93,8 +130,8 @@ impl Fairing for InitDataFairing {
 
         init(
             config.unwrap(),
-            rocket.state::<Box<dyn DataStore>>().unwrap(),
-            rocket.state::<Box<dyn SchemaStore>>().unwrap()
+            rocket.state::<Arc<dyn DataStore>>().unwrap(),
+            rocket.state::<Arc<dyn SchemaStore>>().unwrap()
         ).await;
 
         Ok(rocket)
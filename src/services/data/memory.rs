@@ -1,105 +1,1015 @@
-use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
 
-use async_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use async_lock::{Mutex, MutexGuard, RwLock, RwLockWriteGuard};
 use async_trait::async_trait;
-use cedar_policy::Schema;
-use cedar_policy_core::entities;
-use log::{debug, error, info};
+use cedar_policy::EntityUid;
+use cedar_policy_validator::ValidatorSchema;
+use chrono::{DateTime, Utc};
+use cedar_policy_core::ast;
+use cedar_policy_core::entities::{self, TCComputation};
+use cedar_policy_core::transitive_closure::TCNode;
+use im::OrdSet;
+use ref_cast::RefCast;
+use log::{debug, error, info, warn};
+use rocket::futures::stream::{self, BoxStream, StreamExt};
 
 use crate::schemas::data as schemas;
-use crate::services::data::DataStore;
+use crate::services::data::errors::{DataImportError, DataStoreError};
+use crate::services::data::hierarchy::MAX_PATHS;
+use crate::services::data::import::EntityReader;
+use crate::services::data::index::{query_value_key, EntityIndex, Filter};
+use crate::services::data::integrity::{check_references, describe_cycle, describe_dangling, find_cycle, EntityLinks};
 
-pub struct Entities(cedar_policy::Entities, entities::Entities);
+/// The number of entities returned by a query without a limit.
+const DEFAULT_QUERY_LIMIT: usize = 100;
+/// The number of entities serialized at once by an export.
+const EXPORT_CHUNK: usize = 1_000;
+use crate::services::data::{CandidateCheck, DataStore};
+use crate::services::staged::{StagedChange, StagedSwap};
+
+/// Entities referenced by attributes, with the attribute holding them.
+type References = Vec<(String, EntityUid)>;
+
+/// What is kept of a stored entity: its cedar form with all of its ancestors, the parents it declares,
+/// the names of its attributes which the ast doesn't expose, the entities its attributes refer to
+/// and when it expires.
+struct StoredEntity {
+    entity: ast::Entity,
+    parents: Vec<ast::EntityUID>,
+    attributes: Vec<String>,
+    references: References,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// A persistent map, a copy shares the entities it doesn't change with the map it was taken from.
+type StoredEntities = im::OrdMap<EntityUid, Arc<StoredEntity>>;
+
+/// The entity with its ancestors replaced.
+fn with_ancestors(entity: &ast::Entity, attributes: &[String], ancestors: HashSet<ast::EntityUID>) -> ast::Entity {
+    let attrs = attributes
+        .iter()
+        .filter_map(|attribute| Some((attribute.as_str().into(), entity.get(attribute)?.clone())))
+        .collect();
+    ast::Entity::new(entity.uid(), attrs, ancestors)
+}
+
+/// The parents the stored entity declares.
+fn stored_parents<'a>(stored: &'a StoredEntities, uid: &EntityUid) -> impl Iterator<Item = &'a EntityUid> {
+    let entity = stored.get(uid).into_iter();
+    entity.flat_map(|stored| stored.parents.iter()).map(EntityUid::ref_cast)
+}
+
+fn entity_links<'a>(
+    stored: &'a StoredEntities,
+    uid: &'a EntityUid,
+    entity: &'a StoredEntity,
+) -> EntityLinks<'a> {
+    EntityLinks {
+        uid,
+        parents: stored_parents(stored, uid).collect(),
+        references: &entity.references,
+    }
+}
+
+/// The actions declared by the schema, they are known entities even when not stored.
+fn schema_actions(schema: &Option<ValidatorSchema>) -> HashSet<EntityUid> {
+    let actions = schema.as_ref().and_then(|schema| schema.action_entities().ok());
+    let actions = actions.into_iter().flat_map(entities::Entities::into_iter);
+    actions.map(|action| EntityUid::ref_cast(&action.uid()).clone()).collect()
+}
+
+/// The entities as evaluated by cedar, given with all of their ancestors.
+fn cedar_entities(entities: impl IntoIterator<Item = ast::Entity>) -> cedar_policy::Entities {
+    // The ancestors are already complete, the hierarchy isn't walked again and can't be found invalid.
+    let entities = entities::Entities::from_entities(entities, TCComputation::AssumeAlreadyComputed).unwrap();
+    cedar_policy::Entities::ref_cast(&entities).clone()
+}
+
+/// A snapshot of the stored entities. Its collections are persistent, a change copies only what it touches.
+pub struct Entities {
+    stored: StoredEntities,
+    index: EntityIndex,
+    /// The entities having an expiry, soonest first.
+    expiries: OrdSet<(DateTime<Utc>, EntityUid)>,
+    /// The uids of the entities of every partition, by partition name.
+    partitions: HashMap<String, Arc<HashSet<EntityUid>>>,
+    /// Incremented on every change.
+    revision: u64,
+}
 
 impl Entities {
     fn empty() -> Self {
         Self {
-            0: cedar_policy::Entities::empty(),
-            1: entities::Entities::new(),
+            stored: StoredEntities::new(),
+            index: EntityIndex::default(),
+            expiries: OrdSet::new(),
+            partitions: HashMap::new(),
+            revision: 0,
         }
     }
 
+    /// All the stored entities as evaluated by cedar.
     fn cedar_entities(&self) -> cedar_policy::Entities {
-        self.0.clone()
+        cedar_entities(self.stored.values().map(|stored| stored.entity.clone()))
+    }
+
+    /// The stored entities reachable from the `roots`: the roots and the entities their attributes refer to,
+    /// transitively. Entities carry all of their ancestors, the entities holding them aren't needed.
+    fn reachable(&self, roots: &[EntityUid]) -> cedar_policy::Entities {
+        let mut visited: HashSet<&EntityUid> = HashSet::new();
+        let mut pending: Vec<&EntityUid> = roots.iter().collect();
+        let mut entities = Vec::new();
+        while let Some(uid) = pending.pop() {
+            if !visited.insert(uid) {
+                continue;
+            }
+            if let Some(stored) = self.stored.get(uid) {
+                entities.push(stored.entity.clone());
+                pending.extend(stored.references.iter().map(|(_, reference)| reference));
+            }
+        }
+        cedar_entities(entities)
+    }
+
+    /// The stored entity with all of its ancestors.
+    fn entity(&self, uid: &EntityUid) -> Option<&ast::Entity> {
+        self.stored.get(uid).map(|stored| &stored.entity)
+    }
+
+    /// The entity in JSON form, with its expiry when it has one.
+    fn json(&self, entity: &ast::Entity) -> schemas::Entity {
+        let mut json = schemas::Entity::from(entity);
+        if !self.expiries.is_empty() {
+            let expires_at = self.stored.get(EntityUid::ref_cast(&entity.uid())).and_then(|stored| stored.expires_at);
+            expires_at.into_iter().for_each(|expires_at| json.set_expires_at(expires_at));
+        }
+        json
     }
 
-    #[allow(dead_code)]
-    fn core_entities(&self) -> entities::Entities {
-        self.1.clone()
+    /// Every entity in JSON form.
+    fn export(&self) -> schemas::Entities {
+        self.stored.values().map(|stored| self.json(&stored.entity)).collect::<Vec<schemas::Entity>>().into()
     }
 
-    fn new(cedar_entities: cedar_policy::Entities, core_entities: entities::Entities) -> Self {
+    /// The stored entity in JSON form with the parents it declares.
+    fn declared(&self, uid: &EntityUid) -> Option<schemas::Entity> {
+        let stored = self.stored.get(uid)?;
+        let parents = stored.parents.iter().cloned().collect();
+        Some(self.json(&with_ancestors(self.entity(uid)?, &stored.attributes, parents)))
+    }
+
+    /// Whether an entity expired at `now`.
+    fn has_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expiries.get_min().is_some_and(|(expires_at, _)| *expires_at <= now)
+    }
+
+    /// The entities expired at `now`.
+    fn expired(&self, now: DateTime<Utc>) -> Vec<EntityUid> {
+        let expired = self.expiries.iter().take_while(|(expires_at, _)| *expires_at <= now);
+        expired.map(|(_, uid)| uid.clone()).collect()
+    }
+
+    /// The ancestors of the `affected` entities in `stored`, inherited from the parents they declare.
+    /// The other entities keep the ancestors they have here, `affected` has to include their descendants.
+    fn close(
+        &self,
+        stored: &StoredEntities,
+        affected: &HashSet<EntityUid>,
+    ) -> HashMap<EntityUid, HashSet<ast::EntityUID>> {
+        let mut closed: HashMap<EntityUid, HashSet<ast::EntityUID>> = HashMap::new();
+        let mut visited: HashSet<&EntityUid> = HashSet::new();
+        for root in affected.iter().filter(|uid| stored.contains_key(*uid)) {
+            // The hierarchy is walked without recursion, parents are closed before their children.
+            let mut stack = vec![(root, false)];
+            while let Some((uid, expanded)) = stack.pop() {
+                if closed.contains_key(uid) {
+                    continue;
+                }
+                let entity = &stored[uid];
+                let parents = entity.parents.iter().map(EntityUid::ref_cast);
+                if !expanded {
+                    visited.insert(uid);
+                    stack.push((uid, true));
+                    let pending = parents.filter(|parent| affected.contains(*parent) && stored.contains_key(*parent));
+                    stack.extend(pending.filter(|parent| !visited.contains(parent)).map(|parent| (parent, false)));
+                    continue;
+                }
+                let mut ancestors: HashSet<ast::EntityUID> = entity.parents.iter().cloned().collect();
+                for parent in parents {
+                    if let Some(inherited) = closed.get(parent) {
+                        ancestors.extend(inherited.iter().cloned());
+                    } else if let Some(parent) = self.entity(parent).filter(|_| stored.contains_key(parent)) {
+                        ancestors.extend(parent.ancestors().cloned());
+                    }
+                }
+                closed.insert(uid.clone(), ancestors);
+            }
+        }
+        closed
+    }
+
+    /// These entities changed to `stored`, which holds the upserted entities parsed with the parents they declare.
+    /// `affected` holds the upserted and removed entities and their descendants, only their ancestors are
+    /// computed again and only they are indexed again. The other entities are shared with these entities.
+    fn changed(&self, mut stored: StoredEntities, affected: &HashSet<EntityUid>) -> Self {
+        let mut index = self.index.clone();
+        let mut expiries = self.expiries.clone();
+        for uid in affected {
+            if let Some(previous) = self.stored.get(uid) {
+                let references = &previous.references;
+                index.remove(uid, &previous.entity, &previous.parents, &previous.attributes, references);
+                if let Some(expires_at) = previous.expires_at {
+                    expiries.remove(&(expires_at, uid.clone()));
+                }
+            }
+            if let Some(expires_at) = stored.get(uid).and_then(|stored| stored.expires_at) {
+                expiries.insert((expires_at, uid.clone()));
+            }
+        }
+        for (uid, ancestors) in self.close(&stored, affected) {
+            let entry = match stored.get_mut(&uid) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            match Arc::get_mut(entry) {
+                // Only freshly parsed entities aren't shared, they just have the parents they declare.
+                Some(parsed) => ancestors.into_iter().for_each(|ancestor| parsed.entity.add_edge_to(ancestor)),
+                None => {
+                    *entry = Arc::new(StoredEntity {
+                        entity: with_ancestors(&entry.entity, &entry.attributes, ancestors),
+                        parents: entry.parents.clone(),
+                        attributes: entry.attributes.clone(),
+                        references: entry.references.clone(),
+                        expires_at: entry.expires_at,
+                    })
+                }
+            }
+            index.insert(&uid, &entry.entity, &entry.parents, &entry.attributes, &entry.references);
+        }
         Self {
-            0: cedar_entities,
-            1: core_entities,
+            stored,
+            index,
+            expiries,
+            partitions: self.partitions.clone(),
+            revision: 0,
+        }
+    }
+
+    /// These entities without the `removed` ones.
+    fn without(&self, removed: &[EntityUid]) -> Self {
+        let mut stored = self.stored.clone();
+        for uid in removed {
+            stored.remove(uid);
+        }
+        let mut affected: HashSet<EntityUid> = removed.iter().cloned().collect();
+        affected.extend(removed.iter().flat_map(|uid| self.index.descendants(uid)).cloned());
+        self.changed(stored, &affected)
+    }
+
+    /// The stored entities of the partition missing from `uids`.
+    fn left_out(&self, partition: &str, uids: &HashSet<EntityUid>) -> Vec<EntityUid> {
+        let owned = self.partitions.get(partition).into_iter().flat_map(|owned| owned.iter());
+        owned.filter(|uid| !uids.contains(*uid) && self.stored.contains_key(*uid)).cloned().collect()
+    }
+
+    /// Give the `uids` to the partition, the other partitions holding some of them lose them.
+    fn set_partition(&mut self, partition: &str, uids: HashSet<EntityUid>) {
+        for (name, owned) in self.partitions.iter_mut() {
+            if name != partition && owned.iter().any(|uid| uids.contains(uid)) {
+                *owned = Arc::new(owned.difference(&uids).cloned().collect());
+            }
+        }
+        self.partitions.insert(partition.to_string(), Arc::new(uids));
+    }
+
+    /// The parents the stored entity declares.
+    fn parents(&self, uid: &EntityUid) -> Vec<EntityUid> {
+        let mut parents: Vec<EntityUid> = stored_parents(&self.stored, uid).cloned().collect();
+        parents.sort_by_key(|parent| parent.to_string());
+        parents
+    }
+
+    fn ancestors(&self, uid: &EntityUid) -> Vec<EntityUid> {
+        let ancestors = self.entity(uid).into_iter().flat_map(ast::Entity::ancestors);
+        ancestors.map(|ancestor| EntityUid::ref_cast(ancestor).clone()).collect()
+    }
+
+    /// Extend `path` with every chain of parents leading to `to`.
+    fn collect_paths(&self, to: &EntityUid, path: &mut Vec<EntityUid>, paths: &mut Vec<Vec<EntityUid>>) {
+        let last = path.last().unwrap().clone();
+        if &last == to {
+            paths.push(path.clone());
+            return;
+        }
+        for parent in self.parents(&last) {
+            if paths.len() >= MAX_PATHS {
+                return;
+            }
+            // Only parents leading to `to` are followed.
+            let leads_to =
+                |entity: &ast::Entity| entity.ancestors().any(|ancestor| EntityUid::ref_cast(ancestor) == to);
+            if &parent == to || self.entity(&parent).is_some_and(leads_to) {
+                path.push(parent);
+                self.collect_paths(to, path, paths);
+                path.pop();
+            }
         }
     }
 }
 
+fn uid_strings<'a>(uids: impl IntoIterator<Item = &'a EntityUid>) -> Vec<String> {
+    let mut uids: Vec<String> = uids.into_iter().map(EntityUid::to_string).collect();
+    uids.sort();
+    uids
+}
+
+/// Entities keyed by uid, parsed with just the parents they declare as ancestors.
+type ParsedEntities = HashMap<EntityUid, StoredEntity>;
+
+/// The entities referenced by the attributes, nested in sets and records included, with the attribute holding them.
+fn entity_references(entity: &ast::Entity, attributes: &[String]) -> References {
+    let mut references = Vec::new();
+    for attribute in attributes {
+        let values = entity.get(attribute).into_iter().flat_map(|value| value.subexpressions());
+        references.extend(values.filter_map(|value| match value.expr_kind() {
+            ast::ExprKind::Lit(ast::Literal::EntityUID(uid)) => {
+                Some((attribute.clone(), EntityUid::ref_cast(uid.as_ref()).clone()))
+            }
+            _ => None,
+        }));
+    }
+    references
+}
+
+/// The attribute names of an entity, the parents of an action parsed with all of its ancestors, and its expiry.
+type Declared = (Vec<String>, Option<Vec<EntityUid>>, Option<DateTime<Utc>>);
+
+/// Parse the entities against the schema, their `ttl` counts from now.
+fn parse_entities(
+    entities: schemas::Entities,
+    schema: &Option<ValidatorSchema>,
+) -> Result<ParsedEntities, Box<dyn Error>> {
+    let now = Utc::now();
+    // What the ast doesn't keep is taken from the JSON before it is parsed.
+    let mut declared: HashMap<EntityUid, Declared> = HashMap::with_capacity(entities.len());
+    for entity in entities.iter() {
+        if let Some(uid) = entity.uid() {
+            let invalid = |err| DataStoreError::InvalidExpiry(format!("entity {}: {}", uid, err));
+            let expires_at = entity.expires_at(now).map_err(invalid)?;
+            // Actions are parsed with all of their ancestors, the parents they declare are kept aside.
+            let parents = Some(entity.parents()).filter(|_| schema.is_some() && entity.is_action());
+            declared.insert(uid, (entity.attribute_names(), parents, expires_at));
+        }
+    }
+    let parsed = entities.into_declared_entities(schema.as_ref())?;
+    Ok(parsed
+        .into_iter()
+        .map(|entity| {
+            let uid = EntityUid::ref_cast(&entity.uid()).clone();
+            let (attributes, parents, expires_at) = declared.remove(&uid).unwrap_or_default();
+            let ancestors = entity.ancestors().filter(|ancestor| match &parents {
+                Some(parents) => parents.contains(EntityUid::ref_cast(*ancestor)),
+                None => true,
+            });
+            let parents = ancestors.cloned().collect();
+            let stored = StoredEntity {
+                parents,
+                references: entity_references(&entity, &attributes),
+                attributes,
+                expires_at,
+                entity,
+            };
+            (uid, stored)
+        })
+        .collect())
+}
+
 pub struct MemoryDataStore {
-    entities: RwLock<Entities>,
+    /// Replaced as a whole on every change, readers keep using the entities they took.
+    entities: RwLock<Arc<Entities>>,
+    /// Serializes writers, the entities are recomputed without holding the write lock.
+    writer: Mutex<()>,
+    max_entities: Option<usize>,
+    integrity: schemas::IntegrityMode,
 }
 
 impl MemoryDataStore {
     pub fn new() -> Self {
         Self {
-            entities: RwLock::new(Entities::empty()),
+            entities: RwLock::new(Arc::new(Entities::empty())),
+            writer: Mutex::new(()),
+            max_entities: None,
+            integrity: schemas::IntegrityMode::default(),
         }
     }
 
-    async fn read(&self) -> RwLockReadGuard<Entities> {
+    /// Reject changes that would store more than `max_entities` entities.
+    pub fn with_max_entities(mut self, max_entities: Option<usize>) -> Self {
+        self.max_entities = max_entities;
+        self
+    }
+
+    /// Choose whether changes leaving dangling entity references are rejected or logged.
+    pub fn with_integrity(mut self, integrity: schemas::IntegrityMode) -> Self {
+        self.integrity = integrity;
+        self
+    }
+
+    /// The current entities, the lock is only held while taking them.
+    async fn read(&self) -> Arc<Entities> {
         debug!("Trying to acquire read lock on entities");
-        self.entities.read().await
+        self.entities.read().await.clone()
     }
 
-    async fn write(&self) -> RwLockWriteGuard<Entities> {
+    /// The current entities, the expired ones are removed first when the sweep didn't remove them yet.
+    async fn live(&self) -> Arc<Entities> {
+        let current = self.read().await;
+        if !current.has_expired(Utc::now()) {
+            return current;
+        }
+        let _writer = self.writer().await;
+        self.read().await
+    }
+
+    /// Remove the entities expired at `now`, the writer lock has to be held.
+    async fn remove_expired(&self, now: DateTime<Utc>) -> Result<usize, Box<dyn Error>> {
+        let current = self.read().await;
+        let expired = current.expired(now);
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        info!("Removing {} expired entities", expired.len());
+        let changed = current.without(&expired);
+        drop(current);
+        self.replace(changed).await;
+        Ok(expired.len())
+    }
+
+    async fn write(&self) -> RwLockWriteGuard<'_, Arc<Entities>> {
         debug!("Trying to acquire write lock on entities");
         self.entities.write().await
     }
+
+    /// Serialize a change, the expired entities are removed first so that it applies to the live ones.
+    async fn writer(&self) -> MutexGuard<'_, ()> {
+        let writer = self.writer.lock().await;
+        if let Err(err) = self.remove_expired(Utc::now()).await {
+            error!("Failed to remove expired entities: {}", err);
+        }
+        writer
+    }
+
+    fn check_quota(&self, count: usize) -> Result<(), DataStoreError> {
+        match self.max_entities {
+            Some(max) if count > max => Err(DataStoreError::QuotaExceeded(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reject a change making an entity its own ancestor, and check the references it leaves dangling:
+    /// those of the upserted entities and those of the other entities to the removed ones.
+    fn check_change(
+        &self,
+        stored: &StoredEntities,
+        upserted: &HashSet<EntityUid>,
+        removed: &[EntityUid],
+        index: &EntityIndex,
+        schema: &Option<ValidatorSchema>,
+    ) -> Result<(), DataStoreError> {
+        let upserted_links = || upserted.iter().filter_map(|uid| stored.get_key_value(uid));
+        let start = upserted_links().map(|(uid, _)| uid);
+        if let Some(cycle) = find_cycle(start, |uid| stored_parents(stored, uid)) {
+            return Err(DataStoreError::Cycle(describe_cycle(&cycle)));
+        }
+
+        let mut report = schemas::IntegrityReport::default();
+        let actions = schema_actions(schema);
+        check_references(
+            upserted_links().map(|(uid, entity)| entity_links(stored, uid, entity)),
+            |uid| stored.contains_key(uid) || actions.contains(uid),
+            &mut report,
+        );
+        let removed: HashSet<&EntityUid> = removed.iter().filter(|uid| !stored.contains_key(*uid)).collect();
+        if !removed.is_empty() {
+            // Only the entities declaring a removed entity as parent or referring to it can be left dangling,
+            // `index` holds them as they were before the change.
+            let linked = removed.iter().flat_map(|uid| index.children(uid).chain(index.referrers(uid)));
+            let others: HashSet<&EntityUid> = linked.filter(|uid| !upserted.contains(*uid)).collect();
+            let others = others.into_iter().filter_map(|uid| stored.get_key_value(uid));
+            check_references(
+                others.map(|(uid, entity)| entity_links(stored, uid, entity)),
+                |uid| !removed.contains(uid),
+                &mut report,
+            );
+        }
+        if report.is_empty() {
+            return Ok(());
+        }
+        let description = describe_dangling(&report);
+        match self.integrity {
+            schemas::IntegrityMode::Reject => Err(DataStoreError::IntegrityViolation(description)),
+            schemas::IntegrityMode::Warn => {
+                warn!("Accepting dangling entity references: {}", description);
+                Ok(())
+            }
+        }
+    }
+
+    /// The stored entities with `upserted` added and `removed` left out.
+    async fn changed(
+        &self,
+        upserted: ParsedEntities,
+        removed: &[EntityUid],
+        schema: &Option<ValidatorSchema>,
+    ) -> Result<Entities, Box<dyn Error>> {
+        let current = self.read().await;
+        let mut stored = current.stored.clone();
+        for uid in removed {
+            stored.remove(uid);
+        }
+        let upserted_uids: HashSet<EntityUid> = upserted.keys().cloned().collect();
+        stored.extend(upserted.into_iter().map(|(uid, entity)| (uid, Arc::new(entity))));
+        self.check_quota(stored.len())?;
+        self.check_change(&stored, &upserted_uids, removed, &current.index, schema)?;
+        // Entities inherit ancestors through the changed ones, they have to be closed again as well.
+        let mut affected: HashSet<EntityUid> = upserted_uids.into_iter().chain(removed.iter().cloned()).collect();
+        let descendants: Vec<EntityUid> =
+            affected.iter().flat_map(|uid| current.index.descendants(uid)).cloned().collect();
+        affected.extend(descendants);
+        Ok(current.changed(stored, &affected))
+    }
+
+    /// The parsed entities replacing all the stored ones.
+    fn replaced(&self, parsed: ParsedEntities, schema: &Option<ValidatorSchema>) -> Result<Entities, Box<dyn Error>> {
+        let stored: StoredEntities = parsed.into_iter().map(|(uid, entity)| (uid, Arc::new(entity))).collect();
+        let uids: HashSet<EntityUid> = stored.keys().cloned().collect();
+        self.check_change(&stored, &uids, &[], &EntityIndex::default(), schema)?;
+        Ok(Entities::empty().changed(stored, &uids))
+    }
+
+    /// The entities replacing all the stored ones.
+    fn replacing(
+        &self,
+        entities: schemas::Entities,
+        schema: &Option<ValidatorSchema>,
+    ) -> Result<Entities, Box<dyn Error>> {
+        self.check_quota(entities.len())?;
+        match parse_entities(entities, schema).and_then(|parsed| self.replaced(parsed, schema)) {
+            Ok(entities) => Ok(entities),
+            Err(err) => {
+                error!("Failed to parse entities");
+                Err(err)
+            }
+        }
+    }
+
+    /// Swap in the new entities, the replaced ones are dropped after the lock is released.
+    async fn replace(&self, mut entities: Entities) -> Arc<Entities> {
+        let mut lock = self.write().await;
+        entities.revision = lock.revision + 1;
+        std::mem::replace(&mut *lock, Arc::new(entities))
+    }
+
+    /// Apply the operations to the JSON form of the entities they touch.
+    /// Removed entities are mapped to `None`, entities that aren't touched are left out.
+    async fn operate(
+        &self,
+        operations: Vec<schemas::EntityOperation>,
+    ) -> Result<HashMap<EntityUid, Option<schemas::Entity>>, DataStoreError> {
+        let current = self.read().await;
+        let mut touched: HashMap<EntityUid, Option<schemas::Entity>> = HashMap::new();
+        for (index, operation) in operations.into_iter().enumerate() {
+            let failed = |reason: String| DataStoreError::EntityOperationFailed(index, reason);
+            let uid = |reference: &schemas::EntityReference| {
+                EntityUid::try_from(reference).map_err(|err| failed(err.to_string()))
+            };
+            let current = |touched: &HashMap<EntityUid, Option<schemas::Entity>>, uid: &EntityUid| match touched.get(uid) {
+                Some(entity) => entity.clone(),
+                None => current.declared(uid),
+            };
+            let existing = |touched: &HashMap<EntityUid, Option<schemas::Entity>>, uid: &EntityUid| {
+                current(touched, uid).ok_or_else(|| failed(format!("entity {} does not exist", uid)))
+            };
+            match operation {
+                schemas::EntityOperation::Add { entity } => {
+                    let uid = entity.uid().ok_or_else(|| failed("entity has no uid".to_string()))?;
+                    if current(&touched, &uid).is_some() {
+                        return Err(failed(format!("entity {} already exists", uid)));
+                    }
+                    touched.insert(uid, Some(entity));
+                }
+                schemas::EntityOperation::Replace { entity } => {
+                    let uid = entity.uid().ok_or_else(|| failed("entity has no uid".to_string()))?;
+                    existing(&touched, &uid)?;
+                    touched.insert(uid, Some(entity));
+                }
+                schemas::EntityOperation::Remove { uid: reference } => {
+                    let uid = uid(&reference)?;
+                    existing(&touched, &uid)?;
+                    touched.insert(uid, None);
+                }
+                schemas::EntityOperation::SetAttribute { uid: reference, attribute, value } => {
+                    let uid = uid(&reference)?;
+                    let mut entity = existing(&touched, &uid)?;
+                    entity.set_attribute(attribute, value);
+                    touched.insert(uid, Some(entity));
+                }
+                schemas::EntityOperation::UnsetAttribute { uid: reference, attribute } => {
+                    let uid = uid(&reference)?;
+                    let mut entity = existing(&touched, &uid)?;
+                    if !entity.unset_attribute(&attribute) {
+                        return Err(failed(format!("entity {} has no attribute {}", uid, attribute)));
+                    }
+                    touched.insert(uid, Some(entity));
+                }
+                schemas::EntityOperation::AddParent { uid: reference, parent } => {
+                    let uid = uid(&reference)?;
+                    let mut entity = existing(&touched, &uid)?;
+                    entity.add_parent(&parent).map_err(|err| failed(err.to_string()))?;
+                    touched.insert(uid, Some(entity));
+                }
+                schemas::EntityOperation::RemoveParent { uid: reference, parent } => {
+                    let (uid, parent_uid) = (uid(&reference)?, uid(&parent)?);
+                    let mut entity = existing(&touched, &uid)?;
+                    if !entity.remove_parent(&parent).map_err(|err| failed(err.to_string()))? {
+                        return Err(failed(format!("entity {} has no parent {}", uid, parent_uid)));
+                    }
+                    touched.insert(uid, Some(entity));
+                }
+            }
+        }
+        Ok(touched)
+    }
+
+    /// The stored entities with the operations applied.
+    async fn operated(
+        &self,
+        operations: Vec<schemas::EntityOperation>,
+        schema: &Option<ValidatorSchema>,
+    ) -> Result<Entities, Box<dyn Error>> {
+        // The operations are applied to copies, a failing operation leaves the stored entities untouched.
+        let touched = self.operate(operations).await?;
+        let mut removed = Vec::new();
+        let mut upserted = Vec::new();
+        for (uid, entity) in touched {
+            match entity {
+                Some(entity) => upserted.push(entity),
+                None => removed.push(uid),
+            }
+        }
+        let upserted = parse_entities(upserted.into(), schema)?;
+        self.changed(upserted, &removed, schema).await
+    }
 }
 
 #[async_trait]
 impl DataStore for MemoryDataStore {
     async fn entities(&self) -> cedar_policy::Entities {
-        let lock = self.read().await;
-        lock.cedar_entities()
+        let current = self.live().await;
+        current.cedar_entities()
+    }
+
+    async fn reachable_entities(&self, roots: &[EntityUid]) -> cedar_policy::Entities {
+        let current = self.live().await;
+        current.reachable(roots)
     }
 
     async fn get_entities(&self) -> schemas::Entities {
         info!("Getting stored entities");
-        let lock = self.read().await;
-        schemas::Entities::from(lock.1.clone())
+        let current = self.live().await;
+        current.export()
+    }
+
+    async fn count_entities(&self) -> usize {
+        self.live().await.stored.len()
     }
 
     async fn delete_entities(&self) {
         info!("Deleting stored entities");
-        let mut lock = self.write().await;
-        *lock = Entities::empty();
+        let _writer = self.writer().await;
+        self.replace(Entities::empty()).await;
     }
 
     async fn update_entities(
         &self,
         entities: schemas::Entities,
-        schema: Option<Schema>,
+        schema: Option<ValidatorSchema>,
     ) -> Result<schemas::Entities, Box<dyn Error>> {
         info!("Updating stored entities");
-        let mut lock = self.write().await;
-        let core_entities: entities::Entities = match entities.try_into() {
-            Ok(entities) => entities,
-            Err(err) => {
-                return {
-                    error!("Failed to parse entities");
-                    Err(err.into())
-                }
+        let _writer = self.writer().await;
+        let entities = self.replacing(entities, &schema)?;
+        let schema_entities = entities.export();
+        self.replace(entities).await;
+        Ok(schema_entities)
+    }
+
+    async fn stage_entities(
+        &self,
+        entities: schemas::Entities,
+        schema: Option<ValidatorSchema>,
+    ) -> Result<(schemas::Entities, Box<dyn StagedChange + '_>), Box<dyn Error>> {
+        info!("Staging entities");
+        let mut entities = self.replacing(entities, &schema)?;
+        let schema_entities = entities.export();
+        let staged = StagedSwap::new(&self.entities, Some(&self.writer), move |stored: &mut Arc<Entities>| {
+            entities.revision = stored.revision + 1;
+            *stored = Arc::new(entities);
+        });
+        Ok((schema_entities, Box::new(staged)))
+    }
+
+    async fn get_entity(&self, uid: &EntityUid) -> Option<schemas::Entity> {
+        info!("Getting stored entity {}", uid);
+        let current = self.live().await;
+        current.entity(uid).map(|entity| current.json(entity))
+    }
+
+    async fn query_entities(&self, query: &schemas::EntityQuery) -> Result<schemas::EntityPage, Box<dyn Error>> {
+        info!("Querying stored entities");
+        let ancestor = match &query.ancestor {
+            Some(ancestor) => Some(
+                EntityUid::from_str(ancestor)
+                    .map_err(|err| DataStoreError::InvalidQuery(format!("invalid ancestor {}: {}", ancestor, err)))?,
+            ),
+            None => None,
+        };
+        let mut filters = Vec::new();
+        if let Some(entity_type) = &query.entity_type {
+            filters.push(Filter::Type(entity_type));
+        }
+        if let Some(ancestor) = &ancestor {
+            filters.push(Filter::Ancestor(ancestor));
+        }
+        match (&query.attribute, &query.value) {
+            (Some(attribute), Some(value)) => filters.push(Filter::Value(attribute, query_value_key(value))),
+            (Some(attribute), None) => filters.push(Filter::Attribute(attribute)),
+            (None, Some(_)) => {
+                return Err(DataStoreError::InvalidQuery("value requires an attribute".to_string()).into())
             }
+            (None, None) => {}
+        }
+        let fields: Option<Vec<&str>> = query.fields.as_ref().map(|fields| fields.split(',').map(str::trim).collect());
+
+        let current = self.live().await;
+        let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+        let (uids, more) = current.index.query(&filters, query.cursor.as_deref(), limit);
+        let next_cursor = match uids.last() {
+            Some(uid) if more => Some(uid.to_string()),
+            _ => None,
         };
-        let schema_entities: schemas::Entities = core_entities.clone().into();
-        let cedar_entities: cedar_policy::Entities = match schema_entities.borrow().convert_to_cedar_entities(&schema) {
-            Ok(entities) => entities,
-            Err(err) => return Err(err.into()),
+        let entities: Vec<schemas::Entity> = uids
+            .into_iter()
+            .filter_map(|uid| current.entity(uid))
+            .map(|entity| {
+                let mut entity = current.json(entity);
+                if let Some(fields) = &fields {
+                    entity.project(fields);
+                }
+                entity
+            })
+            .collect();
+        Ok(schemas::EntityPage {
+            entities: entities.into(),
+            next_cursor,
+        })
+    }
+
+    async fn entity_ancestors(&self, uid: &EntityUid) -> Option<schemas::EntityRelations> {
+        let current = self.live().await;
+        current.stored.get(uid)?;
+        Some(schemas::EntityRelations {
+            uid: uid.to_string(),
+            direct: uid_strings(&current.parents(uid)),
+            transitive: uid_strings(&current.ancestors(uid)),
+        })
+    }
+
+    async fn entity_descendants(&self, uid: &EntityUid) -> Option<schemas::EntityRelations> {
+        let current = self.live().await;
+        current.stored.get(uid)?;
+        Some(schemas::EntityRelations {
+            uid: uid.to_string(),
+            direct: uid_strings(current.index.children(uid)),
+            transitive: uid_strings(current.index.descendants(uid)),
+        })
+    }
+
+    async fn entity_paths(&self, from: &EntityUid, to: &EntityUid) -> Option<schemas::EntityPaths> {
+        let current = self.live().await;
+        current.stored.get(from)?;
+        let mut paths = Vec::new();
+        current.collect_paths(to, &mut vec![from.clone()], &mut paths);
+        Some(schemas::EntityPaths {
+            from: from.to_string(),
+            to: to.to_string(),
+            paths: paths
+                .iter()
+                .map(|path| path.iter().map(EntityUid::to_string).collect())
+                .collect(),
+        })
+    }
+
+    async fn entity_graph(&self, uid: &EntityUid) -> Option<schemas::EntityGraph> {
+        let current = self.live().await;
+        current.stored.get(uid)?;
+        let mut nodes: HashSet<EntityUid> = current.ancestors(uid).into_iter().collect();
+        nodes.extend(current.index.descendants(uid).cloned());
+        nodes.insert(uid.clone());
+        let mut edges: Vec<schemas::EntityEdge> = nodes
+            .iter()
+            .flat_map(|child| {
+                let parents = current.parents(child).into_iter().filter(|parent| nodes.contains(parent));
+                parents.map(move |parent| schemas::EntityEdge {
+                    child: child.to_string(),
+                    parent: parent.to_string(),
+                })
+            })
+            .collect();
+        edges.sort_by(|a, b| (&a.child, &a.parent).cmp(&(&b.child, &b.parent)));
+        Some(schemas::EntityGraph {
+            nodes: uid_strings(&nodes),
+            edges,
+        })
+    }
+
+    async fn upsert_entities(
+        &self,
+        entities: schemas::Entities,
+        schema: Option<ValidatorSchema>,
+    ) -> Result<schemas::Entities, Box<dyn Error>> {
+        info!("Upserting {} entities", entities.len());
+        let upserted = parse_entities(entities, &schema)?;
+        let uids: Vec<EntityUid> = upserted.keys().cloned().collect();
+        let _writer = self.writer().await;
+        let changed = self.changed(upserted, &[], &schema).await?;
+        let schema_entities = uids
+            .iter()
+            .filter_map(|uid| changed.entity(uid))
+            .map(|entity| changed.json(entity))
+            .collect::<Vec<schemas::Entity>>();
+        self.replace(changed).await;
+        Ok(schema_entities.into())
+    }
+
+    async fn remove_entities(&self, uids: &[EntityUid]) -> Result<usize, Box<dyn Error>> {
+        info!("Removing {} entities", uids.len());
+        let _writer = self.writer().await;
+        let removed = {
+            let current = self.read().await;
+            let uids: HashSet<&EntityUid> = uids.iter().collect();
+            uids.into_iter().filter(|uid| current.stored.contains_key(uid)).count()
         };
-        *lock = Entities::new(cedar_entities, core_entities);
-        Ok(schema_entities)
+        if removed == 0 {
+            return Ok(0);
+        }
+        let changed = self.changed(HashMap::new(), uids, &None).await?;
+        self.replace(changed).await;
+        Ok(removed)
+    }
+
+    async fn apply_entity_operations(
+        &self,
+        operations: Vec<schemas::EntityOperation>,
+        schema: Option<ValidatorSchema>,
+    ) -> Result<schemas::DataRevision, Box<dyn Error>> {
+        info!("Applying {} entity operations", operations.len());
+        let _writer = self.writer().await;
+        let changed = self.operated(operations, &schema).await?;
+        let revision = self.replace(changed).await.revision + 1;
+        Ok(schemas::DataRevision { revision })
+    }
+
+    async fn candidate_entities(
+        &self,
+        entities: &schemas::Entities,
+        removed: &[EntityUid],
+        schema: Option<ValidatorSchema>,
+    ) -> Result<cedar_policy::Entities, Box<dyn Error>> {
+        let upserted = parse_entities(entities.clone(), &schema)?;
+        // The candidate is built from the live entities.
+        self.live().await;
+        Ok(self.changed(upserted, removed, &schema).await?.cedar_entities())
+    }
+
+    async fn candidate_operations(
+        &self,
+        operations: &[schemas::EntityOperation],
+        schema: Option<ValidatorSchema>,
+    ) -> Result<cedar_policy::Entities, Box<dyn Error>> {
+        self.live().await;
+        Ok(self.operated(operations.to_vec(), &schema).await?.cedar_entities())
+    }
+
+    async fn replace_partition(
+        &self,
+        partition: &str,
+        entities: schemas::Entities,
+        schema: Option<ValidatorSchema>,
+        check: Option<&CandidateCheck<'_>>,
+    ) -> Result<usize, Box<dyn Error>> {
+        info!("Replacing the entities of partition {} with {} entities", partition, entities.len());
+        let upserted = parse_entities(entities, &schema)?;
+        let uids: HashSet<EntityUid> = upserted.keys().cloned().collect();
+        let count = uids.len();
+        let _writer = self.writer().await;
+        let removed = self.read().await.left_out(partition, &uids);
+        let mut changed = self.changed(upserted, &removed, &schema).await?;
+        changed.set_partition(partition, uids);
+        if let Some(check) = check {
+            check(&changed.cedar_entities())?;
+        }
+        self.replace(changed).await;
+        Ok(count)
+    }
+
+    async fn import_entities(
+        &self,
+        reader: &mut dyn EntityReader,
+        replace: bool,
+        schema: Option<ValidatorSchema>,
+        check: Option<&CandidateCheck<'_>>,
+    ) -> Result<usize, Box<dyn Error>> {
+        info!("Importing entities");
+        // Chunks are parsed as they are read, only their parsed form is kept.
+        let mut imported: ParsedEntities = HashMap::new();
+        while let Some(chunk) = reader.next_chunk().await? {
+            if replace {
+                self.check_quota(imported.len() + chunk.len())?;
+            }
+            let mut uids = HashSet::new();
+            for uid in chunk.iter().filter_map(|entity| entity.uid()) {
+                if imported.contains_key(&uid) || !uids.insert(uid.clone()) {
+                    return Err(DataImportError::Duplicate(uid.to_string()).into());
+                }
+            }
+            imported.extend(parse_entities(chunk, &schema)?);
+        }
+        let count = imported.len();
+        let _writer = self.writer().await;
+        let changed = match replace {
+            true => self.replaced(imported, &schema)?,
+            false => self.changed(imported, &[], &schema).await?,
+        };
+        if let Some(check) = check {
+            check(&changed.cedar_entities())?;
+        }
+        self.replace(changed).await;
+        info!("Imported {} entities", count);
+        Ok(count)
+    }
+
+    async fn export_entities(&self) -> BoxStream<'static, schemas::Entities> {
+        info!("Exporting stored entities");
+        let current = self.live().await;
+        // The chunks are taken from the same entities in uid order, later changes aren't exported.
+        let state = Some((current, None));
+        stream::unfold(state, |state: Option<(Arc<Entities>, Option<String>)>| async move {
+            let (current, cursor) = state?;
+            let (uids, more) = current.index.query(&[], cursor.as_deref(), EXPORT_CHUNK);
+            let chunk: Vec<schemas::Entity> =
+                uids.iter().filter_map(|uid| current.entity(uid)).map(|entity| current.json(entity)).collect();
+            if chunk.is_empty() {
+                return None;
+            }
+            let cursor = uids.last().filter(|_| more).map(|uid| uid.to_string());
+            let next = cursor.map(|cursor| (current.clone(), Some(cursor)));
+            Some((schemas::Entities::from(chunk), next))
+        })
+        .boxed()
+    }
+
+    async fn has_partition(&self, partition: &str) -> bool {
+        self.read().await.partitions.contains_key(partition)
+    }
+
+    async fn remove_expired_entities(&self) -> usize {
+        let _writer = self.writer.lock().await;
+        match self.remove_expired(Utc::now()).await {
+            Ok(removed) => removed,
+            Err(err) => {
+                error!("Failed to remove expired entities: {}", err);
+                0
+            }
+        }
+    }
+
+    async fn check_integrity(&self, schema: Option<ValidatorSchema>) -> schemas::IntegrityReport {
+        info!("Checking the integrity of stored entities");
+        let current = self.live().await;
+        let stored = &current.stored;
+        let actions = schema_actions(&schema);
+        let mut report = schemas::IntegrityReport::default();
+        check_references(
+            stored.iter().map(|(uid, entity)| entity_links(stored, uid, entity)),
+            |uid| stored.contains_key(uid) || actions.contains(uid),
+            &mut report,
+        );
+        // Changes introducing a cycle are rejected, this only finds one in entities stored otherwise.
+        let cycle = find_cycle(stored.keys(), |uid| stored_parents(stored, uid));
+        report.cycles.extend(cycle.map(|cycle| cycle.iter().map(EntityUid::to_string).collect()));
+        report
     }
 }
@@ -1,21 +1,112 @@
 use std::error::Error;
 
 use async_trait::async_trait;
-use cedar_policy::Schema;
+use cedar_policy::EntityUid;
+use cedar_policy_validator::ValidatorSchema;
+use rocket::futures::stream::BoxStream;
 
 use crate::schemas::data as schemas;
+use crate::services::data::import::EntityReader;
+use crate::services::staged::StagedChange;
 
+pub mod errors;
+pub mod expiry;
+pub mod hierarchy;
+pub mod import;
+pub mod index;
+pub mod integrity;
 pub mod memory;
+pub mod sources;
 pub mod load_from_file;
 
+/// Checks the entities a change results in, before the change is stored.
+pub type CandidateCheck<'a> = dyn Fn(&cedar_policy::Entities) -> Result<(), Box<dyn Error>> + Send + Sync + 'a;
+
 #[async_trait]
 pub trait DataStore: Send + Sync {
     async fn entities(&self) -> cedar_policy::Entities;
+    /// The stored entities evaluating a request about the `roots` can reach, through the entities the
+    /// attributes refer to.
+    async fn reachable_entities(&self, roots: &[EntityUid]) -> cedar_policy::Entities;
     async fn get_entities(&self) -> schemas::Entities;
+    /// How many entities are stored.
+    async fn count_entities(&self) -> usize;
     async fn delete_entities(&self);
     async fn update_entities(
         &self,
         entities: schemas::Entities,
-        schema: Option<Schema>,
+        schema: Option<ValidatorSchema>,
+    ) -> Result<schemas::Entities, Box<dyn Error>>;
+    /// Check the entities replacing the stored ones, the staged change stores them.
+    async fn stage_entities(
+        &self,
+        entities: schemas::Entities,
+        schema: Option<ValidatorSchema>,
+    ) -> Result<(schemas::Entities, Box<dyn StagedChange + '_>), Box<dyn Error>>;
+    /// The stored entity with all of its ancestors.
+    async fn get_entity(&self, uid: &EntityUid) -> Option<schemas::Entity>;
+    /// A page of the stored entities matching the query.
+    async fn query_entities(&self, query: &schemas::EntityQuery) -> Result<schemas::EntityPage, Box<dyn Error>>;
+    /// The parents and ancestors of a stored entity.
+    async fn entity_ancestors(&self, uid: &EntityUid) -> Option<schemas::EntityRelations>;
+    /// The children and descendants of a stored entity.
+    async fn entity_descendants(&self, uid: &EntityUid) -> Option<schemas::EntityRelations>;
+    /// The chains of parents from a stored entity to its ancestor `to`.
+    async fn entity_paths(&self, from: &EntityUid, to: &EntityUid) -> Option<schemas::EntityPaths>;
+    /// The ancestors and descendants of a stored entity with the edges between them.
+    async fn entity_graph(&self, uid: &EntityUid) -> Option<schemas::EntityGraph>;
+    /// Add the entities or replace the stored ones with the same uid, returns the upserted entities.
+    async fn upsert_entities(
+        &self,
+        entities: schemas::Entities,
+        schema: Option<ValidatorSchema>,
     ) -> Result<schemas::Entities, Box<dyn Error>>;
+    /// Remove the entities, returns how many of them were stored.
+    async fn remove_entities(&self, uids: &[EntityUid]) -> Result<usize, Box<dyn Error>>;
+    /// Apply the operations all together or not at all, returns the revision of the changed entities.
+    async fn apply_entity_operations(
+        &self,
+        operations: Vec<schemas::EntityOperation>,
+        schema: Option<ValidatorSchema>,
+    ) -> Result<schemas::DataRevision, Box<dyn Error>>;
+    /// Replace the entities of a partition, the entities of other partitions and those changed directly are kept.
+    /// Entities given here are taken over from the partition holding them. Returns how many the partition holds.
+    /// The resulting entities are passed to `check` before they are stored.
+    async fn replace_partition(
+        &self,
+        partition: &str,
+        entities: schemas::Entities,
+        schema: Option<ValidatorSchema>,
+        check: Option<&CandidateCheck<'_>>,
+    ) -> Result<usize, Box<dyn Error>>;
+    /// Upsert the entities read, or replace all the stored entities with them. Returns how many were read.
+    /// The resulting entities are passed to `check` before they are stored.
+    async fn import_entities(
+        &self,
+        reader: &mut dyn EntityReader,
+        replace: bool,
+        schema: Option<ValidatorSchema>,
+        check: Option<&CandidateCheck<'_>>,
+    ) -> Result<usize, Box<dyn Error>>;
+    /// The stored entities a chunk at a time, as they were when the export started.
+    async fn export_entities(&self) -> BoxStream<'static, schemas::Entities>;
+    /// Whether the partition was replaced since the entities were last replaced as a whole.
+    async fn has_partition(&self, partition: &str) -> bool;
+    /// Remove the entities whose expiry has passed, returns how many were removed.
+    async fn remove_expired_entities(&self) -> usize;
+    /// The dangling references and cycles among the stored entities, actions of the schema count as stored.
+    async fn check_integrity(&self, schema: Option<ValidatorSchema>) -> schemas::IntegrityReport;
+    /// The entities resulting from upserting `entities` and removing `removed`, without storing them.
+    async fn candidate_entities(
+        &self,
+        entities: &schemas::Entities,
+        removed: &[EntityUid],
+        schema: Option<ValidatorSchema>,
+    ) -> Result<cedar_policy::Entities, Box<dyn Error>>;
+    /// The entities resulting from applying the operations, without storing them.
+    async fn candidate_operations(
+        &self,
+        operations: &[schemas::EntityOperation],
+        schema: Option<ValidatorSchema>,
+    ) -> Result<cedar_policy::Entities, Box<dyn Error>>;
 }
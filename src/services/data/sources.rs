@@ -0,0 +1,408 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_lock::{Mutex, RwLock};
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, StatusCode, Url};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::serde::json::serde_json::{from_slice, from_str, from_value};
+use rocket::serde::json::Value;
+use rocket::tokio::time::{interval, MissedTickBehavior};
+use rocket::{tokio, Build, Orbit, Rocket};
+
+use crate::config;
+use crate::schemas::data as schemas;
+use crate::services::data::errors::DataSourceError;
+use crate::services::data::{CandidateCheck, DataStore};
+use crate::services::policies::PolicyStore;
+use crate::services::policy_tests::runner::entities_check;
+use crate::services::policy_tests::{PolicyTestStore, TestGate};
+use crate::services::schema::SchemaStore;
+
+/// How long a single fetch may take.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A step of a path to the entities in a fetched document.
+#[derive(Debug, PartialEq)]
+pub enum PathStep {
+    Key(String),
+    Index(usize),
+}
+
+/// Parse a jq-like path: `.data.entities`, `.items[0].entities` or `.["odd key"]`, `.` being the whole document.
+pub fn parse_path(path: &str) -> Result<Vec<PathStep>, String> {
+    let invalid = |reason: &str| format!("invalid path {}: {}", path, reason);
+    let mut steps = Vec::new();
+    let mut rest = path.trim();
+    if rest == "." {
+        return Ok(steps);
+    }
+    while !rest.is_empty() {
+        if let Some(tail) = rest.strip_prefix('[') {
+            let end = tail.find(']').ok_or_else(|| invalid("unclosed ["))?;
+            let inner = &tail[..end];
+            let step = match inner.strip_prefix('"').and_then(|inner| inner.strip_suffix('"')) {
+                Some(key) => PathStep::Key(key.to_string()),
+                None => PathStep::Index(inner.parse().map_err(|_| invalid("indexes are non-negative integers"))?),
+            };
+            steps.push(step);
+            rest = &tail[end + 1..];
+        } else if let Some(tail) = rest.strip_prefix('.') {
+            let end = tail.find(['.', '[']).unwrap_or(tail.len());
+            if end > 0 {
+                steps.push(PathStep::Key(tail[..end].to_string()));
+            } else if !tail.starts_with('[') {
+                return Err(invalid("empty key"));
+            }
+            rest = &tail[end..];
+        } else {
+            return Err(invalid("steps start with . or ["));
+        }
+    }
+    Ok(steps)
+}
+
+/// Take the value at the path out of the document.
+pub fn select(mut document: Value, path: &[PathStep]) -> Option<Value> {
+    for step in path {
+        document = match (step, document) {
+            (PathStep::Key(key), Value::Object(mut object)) => object.remove(key)?,
+            (PathStep::Index(index), Value::Array(mut values)) if *index < values.len() => values.swap_remove(*index),
+            _ => return None,
+        };
+    }
+    Some(document)
+}
+
+/// What a conditional fetch is compared with, kept from the last fetch whose entities were stored.
+#[derive(Default)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// The modification time of a file source.
+    modified: Option<SystemTime>,
+}
+
+enum Fetched {
+    Unchanged,
+    Changed(Vec<u8>, Validators),
+}
+
+/// A source whose entities are fetched into the partition named after it.
+pub struct DataSource {
+    config: schemas::DataSourceConfig,
+    path: Vec<PathStep>,
+    /// Held for the whole refresh, refreshes of a source don't overlap.
+    validators: Mutex<Validators>,
+    status: RwLock<schemas::DataSourceStatus>,
+}
+
+impl DataSource {
+    pub fn new(config: schemas::DataSourceConfig) -> Result<Self, DataSourceError> {
+        let invalid = |reason: String| DataSourceError::InvalidConfig(format!("{}: {}", config.name, reason));
+        if config.name.is_empty() {
+            return Err(DataSourceError::InvalidConfig("sources need a name".to_string()));
+        }
+        match (&config.url, &config.file) {
+            (Some(url), None) => match Url::parse(url) {
+                Ok(url) if ["http", "https"].contains(&url.scheme()) => {}
+                Ok(url) => return Err(invalid(format!("unsupported url scheme {}", url.scheme()))),
+                Err(err) => return Err(invalid(format!("invalid url {}: {}", url, err))),
+            },
+            (None, Some(_)) => {}
+            _ => return Err(invalid("either a url or a file is required".to_string())),
+        }
+        if config.interval == 0 {
+            return Err(invalid("the interval is at least 1 second".to_string()));
+        }
+        let path = parse_path(config.path.as_deref().unwrap_or(".")).map_err(invalid)?;
+        let status = schemas::DataSourceStatus {
+            name: config.name.clone(),
+            location: config.location(),
+            interval: config.interval,
+            last_fetch: None,
+            last_update: None,
+            last_error: None,
+            entities: 0,
+        };
+        Ok(Self {
+            config,
+            path,
+            validators: Mutex::new(Validators::default()),
+            status: RwLock::new(status),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.config.interval)
+    }
+
+    pub async fn status(&self) -> schemas::DataSourceStatus {
+        self.status.read().await.clone()
+    }
+
+    async fn fetch(&self, client: &Client, validators: &Validators) -> Result<Fetched, DataSourceError> {
+        let location = self.config.location();
+        let failed = |err: String| DataSourceError::Fetch(location.clone(), err);
+        if let Some(file) = &self.config.file {
+            let metadata = tokio::fs::metadata(file).await.map_err(|err| failed(err.to_string()))?;
+            let modified = metadata.modified().ok();
+            if modified.is_some() && modified == validators.modified {
+                return Ok(Fetched::Unchanged);
+            }
+            let body = tokio::fs::read(file).await.map_err(|err| failed(err.to_string()))?;
+            let fetched = Validators {
+                modified,
+                ..Default::default()
+            };
+            return Ok(Fetched::Changed(body, fetched));
+        }
+
+        let mut request = client.get(&location);
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = request.send().await.map_err(|err| failed(err.to_string()))?;
+        match response.status() {
+            StatusCode::NOT_MODIFIED => return Ok(Fetched::Unchanged),
+            status if !status.is_success() => return Err(DataSourceError::Status(location, status.as_u16())),
+            _ => {}
+        }
+        let header = |name| response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let fetched = Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            modified: None,
+        };
+        let body = response.bytes().await.map_err(|err| failed(err.to_string()))?;
+        Ok(Fetched::Changed(body.to_vec(), fetched))
+    }
+
+    /// Fetch the source and replace its partition, returns how many entities were stored if they changed.
+    async fn update(
+        &self,
+        client: &Client,
+        validators: &mut Validators,
+        data_store: &dyn DataStore,
+        schema_store: &dyn SchemaStore,
+        gate: Option<&SourceGate>,
+    ) -> Result<Option<usize>, Box<dyn Error>> {
+        let (body, fetched) = match self.fetch(client, validators).await? {
+            Fetched::Unchanged => return Ok(None),
+            Fetched::Changed(body, fetched) => (body, fetched),
+        };
+        let location = self.config.location();
+        let invalid = |reason: String| DataSourceError::InvalidEntities(location.clone(), reason);
+        let document: Value = from_slice(&body).map_err(|err| invalid(err.to_string()))?;
+        let path = self.config.path.as_deref().unwrap_or(".");
+        let entities = select(document, &self.path).ok_or_else(|| invalid(format!("nothing at path {}", path)))?;
+        let entities: schemas::Entities = from_value(entities).map_err(|err| invalid(err.to_string()))?;
+        let schema = schema_store.get_validator_schema().await;
+        // The gate is held from taking the tests until the entities are stored.
+        let _held = match gate {
+            Some(gate) => Some(gate.test_gate.hold().await),
+            None => None,
+        };
+        let check = match gate {
+            Some(gate) => entities_check(gate.test_store.as_ref(), gate.policy_store.as_ref()).await,
+            None => None,
+        };
+        let source = location.as_str();
+        let check = check.map(|check| {
+            move |entities: &cedar_policy::Entities| {
+                check(entities).map_err(|failures| {
+                    let ids: Vec<String> = failures.into_iter().map(|result| result.id).collect();
+                    DataSourceError::TestsFailed(source.to_owned(), ids.join(", ")).into()
+                })
+            }
+        });
+        let check = check.as_ref().map(|check| check as &CandidateCheck);
+        let count = data_store.replace_partition(&self.config.name, entities, schema, check).await?;
+        *validators = fetched;
+        Ok(Some(count))
+    }
+
+    /// Fetch the source and replace the entities of its partition when they changed.
+    /// With a `gate`, entities making a stored test fail are rejected.
+    pub async fn refresh(
+        &self,
+        client: &Client,
+        data_store: &dyn DataStore,
+        schema_store: &dyn SchemaStore,
+        gate: Option<&SourceGate>,
+    ) {
+        let mut validators = self.validators.lock().await;
+        // Replacing all the entities drops the partition, the source is then fetched whether it changed or not.
+        if !data_store.has_partition(&self.config.name).await {
+            *validators = Validators::default();
+        }
+        let updated = self.update(client, &mut validators, data_store, schema_store, gate).await;
+        let updated = updated.map_err(|err| err.to_string());
+        let now = Utc::now();
+        let mut status = self.status.write().await;
+        status.last_fetch = Some(now);
+        match updated {
+            Ok(None) => {
+                debug!("Data source {} is unchanged", self.config.name);
+                status.last_error = None;
+            }
+            Ok(Some(count)) => {
+                info!("Stored {} entities from data source {}", count, self.config.name);
+                status.last_update = Some(now);
+                status.last_error = None;
+                status.entities = count;
+            }
+            Err(err) => {
+                warn!("Failed to refresh data source {}: {}", self.config.name, err);
+                status.last_error = Some(err);
+            }
+        }
+    }
+}
+
+/// The test gate sources hold, and the stores their entities are checked against, when the gate is enabled.
+pub struct SourceGate {
+    pub test_gate: Arc<TestGate>,
+    pub test_store: Arc<dyn PolicyTestStore>,
+    pub policy_store: Arc<dyn PolicyStore>,
+}
+
+/// The configured data sources and the client fetching them.
+pub struct DataSources {
+    sources: Vec<Arc<DataSource>>,
+    client: Client,
+}
+
+impl DataSources {
+    pub fn new(configs: Vec<schemas::DataSourceConfig>) -> Result<Self, DataSourceError> {
+        let mut names = HashSet::new();
+        let mut sources = Vec::new();
+        for config in configs {
+            if !names.insert(config.name.clone()) {
+                return Err(DataSourceError::InvalidConfig(format!("{} is configured twice", config.name)));
+            }
+            sources.push(Arc::new(DataSource::new(config)?));
+        }
+        let client = Client::builder().timeout(FETCH_TIMEOUT).build();
+        let client = client.map_err(|err| DataSourceError::InvalidConfig(err.to_string()))?;
+        Ok(Self { sources, client })
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            sources: Vec::new(),
+            client: Client::new(),
+        }
+    }
+
+    pub fn sources(&self) -> &[Arc<DataSource>] {
+        &self.sources
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub async fn status(&self) -> Vec<schemas::DataSourceStatus> {
+        let mut status = Vec::with_capacity(self.sources.len());
+        for source in &self.sources {
+            status.push(source.status().await);
+        }
+        status
+    }
+}
+
+pub async fn load_data_sources_from_file(path: PathBuf) -> Result<Vec<schemas::DataSourceConfig>, Box<dyn Error>> {
+    let contents = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|err| format!("Failed to read file {}: {}", path.display(), err))?;
+    let sources = from_str(&contents).map_err(|err| format!("Failed to deserialize JSON: {}", err))?;
+    Ok(sources)
+}
+
+/// Loads the configured data sources and polls each of them into its partition of the agent data store.
+pub struct DataSourcesFairing;
+
+#[async_trait::async_trait]
+impl Fairing for DataSourcesFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Data Sources",
+            kind: Kind::Ignite | Kind::Liftoff,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> Result<Rocket<Build>, Rocket<Build>> {
+        let path = rocket.state::<config::Config>().and_then(|config| config.data_sources.clone());
+        let sources = match path {
+            Some(path) => match load_data_sources_from_file(path).await {
+                Ok(configs) => DataSources::new(configs).map_err(|err| err.into()),
+                Err(err) => Err(err),
+            },
+            None => Ok(DataSources::empty()),
+        };
+        match sources {
+            Ok(sources) => Ok(rocket.manage(Arc::new(sources))),
+            Err(err) => {
+                error!("Failed to load data sources: {}", err);
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (sources, data_store, schema_store) = match (
+            rocket.state::<Arc<DataSources>>(),
+            rocket.state::<Arc<dyn DataStore>>(),
+            rocket.state::<Arc<dyn SchemaStore>>(),
+        ) {
+            (Some(sources), Some(data_store), Some(schema_store)) => (sources, data_store, schema_store),
+            _ => return,
+        };
+        let gate = match (
+            rocket.state::<config::Config>().filter(|config| config.test_gate_enabled()),
+            rocket.state::<Arc<TestGate>>(),
+            rocket.state::<Arc<dyn PolicyTestStore>>(),
+            rocket.state::<Arc<dyn PolicyStore>>(),
+        ) {
+            (Some(_), Some(test_gate), Some(test_store), Some(policy_store)) => Some(Arc::new(SourceGate {
+                test_gate: test_gate.clone(),
+                test_store: test_store.clone(),
+                policy_store: policy_store.clone(),
+            })),
+            _ => None,
+        };
+        for source in sources.sources() {
+            let (source, client) = (source.clone(), sources.client().clone());
+            let (data_store, schema_store, gate) = (data_store.clone(), schema_store.clone(), gate.clone());
+            let mut shutdown = rocket.shutdown();
+
+            info!("Polling data source {} every {:?}", source.name(), source.interval());
+            tokio::spawn(async move {
+                let mut ticks = interval(source.interval());
+                ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    tokio::select! {
+                        _ = ticks.tick() => {
+                            source.refresh(&client, data_store.as_ref(), schema_store.as_ref(), gate.as_deref()).await
+                        }
+                        _ = &mut shutdown => break,
+                    }
+                }
+                info!("Stopped polling data source {}", source.name());
+            });
+        }
+    }
+}
@@ -1,7 +1,13 @@
+pub mod bundle;
 pub mod data;
 pub mod policies;
+pub mod policy_tests;
 pub mod schema;
+pub mod staged;
+pub mod tenants;
 
 pub use data::DataStore;
 pub use policies::PolicyStore;
+pub use policy_tests::PolicyTestStore;
 pub use schema::SchemaStore;
+pub use tenants::TenantStore;
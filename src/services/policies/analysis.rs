@@ -0,0 +1,226 @@
+use cedar_policy::{
+    ActionConstraint, Effect, Entities, EntityUid, PolicySet, PrincipalConstraint,
+    ResourceConstraint, Schema, TypeErrorKind, ValidationErrorKind, ValidationMode,
+    ValidationResult, Validator,
+};
+use log::{debug, info};
+use rocket::serde::json::{json, Value};
+
+use crate::schemas::analysis::{FindingKind, FindingSeverity, PolicyAnalysis, PolicyFinding};
+
+/// Principal and resource scope constraints share the same shape.
+#[derive(PartialEq)]
+enum EntityScope {
+    Any,
+    In(EntityUid),
+    Eq(EntityUid),
+}
+
+impl From<PrincipalConstraint> for EntityScope {
+    fn from(constraint: PrincipalConstraint) -> Self {
+        match constraint {
+            PrincipalConstraint::Any => EntityScope::Any,
+            PrincipalConstraint::In(uid) => EntityScope::In(uid),
+            PrincipalConstraint::Eq(uid) => EntityScope::Eq(uid),
+        }
+    }
+}
+
+impl From<ResourceConstraint> for EntityScope {
+    fn from(constraint: ResourceConstraint) -> Self {
+        match constraint {
+            ResourceConstraint::Any => EntityScope::Any,
+            ResourceConstraint::In(uid) => EntityScope::In(uid),
+            ResourceConstraint::Eq(uid) => EntityScope::Eq(uid),
+        }
+    }
+}
+
+impl EntityScope {
+    /// Whether every entity matched by `inner` is also matched by `self`.
+    fn covers(&self, inner: &EntityScope) -> bool {
+        match (self, inner) {
+            (EntityScope::Any, _) => true,
+            (_, EntityScope::Any) => false,
+            (EntityScope::Eq(outer), EntityScope::Eq(inner)) => outer == inner,
+            (EntityScope::Eq(_), EntityScope::In(_)) => false,
+            // Without entity data the only ancestor we know of is the entity itself.
+            (EntityScope::In(outer), EntityScope::Eq(inner) | EntityScope::In(inner)) => {
+                outer == inner
+            }
+        }
+    }
+}
+
+fn action_covers(outer: &ActionConstraint, inner: &ActionConstraint, actions: &Entities) -> bool {
+    // Cedar doesn't count an entity as its own ancestor, `in` does.
+    let within = |o: &EntityUid, i: &EntityUid| o == i || actions.is_ancestor_of(o, i);
+    match (outer, inner) {
+        (ActionConstraint::Any, _) => true,
+        (_, ActionConstraint::Any) => false,
+        (ActionConstraint::Eq(outer), ActionConstraint::Eq(inner)) => outer == inner,
+        (ActionConstraint::Eq(_), ActionConstraint::In(_)) => false,
+        (ActionConstraint::In(outer), ActionConstraint::Eq(inner)) => {
+            outer.iter().any(|o| within(o, inner))
+        }
+        (ActionConstraint::In(outer), ActionConstraint::In(inner)) => inner
+            .iter()
+            .all(|i| outer.iter().any(|o| within(o, i))),
+    }
+}
+
+struct AnalyzedPolicy {
+    id: String,
+    effect: Effect,
+    principal: EntityScope,
+    action: ActionConstraint,
+    resource: EntityScope,
+    /// The `when`/`unless` clauses in their JSON form, used for structural comparison.
+    conditions: Value,
+}
+
+impl AnalyzedPolicy {
+    fn new(policy: &cedar_policy::Policy) -> Self {
+        // Stored policies are printed back with `when { true }` standing for no conditions.
+        let trivial = json!({"kind": "when", "body": {"Value": true}});
+        let conditions = match policy.to_json() {
+            Ok(json) => match json.get("conditions") {
+                Some(Value::Array(conditions)) => {
+                    Value::Array(conditions.iter().filter(|c| **c != trivial).cloned().collect())
+                }
+                _ => Value::Null,
+            },
+            Err(_) => Value::Null,
+        };
+        Self {
+            id: policy.id().to_string(),
+            effect: policy.effect(),
+            principal: policy.principal_constraint().into(),
+            action: policy.action_constraint(),
+            resource: policy.resource_constraint().into(),
+            conditions,
+        }
+    }
+
+    fn is_unconditional(&self) -> bool {
+        match &self.conditions {
+            Value::Array(conditions) => conditions.is_empty(),
+            _ => false,
+        }
+    }
+
+    fn same_scope(&self, other: &AnalyzedPolicy) -> bool {
+        self.principal == other.principal
+            && self.action == other.action
+            && self.resource == other.resource
+    }
+
+    fn covers_scope(&self, other: &AnalyzedPolicy, actions: &Entities) -> bool {
+        self.principal.covers(&other.principal)
+            && action_covers(&self.action, &other.action, actions)
+            && self.resource.covers(&other.resource)
+    }
+}
+
+fn schema_findings(policy_set: &PolicySet, schema: &Schema, mode: ValidationMode) -> Vec<PolicyFinding> {
+    let validator = Validator::new(schema.clone());
+    let validation_result = validator.validate(policy_set, mode);
+    let mut findings = Vec::new();
+    for error in ValidationResult::validation_errors(&validation_result) {
+        let (kind, severity) = match error.error_kind() {
+            ValidationErrorKind::InvalidActionApplication(_)
+            | ValidationErrorKind::TypeError(TypeErrorKind::ImpossiblePolicy) => {
+                (FindingKind::UnreachableScope, FindingSeverity::Warning)
+            }
+            ValidationErrorKind::UnrecognizedEntityType(_)
+            | ValidationErrorKind::UnrecognizedActionId(_)
+            | ValidationErrorKind::TypeError(TypeErrorKind::UnsafeAttributeAccess(_)) => {
+                (FindingKind::UnknownReference, FindingSeverity::Error)
+            }
+            other => {
+                debug!("Skipping validation error outside of the analysis: {}", other);
+                continue;
+            }
+        };
+        findings.push(PolicyFinding {
+            kind,
+            severity,
+            policies: vec![error.location().policy_id().to_string()],
+            explanation: error.error_kind().to_string(),
+        });
+    }
+    findings
+}
+
+fn pair_findings(policies: &[AnalyzedPolicy], actions: &Entities) -> Vec<PolicyFinding> {
+    let mut findings = Vec::new();
+    for (index, policy) in policies.iter().enumerate() {
+        for other in &policies[index + 1..] {
+            if policy.effect == other.effect && policy.same_scope(other) {
+                if policy.conditions == other.conditions {
+                    findings.push(PolicyFinding {
+                        kind: FindingKind::DuplicatePolicy,
+                        severity: FindingSeverity::Warning,
+                        policies: vec![other.id.clone(), policy.id.clone()],
+                        explanation: format!(
+                            "Policy {} has the same effect, scope and conditions as policy {}",
+                            other.id, policy.id
+                        ),
+                    });
+                } else {
+                    findings.push(PolicyFinding {
+                        kind: FindingKind::DuplicateScope,
+                        severity: FindingSeverity::Info,
+                        policies: vec![other.id.clone(), policy.id.clone()],
+                        explanation: format!(
+                            "Policy {} has the same effect and scope as policy {} and differs only in its conditions",
+                            other.id, policy.id
+                        ),
+                    });
+                }
+            }
+        }
+    }
+
+    for permit in policies.iter().filter(|p| p.effect == Effect::Permit) {
+        for forbid in policies.iter().filter(|p| p.effect == Effect::Forbid) {
+            if forbid.is_unconditional() && forbid.covers_scope(permit, actions) {
+                findings.push(PolicyFinding {
+                    kind: FindingKind::ShadowedPermit,
+                    severity: FindingSeverity::Warning,
+                    policies: vec![permit.id.clone(), forbid.id.clone()],
+                    explanation: format!(
+                        "Permit {} can never take effect, the unconditional forbid {} applies to every request it matches",
+                        permit.id, forbid.id
+                    ),
+                });
+            }
+        }
+    }
+    findings
+}
+
+/// Look for dead, duplicated or schema breaking policies in the given set.
+/// Schema based checks are only performed when a schema is given, validating in the given mode.
+pub fn analyze_policies(policy_set: &PolicySet, schema: &Option<Schema>, mode: ValidationMode) -> PolicyAnalysis {
+    let mut policies: Vec<AnalyzedPolicy> = policy_set.policies().map(AnalyzedPolicy::new).collect();
+    policies.sort_by(|a, b| a.id.cmp(&b.id));
+    info!("Analyzing {} policies", policies.len());
+
+    let actions = match schema {
+        Some(schema) => schema.action_entities().unwrap_or_else(|_| Entities::empty()),
+        None => Entities::empty(),
+    };
+    let mut findings = match schema {
+        Some(schema) => schema_findings(policy_set, schema, mode),
+        None => Vec::new(),
+    };
+    findings.extend(pair_findings(&policies, &actions));
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity).then_with(|| a.policies.cmp(&b.policies)));
+
+    PolicyAnalysis {
+        policies: policies.len(),
+        schema_checked: schema.is_some(),
+        findings,
+    }
+}
@@ -1,4 +1,11 @@
 use thiserror::Error;
+
+use crate::schemas::policies::PolicyError;
+
+fn join_policy_errors(errors: &[PolicyError]) -> String {
+    errors.iter().map(|e| e.to_string()).collect::<Vec<String>>().join("; ")
+}
+
 #[derive(Error, Debug)]
 #[non_exhaustive]
 pub enum PolicyStoreError {
@@ -11,7 +18,38 @@ pub enum PolicyStoreError {
     /// Policy with the given id was not found.
     #[error("Unable to find policy with id {0}")]
     PolicyNotFoundError(String),
-    /// Validation returned an error.
-    #[error("Failed validating policy {0} against the schema: {1}")]
-    PolicyInvalid(String, String)
+    /// Parsing or validation returned errors for one or more policies.
+    #[error("Invalid policy {0}: {}", join_policy_errors(.1))]
+    PolicyInvalid(String, Vec<PolicyError>),
+    /// An operation of a policy transaction could not be applied.
+    #[error("Failed applying policy operation {0}: {1}")]
+    PolicyOperationFailed(usize, String),
+    /// The validity window of the policy ends before it starts.
+    #[error("Invalid schedule for policy {0}: not_after has to be later than not_before")]
+    InvalidSchedule(String),
+    /// The change would store more policies than allowed.
+    #[error("Policy quota exceeded, at most {0} policies can be stored")]
+    QuotaExceeded(usize),
+    /// A search parameter could not be parsed.
+    #[error("Invalid policy search: {0}")]
+    InvalidSearch(String),
+    /// An impact proposal sent as JSON lines could not be read.
+    #[error("Invalid impact proposal: {0}")]
+    InvalidProposal(String),
+    /// An impact proposal sent as JSON lines exceeds the limit.
+    #[error("Impact proposal exceeds the limit of {0} bytes")]
+    ProposalTooLarge(u64),
+}
+
+impl PolicyStoreError {
+    /// Build a `PolicyInvalid` error naming every policy that has an error.
+    pub fn invalid(errors: Vec<PolicyError>) -> Self {
+        let mut policy_ids: Vec<String> = Vec::new();
+        for error in &errors {
+            if !policy_ids.contains(&error.policy_id) {
+                policy_ids.push(error.policy_id.clone());
+            }
+        }
+        PolicyStoreError::PolicyInvalid(policy_ids.join(", "), errors)
+    }
 }
@@ -0,0 +1,40 @@
+use uuid::Uuid;
+
+/// Placeholder of the policy id pattern replaced by the slug of the `@id` annotation.
+pub const ANNOTATION_PLACEHOLDER: &str = "{id}";
+
+/// Lowercase ASCII letters and digits, every other run of characters becomes a single `-`.
+pub fn slug(value: &str) -> String {
+    let mut slug = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Generate an id for a policy submitted without one.
+/// With a pattern and an `@id` annotation the id is the pattern with the annotation slug filled in,
+/// suffixed with `-2`, `-3`, ... while `taken`. Otherwise it's a random UUID, so only the pattern gives a policy
+/// submitted again the same id.
+pub fn generate_policy_id(
+    pattern: Option<&str>,
+    policy: &cedar_policy::Policy,
+    taken: impl Fn(&str) -> bool,
+) -> String {
+    let annotation = policy.annotation("id").map(slug).filter(|slug| !slug.is_empty());
+    let base = match (pattern, annotation) {
+        (Some(pattern), Some(annotation)) => pattern.replace(ANNOTATION_PLACEHOLDER, &annotation),
+        _ => return Uuid::new_v4().to_string(),
+    };
+    let mut id = base.clone();
+    let mut suffix = 1;
+    while taken(&id) {
+        suffix += 1;
+        id = format!("{}-{}", base, suffix);
+    }
+    id
+}
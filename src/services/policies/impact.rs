@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+
+use cedar_policy::{Authorizer, Entities, PolicySet, Request, Response};
+use cedar_policy_core::authorizer::Decision;
+use chrono::Utc;
+use log::info;
+use rocket::serde::json::serde_json;
+use rocket::tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::schemas::analysis::{
+    ImpactChange, ImpactDecision, ImpactError, ImpactReport, ImpactRequest, ImpactSummary,
+    ProposalMode,
+};
+use crate::schemas::authorization::{AuthorizationCall, AuthorizationRequest, DecisionRef};
+use crate::schemas::policies::{Policy, PolicyError};
+use crate::services::policies::errors::PolicyStoreError;
+use crate::services::policies::ids::generate_policy_id;
+
+/// The policies making up the proposed set, given the currently stored ones.
+/// Proposed policies without an id get one generated from `id_pattern` as the store would, see `generate_policy_id`.
+pub fn proposed_policies(stored: Vec<Policy>, proposal: &ImpactRequest, id_pattern: Option<&str>) -> Vec<Policy> {
+    let mut policies = match proposal.mode {
+        ProposalMode::Full => proposal.policies.clone(),
+        ProposalMode::Partial => {
+            let mut policies: Vec<Policy> = stored
+                .into_iter()
+                .filter(|p| !proposal.removed.contains(&p.id))
+                .filter(|p| !proposal.policies.iter().any(|proposed| proposed.id == p.id))
+                .collect();
+            policies.extend(proposal.policies.iter().cloned());
+            policies
+        }
+    };
+    let mut taken: HashSet<String> = policies.iter().map(|p| p.id.clone()).filter(|id| !id.is_empty()).collect();
+    for policy in policies.iter_mut().filter(|p| p.id.is_empty()) {
+        // Policies that don't parse keep their empty id, they are reported by `parse_policy_set`.
+        let parsed: Result<cedar_policy::Policy, _> = (&*policy).try_into();
+        let Ok(parsed) = parsed else {
+            continue;
+        };
+        policy.id = generate_policy_id(id_pattern, &parsed, |id| taken.contains(id));
+        taken.insert(policy.id.clone());
+    }
+    policies
+}
+
+/// Build the policy set used for evaluation, reporting every policy that fails to parse.
+/// Inactive policies are left out.
+pub fn parse_policy_set(policies: &[Policy]) -> Result<PolicySet, PolicyStoreError> {
+    let now = Utc::now();
+    parse_policies(policies.iter().filter(|policy| policy.is_active(now)))
+}
+
+/// Build a cedar policy set of the given policies, whether they are active or not.
+pub fn parse_policies<'a>(policies: impl IntoIterator<Item = &'a Policy>) -> Result<PolicySet, PolicyStoreError> {
+    let mut policy_set = PolicySet::new();
+    let mut errors: Vec<PolicyError> = Vec::new();
+    for policy in policies {
+        match policy.try_into() {
+            Ok(p) => policy_set.add(p)?,
+            Err(err) => errors.extend(PolicyError::from_parse_errors(&policy.id, &policy.content, &err)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(policy_set)
+    } else {
+        Err(PolicyStoreError::invalid(errors))
+    }
+}
+
+/// Read a proposal sent as JSON lines, the proposal on the first line and one authorization call per following line.
+/// Blank lines are skipped, lines that are not a valid authorization call are returned as errors.
+pub async fn read_proposal_lines<R: AsyncBufRead + Unpin>(
+    mut reader: R,
+    limit: u64,
+) -> Result<(ImpactRequest, Vec<Result<AuthorizationCall, String>>), PolicyStoreError> {
+    let mut proposal: Option<ImpactRequest> = None;
+    let mut requests = Vec::new();
+    let (mut line, mut number, mut read_bytes) = (Vec::new(), 0, 0);
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line).await;
+        let read = read.map_err(|err| PolicyStoreError::InvalidProposal(err.to_string()))?;
+        if read == 0 {
+            break;
+        }
+        read_bytes += read as u64;
+        if read_bytes > limit {
+            return Err(PolicyStoreError::ProposalTooLarge(limit));
+        }
+        number += 1;
+        let content = line.trim_ascii();
+        if content.is_empty() {
+            continue;
+        }
+        let invalid = |err: serde_json::Error| format!("line {}: {}", number, err);
+        match proposal {
+            None => {
+                let parsed = serde_json::from_slice(content);
+                proposal = Some(parsed.map_err(|err| PolicyStoreError::InvalidProposal(invalid(err)))?);
+            }
+            Some(_) => requests.push(serde_json::from_slice(content).map_err(invalid)),
+        }
+    }
+    let proposal = proposal.ok_or_else(|| PolicyStoreError::InvalidProposal("no proposal given".to_string()))?;
+    Ok((proposal, requests))
+}
+
+/// The requests to evaluate, inline requests first and then the lines read with the proposal.
+pub fn corpus(
+    proposal: &ImpactRequest,
+    lines: Vec<Result<AuthorizationCall, String>>,
+) -> Vec<Result<AuthorizationCall, String>> {
+    proposal.requests.iter().cloned().map(Ok).chain(lines).collect()
+}
+
+fn impact_decision(response: Response) -> ImpactDecision {
+    let mut reasons: Vec<String> = response.diagnostics().reason().map(|r| r.to_string()).collect();
+    reasons.sort();
+    ImpactDecision {
+        decision: match response.decision() {
+            Decision::Allow => DecisionRef::Allow,
+            Decision::Deny => DecisionRef::Deny,
+        },
+        reasons,
+    }
+}
+
+fn request_of(call: &AuthorizationCall, entities: &Entities) -> Result<(Request, Entities), String> {
+    let query: Result<AuthorizationRequest, _> = call.clone().try_into();
+    let query = query.map_err(|err| err.to_string())?;
+    query.get_request_entities(entities.clone()).map_err(|err| err.to_string())
+}
+
+/// Evaluate every request against both policy sets and report the ones answered differently.
+pub fn analyze_impact(
+    current: &PolicySet,
+    proposed: &PolicySet,
+    entities: &Entities,
+    requests: Vec<Result<AuthorizationCall, String>>,
+) -> ImpactReport {
+    info!("Evaluating the impact of a policy change on {} requests", requests.len());
+    let authorizer = Authorizer::new();
+    let mut summary = ImpactSummary::default();
+    let mut changes = Vec::new();
+    let mut errors = Vec::new();
+    for (index, call) in requests.into_iter().enumerate() {
+        let evaluated = call.and_then(|call| {
+            let (request, entities) = request_of(&call, entities)?;
+            Ok((call, request, entities))
+        });
+        let (call, request, entities) = match evaluated {
+            Ok(evaluated) => evaluated,
+            Err(message) => {
+                summary.errors += 1;
+                errors.push(ImpactError { index, message });
+                continue;
+            }
+        };
+        summary.evaluated += 1;
+        let before = impact_decision(authorizer.is_authorized(&request, current, &entities));
+        let after = impact_decision(authorizer.is_authorized(&request, proposed, &entities));
+        if before == after {
+            summary.unchanged += 1;
+            continue;
+        }
+        summary.changed += 1;
+        match (before.decision, after.decision) {
+            (DecisionRef::Allow, DecisionRef::Deny) => summary.allow_to_deny += 1,
+            (DecisionRef::Deny, DecisionRef::Allow) => summary.deny_to_allow += 1,
+            _ => summary.reasons_changed += 1,
+        }
+        changes.push(ImpactChange {
+            index,
+            request: call,
+            current: before,
+            proposed: after,
+        });
+    }
+    ImpactReport {
+        summary,
+        changes,
+        errors,
+    }
+}
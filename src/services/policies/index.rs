@@ -0,0 +1,279 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use cedar_policy::{
+    ActionConstraint, Entities, EntityUid, Policy, PolicyId, PolicySet, PolicySetError, PrincipalConstraint, Request,
+    ResourceConstraint,
+};
+use im::OrdMap;
+
+use crate::services::policies::search::condition_entities;
+
+/// Policies constraining one scope variable, keyed by the entities in their constraint.
+/// Cedar 2 scopes only constrain entities, there are no type constraints to index.
+#[derive(Clone, Default)]
+struct ScopeKeys {
+    /// Unconstrained policies.
+    any: im::HashSet<PolicyId>,
+    /// Policies with `== entity`.
+    eq: OrdMap<EntityUid, im::HashSet<PolicyId>>,
+    /// Policies with `in entity`, or `in [entities]` for actions.
+    within: OrdMap<EntityUid, im::HashSet<PolicyId>>,
+}
+
+enum Scope {
+    Any,
+    Eq(EntityUid),
+    In(Vec<EntityUid>),
+}
+
+impl From<PrincipalConstraint> for Scope {
+    fn from(constraint: PrincipalConstraint) -> Self {
+        match constraint {
+            PrincipalConstraint::Any => Scope::Any,
+            PrincipalConstraint::Eq(entity) => Scope::Eq(entity),
+            PrincipalConstraint::In(entity) => Scope::In(vec![entity]),
+        }
+    }
+}
+
+impl From<ActionConstraint> for Scope {
+    fn from(constraint: ActionConstraint) -> Self {
+        match constraint {
+            ActionConstraint::Any => Scope::Any,
+            ActionConstraint::Eq(entity) => Scope::Eq(entity),
+            ActionConstraint::In(entities) => Scope::In(entities),
+        }
+    }
+}
+
+impl From<ResourceConstraint> for Scope {
+    fn from(constraint: ResourceConstraint) -> Self {
+        match constraint {
+            ResourceConstraint::Any => Scope::Any,
+            ResourceConstraint::Eq(entity) => Scope::Eq(entity),
+            ResourceConstraint::In(entity) => Scope::In(vec![entity]),
+        }
+    }
+}
+
+impl ScopeKeys {
+    fn insert(&mut self, id: &PolicyId, scope: Scope) {
+        match scope {
+            Scope::Any => {
+                self.any.insert(id.clone());
+            }
+            Scope::Eq(entity) => {
+                self.eq.entry(entity).or_default().insert(id.clone());
+            }
+            Scope::In(entities) => {
+                for entity in entities {
+                    self.within.entry(entity).or_default().insert(id.clone());
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, id: &PolicyId, scope: Scope) {
+        fn remove_from(keys: &mut OrdMap<EntityUid, im::HashSet<PolicyId>>, entity: &EntityUid, id: &PolicyId) {
+            if let Some(ids) = keys.get_mut(entity) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    keys.remove(entity);
+                }
+            }
+        }
+        match scope {
+            Scope::Any => {
+                self.any.remove(id);
+            }
+            Scope::Eq(entity) => remove_from(&mut self.eq, &entity, id),
+            Scope::In(entities) => {
+                for entity in entities {
+                    remove_from(&mut self.within, &entity, id);
+                }
+            }
+        }
+    }
+
+    /// Policies whose constraint can hold for `entity`, `None` when every policy can match.
+    /// `in` holds for the entity itself and for all of its ancestors.
+    fn candidates(&self, entity: Option<&EntityUid>, entities: &Entities) -> Option<HashSet<&PolicyId>> {
+        // Unspecified entities are not filtered.
+        let entity = entity?;
+        let mut candidates: HashSet<&PolicyId> = self.any.iter().collect();
+        candidates.extend(self.eq.get(entity).into_iter().flatten());
+        candidates.extend(self.within.get(entity).into_iter().flatten());
+        for ancestor in entities.ancestors(entity).into_iter().flatten() {
+            candidates.extend(self.within.get(ancestor).into_iter().flatten());
+        }
+        Some(candidates)
+    }
+}
+
+/// The most matched policy sets kept before the cache is cleared.
+const MATCHED_LIMIT: usize = 1024;
+
+/// Matched policy sets keyed by their sorted policy ids.
+/// Requests with the same candidates share one set instead of building it again.
+#[derive(Default)]
+struct MatchedSets(Mutex<HashMap<Vec<String>, Arc<PolicySet>>>);
+
+impl MatchedSets {
+    fn get_or_insert(&self, key: Vec<String>, build: impl FnOnce() -> PolicySet) -> Arc<PolicySet> {
+        let mut sets = self.0.lock().unwrap();
+        if let Some(policy_set) = sets.get(&key) {
+            return policy_set.clone();
+        }
+        if sets.len() >= MATCHED_LIMIT {
+            sets.clear();
+        }
+        sets.entry(key).or_insert_with(|| Arc::new(build())).clone()
+    }
+
+    fn clear(&mut self) {
+        self.0.get_mut().unwrap().clear();
+    }
+}
+
+/// A copy starts without cached sets.
+impl Clone for MatchedSets {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+/// A policy set together with an index over the scopes of its policies.
+/// The policies and the index are persistent maps, so copies are cheap and editing a policy takes O(log n).
+/// The cedar set of all policies is only built when it is read, once per change.
+#[derive(Clone, Default)]
+pub struct IndexedPolicySet {
+    policy_set: OnceLock<Arc<PolicySet>>,
+    policies: im::HashMap<PolicyId, Policy>,
+    principals: ScopeKeys,
+    actions: ScopeKeys,
+    resources: ScopeKeys,
+    /// The entities named in the conditions, with the number of policies naming them.
+    condition_entities: OrdMap<EntityUid, usize>,
+    matched: MatchedSets,
+}
+
+impl IndexedPolicySet {
+    /// The cedar set of all policies, built on the first read after a change.
+    pub fn policy_set(&self) -> Arc<PolicySet> {
+        self.policy_set
+            .get_or_init(|| {
+                let mut policy_set = PolicySet::new();
+                for policy in self.policies() {
+                    // The ids are distinct already.
+                    policy_set.add(policy.clone()).unwrap();
+                }
+                Arc::new(policy_set)
+            })
+            .clone()
+    }
+
+    pub fn contains(&self, id: &PolicyId) -> bool {
+        self.policies.contains_key(id)
+    }
+
+    pub fn policies(&self) -> impl Iterator<Item = &Policy> {
+        self.policies.values()
+    }
+
+    /// The entities named in the conditions of the policies.
+    pub fn condition_entities(&self) -> impl Iterator<Item = &EntityUid> {
+        self.condition_entities.keys()
+    }
+
+    pub fn add(&mut self, policy: Policy) -> Result<(), PolicySetError> {
+        if self.contains(policy.id()) {
+            return Err(PolicySetError::AlreadyDefined);
+        }
+        self.changed();
+        self.index(policy);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, ids: &[PolicyId]) {
+        for id in ids {
+            let Some(policy) = self.policies.remove(id) else {
+                continue;
+            };
+            self.principals.remove(id, policy.principal_constraint().into());
+            self.actions.remove(id, policy.action_constraint().into());
+            self.resources.remove(id, policy.resource_constraint().into());
+            for uid in condition_entities(&policy) {
+                if let Some(count) = self.condition_entities.get_mut(&uid) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.condition_entities.remove(&uid);
+                    }
+                }
+            }
+        }
+        self.changed();
+    }
+
+    /// Drop the sets built from the previous policies.
+    fn changed(&mut self) {
+        self.policy_set = OnceLock::new();
+        self.matched.clear();
+    }
+
+    fn index(&mut self, policy: Policy) {
+        let id = policy.id().clone();
+        self.principals.insert(&id, policy.principal_constraint().into());
+        self.actions.insert(&id, policy.action_constraint().into());
+        self.resources.insert(&id, policy.resource_constraint().into());
+        for uid in condition_entities(&policy) {
+            *self.condition_entities.entry(uid).or_default() += 1;
+        }
+        self.policies.insert(id, policy);
+    }
+
+    /// The policies that could apply to the request, given the ancestors of its entities.
+    /// Policies left out have a scope that doesn't hold, so they can't affect the decision.
+    /// The sets are cached until the policies change.
+    pub fn matching(&self, request: &Request, entities: &Entities) -> Arc<PolicySet> {
+        let mut scopes: Vec<HashSet<&PolicyId>> = [
+            self.principals.candidates(request.principal(), entities),
+            self.actions.candidates(request.action(), entities),
+            self.resources.candidates(request.resource(), entities),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if scopes.is_empty() {
+            return self.policy_set();
+        }
+
+        scopes.sort_by_key(|candidates| candidates.len());
+        let (smallest, others) = scopes.split_first().unwrap();
+        let mut ids: Vec<&PolicyId> = smallest
+            .iter()
+            .filter(|id| others.iter().all(|other| other.contains(*id)))
+            .copied()
+            .collect();
+        ids.sort_by_cached_key(|id| id.to_string());
+        let key = ids.iter().map(|id| id.to_string()).collect();
+        self.matched.get_or_insert(key, || {
+            let mut policy_set = PolicySet::new();
+            for id in ids {
+                policy_set.add(self.policies[id].clone()).unwrap();
+            }
+            policy_set
+        })
+    }
+}
+
+impl From<PolicySet> for IndexedPolicySet {
+    fn from(policy_set: PolicySet) -> Self {
+        let mut indexed = IndexedPolicySet::default();
+        for policy in policy_set.policies() {
+            indexed.index(policy.clone());
+        }
+        indexed.policy_set = OnceLock::from(Arc::new(policy_set));
+        indexed
+    }
+}
@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::error::Error;
 use std::fs::File;
 use std::io::Read;
@@ -18,8 +19,8 @@ pub struct InitPoliciesFairing;
 
 pub(crate) async fn init(
     conf: &config::Config,
-    policy_store: &Box<dyn PolicyStore>,
-    schema_store: &Box<dyn SchemaStore>
+    policy_store: &Arc<dyn PolicyStore>,
+    schema_store: &Arc<dyn SchemaStore>
 ) {
     if conf.policies.is_none() {
         return;
@@ -36,7 +37,7 @@ pub(crate) async fn init(
     };
 
     let schema = schema_store.get_cedar_schema().await;
-    match policy_store.update_policies(policies.into_inner(), schema).await {
+    match policy_store.update_policies(policies.into_inner(), schema, conf.validation_settings()).await {
         Ok(policies) => {
             info!("Successfully updated policies from file {}: {} policies", &file_path.display(), policies.len());
         }
@@ -92,8 +93,8 @@ impl Fairing for InitPoliciesFairing {
 
         init(
             config.unwrap(),
-            rocket.state::<Box<dyn PolicyStore>>().unwrap(),
-            rocket.state::<Box<dyn SchemaStore>>().unwrap()
+            rocket.state::<Arc<dyn PolicyStore>>().unwrap(),
+            rocket.state::<Arc<dyn SchemaStore>>().unwrap()
         ).await;
 
         Ok(rocket)
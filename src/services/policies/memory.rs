@@ -1,94 +1,422 @@
-use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::mem;
+use std::str::FromStr;
+use std::sync::Arc;
 
-use async_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use async_lock::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use async_trait::async_trait;
-use cedar_policy::{PolicySet, PolicySetError, Schema, Validator, ValidationMode, ValidationResult};
-use log::{debug, info};
+use cedar_policy::{
+    Entities, EntityUid, PolicyId, PolicySet, PolicySetError, Request, Schema, Validator, ValidationResult,
+};
+use chrono::{DateTime, Utc};
+use im::OrdMap;
+use log::{debug, error, info, warn};
 
 use crate::common;
-use crate::schemas::policies::{Policy, PolicyUpdate};
+use crate::schemas::policies::{
+    Policy, PolicyError, PolicyFilter, PolicyMetadata, PolicyOperation, PolicyOperationResult,
+    PolicyOperationStatus, PolicySchedule, PolicyTransition, PolicyTransitionKind, PolicyUpdate,
+    ValidationLevel, ValidationSettings,
+};
 use crate::services::policies::errors::PolicyStoreError;
+use crate::services::policies::ids::generate_policy_id;
+use crate::services::policies::index::IndexedPolicySet;
 use crate::services::policies::PolicyStore;
+use crate::services::staged::{StagedChange, StagedSwap};
 
-pub struct Policies(HashMap<String, cedar_policy::Policy>, PolicySet);
+#[derive(Clone)]
+struct StoredPolicy {
+    policy: cedar_policy::Policy,
+    /// The submitted policy text, source spans reported by Cedar point into it.
+    source: String,
+    metadata: PolicyMetadata,
+    enabled: bool,
+    schedule: PolicySchedule,
+    /// Errors of a policy accepted in `warn` validation mode.
+    validation_errors: Vec<PolicyError>,
+}
+
+impl StoredPolicy {
+    fn parse(policy: &Policy) -> Result<Self, PolicyStoreError> {
+        if !policy.schedule.is_valid() {
+            return Err(PolicyStoreError::InvalidSchedule(policy.id.clone()));
+        }
+        match policy.try_into() {
+            Ok(p) => Ok(Self {
+                policy: p,
+                source: policy.content.clone(),
+                metadata: policy.metadata.clone(),
+                enabled: policy.enabled,
+                schedule: policy.schedule.clone(),
+                validation_errors: Vec::new(),
+            }),
+            Err(err) => Err(PolicyStoreError::invalid(
+                PolicyError::from_parse_errors(&policy.id, &policy.content, &err),
+            )),
+        }
+    }
+}
+
+impl StoredPolicy {
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.enabled && self.schedule.is_active(now)
+    }
+}
+
+impl From<Arc<StoredPolicy>> for Policy {
+    fn from(stored: Arc<StoredPolicy>) -> Self {
+        let stored = Arc::unwrap_or_clone(stored);
+        Policy {
+            enabled: stored.enabled,
+            schedule: stored.schedule,
+            validation_errors: stored.validation_errors,
+            ..Policy::from(stored.policy).with_metadata(stored.metadata)
+        }
+    }
+}
+
+type StoredPolicies = OrdMap<String, Arc<StoredPolicy>>;
+
+/// The stored policies with the set of active ones, both persistent so copying them is cheap.
+#[derive(Clone)]
+pub struct Policies(StoredPolicies, IndexedPolicySet);
 
 impl Policies {
     fn new() -> Self {
         Self {
-            0: HashMap::new(),
-            1: PolicySet::new(),
+            0: OrdMap::new(),
+            1: IndexedPolicySet::default(),
         }
     }
 
-    #[allow(dead_code)]
-    fn policy_map(&self) -> HashMap<String, cedar_policy::Policy> {
+    fn policy_map(&self) -> StoredPolicies {
         self.0.clone()
     }
 
     fn policy_set(&self) -> PolicySet {
-        self.1.clone()
+        PolicySet::clone(&self.1.policy_set())
+    }
+
+    /// Store a policy, replacing the one with the same id.
+    fn put(&mut self, id: String, stored: Arc<StoredPolicy>) -> Result<(), PolicySetError> {
+        self.1.remove(&[PolicyId::from_str(&id).unwrap()]);
+        if stored.is_active(Utc::now()) {
+            self.1.add(stored.policy.clone())?;
+        }
+        self.0.insert(id, stored);
+        Ok(())
     }
 
-    fn update_policy_set(&mut self) {
+    fn take(&mut self, id: &str) -> Option<Arc<StoredPolicy>> {
+        self.1.remove(&[PolicyId::from_str(id).unwrap()]);
+        self.0.remove(id)
+    }
+
+    /// The set used for evaluation, disabled policies and policies outside of their schedule are left out.
+    fn active_policy_set(
+        policies: &StoredPolicies,
+        now: DateTime<Utc>,
+    ) -> Result<IndexedPolicySet, PolicySetError> {
+        let mut policy_set = IndexedPolicySet::default();
+        for stored in policies.values().filter(|stored| stored.is_active(now)) {
+            policy_set.add(stored.policy.clone())?;
+        }
+        Ok(policy_set)
+    }
+
+    /// Enabled policies whose schedule disagrees with their presence in the active set.
+    fn due_transitions(&self, now: DateTime<Utc>) -> Vec<(String, bool)> {
+        self.0
+            .iter()
+            .filter(|(_, stored)| stored.enabled)
+            .filter_map(|(id, stored)| {
+                let active = stored.schedule.is_active(now);
+                let included = self.1.contains(&PolicyId::from_str(id).unwrap());
+                (active != included).then(|| (id.clone(), active))
+            })
+            .collect()
+    }
+
+    fn parse_operation_policy(index: usize, policy: &Policy) -> Result<StoredPolicy, PolicyStoreError> {
+        match StoredPolicy::parse(policy) {
+            Ok(stored) => Ok(stored),
+            Err(err) => Err(PolicyStoreError::PolicyOperationFailed(index, err.to_string())),
+        }
+    }
+
+    fn validate_policy(
+        stored: &mut StoredPolicy,
+        schema: &Option<Schema>,
+        validation: ValidationSettings,
+    ) -> Result<(), PolicyStoreError> {
+        // Copy the policy into its own set to pass to a validator.
+        let mut validation_set = PolicySet::new();
+        validation_set.add(stored.policy.clone()).unwrap();
+        stored.validation_errors = Self::validate_policy_set(
+            &validation_set,
+            |_| Some(stored.source.as_str()),
+            schema,
+            validation,
+        )?;
+        Ok(())
+    }
+
+    fn validate_policies(
+        policies: &mut StoredPolicies,
+        schema: &Option<Schema>,
+        validation: ValidationSettings,
+    ) -> Result<IndexedPolicySet, PolicyStoreError> {
         let mut policy_set = PolicySet::new();
-        for policy in self.0.values() {
-            policy_set.add(policy.clone()).unwrap();
-        }
-        self.1 = policy_set;
-    }
-
-    fn validate_policy(policy: &cedar_policy::Policy, schema: &Option<Schema>) -> Result<(), PolicyStoreError> {
-        match schema {
-            Some(schema) => {
-                // Copy the policy into its own set to pass to a validator.
-                let mut validation_set = PolicySet::new();
-                validation_set.add(policy.clone()).unwrap();
-                let validator = Validator::new(schema.clone());
-                let validation_result = Validator::validate(
-                    &validator,
-                    &validation_set,
-                    ValidationMode::default()
-                );
-
-                if ValidationResult::validation_passed(&validation_result) {
-                    Ok(())
-                } else {
-                    let errs = ValidationResult::validation_errors(&validation_result);
-                    let mut error_msg = String::from("");
-                    for e in errs {
-                        error_msg += &*format!("{}; ", e);
-                    }
-                    Err(PolicyStoreError::PolicyInvalid(policy.id().to_string(), error_msg).into())
-                }
-            },
-            None => Ok(())
+        for stored in policies.values() {
+            policy_set.add(stored.policy.clone())?;
         }
+        let errors = Self::validate_policy_set(
+            &policy_set,
+            |id| policies.get(id).map(|stored| stored.source.as_str()),
+            schema,
+            validation,
+        )?;
 
+        let mut policy_errors: HashMap<String, Vec<PolicyError>> = HashMap::new();
+        for error in errors {
+            policy_errors.entry(error.policy_id.clone()).or_default().push(error);
+        }
+        for (id, stored) in policies.clone() {
+            let validation_errors = policy_errors.remove(&id).unwrap_or_default();
+            if !validation_errors.is_empty() || !stored.validation_errors.is_empty() {
+                let stored = StoredPolicy {
+                    validation_errors,
+                    ..StoredPolicy::clone(&stored)
+                };
+                policies.insert(id, Arc::new(stored));
+            }
+        }
+        // Inactive policies are validated too, so they can become active again safely.
+        Ok(Self::active_policy_set(policies, Utc::now())?)
+    }
+
+    /// Validate the set according to the validation level.
+    /// Invalid policies are rejected in `enforce` mode, in `warn` mode their errors are returned.
+    fn validate_policy_set<'a>(
+        policy_set: &PolicySet,
+        source: impl Fn(&str) -> Option<&'a str>,
+        schema: &Option<Schema>,
+        validation: ValidationSettings,
+    ) -> Result<Vec<PolicyError>, PolicyStoreError> {
+        let schema = match (schema, validation.level()) {
+            (Some(schema), ValidationLevel::Warn | ValidationLevel::Enforce) => schema,
+            _ => return Ok(Vec::new()),
+        };
+        let validator = Validator::new(schema.clone());
+        let validation_result = Validator::validate(
+            &validator,
+            policy_set,
+            validation.mode().into()
+        );
+
+        if ValidationResult::validation_passed(&validation_result) {
+            return Ok(Vec::new());
+        }
+        let mut errors: Vec<PolicyError> = Vec::new();
+        for e in ValidationResult::validation_errors(&validation_result) {
+            let content = source(&e.location().policy_id().to_string()).unwrap_or_default();
+            errors.push(PolicyError::from_validation_error(content, e));
+        }
+        if validation.level() == ValidationLevel::Enforce {
+            return Err(PolicyStoreError::invalid(errors));
+        }
+        for error in &errors {
+            warn!("Accepting invalid policy {}: {}", error.policy_id, error.message);
+        }
+        Ok(errors)
     }
 }
 
+/// Mutations are serialized by the `writer` lock. Writers edit a copy of the policies while holding it,
+/// readers are only blocked for the final swap under the write lock.
 pub struct MemoryPolicyStore {
     policies: RwLock<Policies>,
+    writer: Mutex<()>,
+    max_policies: Option<usize>,
+    id_pattern: Option<String>,
 }
 
 impl MemoryPolicyStore {
     pub fn new() -> Self {
         Self {
             policies: RwLock::new(Policies::new()),
+            writer: Mutex::new(()),
+            max_policies: None,
+            id_pattern: None,
+        }
+    }
+
+    /// Reject changes that would store more than `max_policies` policies.
+    pub fn with_max_policies(mut self, max_policies: Option<usize>) -> Self {
+        self.max_policies = max_policies;
+        self
+    }
+
+    /// Generate ids of policies created without one from `id_pattern`, see `generate_policy_id`.
+    pub fn with_id_pattern(mut self, id_pattern: Option<String>) -> Self {
+        self.id_pattern = id_pattern;
+        self
+    }
+
+    /// Give a policy submitted without an id a generated one.
+    fn assign_id(&self, stored: &mut StoredPolicy, taken: impl Fn(&str) -> bool) -> String {
+        let id = generate_policy_id(self.id_pattern.as_deref(), &stored.policy, taken);
+        stored.policy = stored.policy.new_id(PolicyId::from_str(&id).unwrap());
+        id
+    }
+
+    fn check_quota(&self, policies: usize) -> Result<(), PolicyStoreError> {
+        match self.max_policies {
+            Some(max) if policies > max => Err(PolicyStoreError::QuotaExceeded(max)),
+            _ => Ok(()),
         }
     }
 
-    async fn read(&self) -> RwLockReadGuard<Policies> {
+    async fn read(&self) -> RwLockReadGuard<'_, Policies> {
         debug!("Trying to acquire read lock on policies");
         self.policies.read().await
     }
 
-    async fn write(&self) -> RwLockWriteGuard<Policies> {
+    async fn write(&self) -> RwLockWriteGuard<'_, Policies> {
         debug!("Trying to acquire write lock on policies");
         self.policies.write().await
     }
+
+    async fn writer(&self) -> MutexGuard<'_, ()> {
+        debug!("Trying to acquire writer lock on policies");
+        self.writer.lock().await
+    }
+
+    /// Swap in the edited copy of the policies, the replaced ones are dropped after releasing the write lock.
+    async fn store(&self, policies: Policies) {
+        let replaced = mem::replace(&mut *self.write().await, policies);
+        debug!("Released write lock on policies");
+        drop(replaced);
+    }
+
+    /// The policies replacing all the stored ones, parsed, given ids and validated, with their active set.
+    fn replacing(
+        &self,
+        policies: Vec<Policy>,
+        schema: &Option<Schema>,
+        validation: ValidationSettings,
+    ) -> Result<(StoredPolicies, IndexedPolicySet), Box<dyn Error>> {
+        let mut new_policies = StoredPolicies::new();
+        let mut without_id: Vec<StoredPolicy> = Vec::new();
+        let mut unparsed_ids: Vec<String> = Vec::new();
+        let mut unparsed = 0;
+        let mut errors: Vec<PolicyError> = Vec::new();
+        for policy in policies {
+            if new_policies.contains_key(&policy.id) || unparsed_ids.contains(&policy.id) {
+                return Err(PolicySetError::AlreadyDefined.into());
+            }
+            match StoredPolicy::parse(&policy) {
+                Ok(stored) if policy.id.is_empty() => without_id.push(stored),
+                Ok(stored) => {
+                    new_policies.insert(policy.id, Arc::new(stored));
+                }
+                Err(PolicyStoreError::PolicyInvalid(_, parse_errors)) => {
+                    if !policy.id.is_empty() {
+                        unparsed_ids.push(policy.id);
+                    }
+                    unparsed += 1;
+                    errors.extend(parse_errors);
+                }
+                Err(err) => return Err(err.into()),
+            };
+        }
+        // Ids are generated once every submitted id is known, so they can't clash.
+        for mut stored in without_id {
+            let id = self.assign_id(&mut stored, |id| {
+                new_policies.contains_key(id) || unparsed_ids.iter().any(|unparsed| unparsed == id)
+            });
+            new_policies.insert(id, Arc::new(stored));
+        }
+
+        self.check_quota(new_policies.len() + unparsed)?;
+
+        // Validate the whole set at once so every failing policy is reported.
+        let policy_set = match Policies::validate_policies(&mut new_policies, schema, validation) {
+            Ok(policy_set) if errors.is_empty() => policy_set,
+            Ok(_) => return Err(PolicyStoreError::invalid(errors).into()),
+            Err(PolicyStoreError::PolicyInvalid(_, validation_errors)) => {
+                errors.extend(validation_errors);
+                return Err(PolicyStoreError::invalid(errors).into());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        Ok((new_policies, policy_set))
+    }
+
+    /// Apply the operations to `policies`, returns the result of each of them.
+    /// Policies created without an id get a generated one.
+    fn operate(
+        &self,
+        policies: &mut StoredPolicies,
+        operations: Vec<PolicyOperation>,
+    ) -> Result<Vec<PolicyOperationResult>, PolicyStoreError> {
+        // Ids are generated knowing every id the operations name, so they can't clash.
+        let named: HashSet<String> = operations
+            .iter()
+            .map(|operation| match operation {
+                PolicyOperation::Create(policy) => policy.id.clone(),
+                PolicyOperation::Upsert(upsert) => upsert.id.clone(),
+                PolicyOperation::Delete { id } => id.clone(),
+            })
+            .collect();
+        let mut results = Vec::with_capacity(operations.len());
+        for (index, operation) in operations.into_iter().enumerate() {
+            let (policy, replace) = match operation {
+                PolicyOperation::Create(policy) => (policy, false),
+                PolicyOperation::Upsert(upsert) => {
+                    let existing = policies.get(&upsert.id).cloned().map(Policy::from);
+                    (upsert.into_policy(existing.as_ref()), true)
+                }
+                PolicyOperation::Delete { id } => match policies.remove(&id) {
+                    Some(policy) => {
+                        results.push(PolicyOperationResult {
+                            id,
+                            status: PolicyOperationStatus::Deleted,
+                            policy: Policy::from(policy),
+                        });
+                        continue;
+                    }
+                    None => return Err(PolicyStoreError::PolicyOperationFailed(
+                        index,
+                        PolicyStoreError::PolicyNotFoundError(id).to_string(),
+                    )),
+                },
+            };
+            if !replace && policies.contains_key(&policy.id) {
+                return Err(PolicyStoreError::PolicyOperationFailed(
+                    index,
+                    format!("policy {} already exists", policy.id),
+                ));
+            }
+            let mut stored = Policies::parse_operation_policy(index, &policy)?;
+            let id = match policy.id.is_empty() {
+                true => self.assign_id(&mut stored, |id| policies.contains_key(id) || named.contains(id)),
+                false => policy.id,
+            };
+            let stored = Arc::new(stored);
+            let status = match policies.insert(id.clone(), stored.clone()) {
+                Some(_) => PolicyOperationStatus::Updated,
+                None => PolicyOperationStatus::Created,
+            };
+            results.push(PolicyOperationResult {
+                id,
+                status,
+                policy: Policy::from(stored),
+            });
+        }
+        Ok(results)
+    }
 }
 
 #[async_trait]
@@ -98,10 +426,44 @@ impl PolicyStore for MemoryPolicyStore {
         lock.policy_set()
     }
 
+    async fn matching_policy_set(&self, request: &Request, entities: &Entities) -> Arc<PolicySet> {
+        let lock = self.read().await;
+        lock.1.matching(request, entities)
+    }
+
+    async fn condition_entities(&self) -> Vec<EntityUid> {
+        let lock = self.read().await;
+        lock.1.condition_entities().cloned().collect()
+    }
+
     async fn get_policies(&self) -> Vec<Policy> {
         info!("Getting policies");
         let lock = self.read().await;
-        Vec::from_iter(lock.0.values().cloned().map(|p| Policy::from(p)))
+        Vec::from_iter(lock.0.values().cloned().map(Policy::from))
+    }
+
+    async fn find_policies(&self, filter: &PolicyFilter) -> Vec<Policy> {
+        info!("Getting policies matching {:?}", filter);
+        let lock = self.read().await;
+        Vec::from_iter(
+            lock.0
+                .values()
+                .filter(|stored| filter.matches(&stored.policy, &stored.metadata))
+                .cloned()
+                .map(Policy::from),
+        )
+    }
+
+    async fn get_invalid_policies(&self) -> Vec<Policy> {
+        info!("Getting policies accepted with validation errors");
+        let lock = self.read().await;
+        Vec::from_iter(
+            lock.0
+                .values()
+                .filter(|stored| !stored.validation_errors.is_empty())
+                .cloned()
+                .map(Policy::from),
+        )
     }
 
     async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
@@ -117,57 +479,58 @@ impl PolicyStore for MemoryPolicyStore {
     async fn create_policy(
         &self,
         policy: &Policy,
-        schema: Option<Schema>
+        schema: Option<Schema>,
+        validation: ValidationSettings,
     ) -> Result<Policy, Box<dyn Error>> {
         info!("Creating policy {}", policy.id);
-        let mut lock = self.write().await;
-        let stored_policy = lock.0.get(&policy.id);
-        match stored_policy {
-            Some(_) => Err(PolicySetError::AlreadyDefined.into()),
-            None => {
-                let policy: cedar_policy::Policy = match policy.try_into() {
-                    Ok(p) => p,
-                    Err(err) => return Err(PolicyStoreError::PolicyParseError(err).into()),
-                };
-                Policies::validate_policy(&policy, &schema)?;
-
-                let policy_id = policy.id().to_string();
-                lock.0.insert(policy_id.clone(), policy);
-                lock.update_policy_set();
-                Ok(Policy::from(
-                    lock.0.get(policy_id.as_str()).unwrap().clone(),
-                ))
-            }
+        let _writer = self.writer().await;
+        let mut stored = StoredPolicy::parse(policy)?;
+        let mut policies = self.read().await.clone();
+        if policies.0.contains_key(&policy.id) {
+            return Err(PolicySetError::AlreadyDefined.into());
         }
+        self.check_quota(policies.0.len() + 1)?;
+        let id = if policy.id.is_empty() {
+            self.assign_id(&mut stored, |id| policies.0.contains_key(id))
+        } else {
+            policy.id.clone()
+        };
+        Policies::validate_policy(&mut stored, &schema, validation)?;
+
+        let stored = Arc::new(stored);
+        policies.put(id, stored.clone())?;
+        self.store(policies).await;
+        Ok(Policy::from(stored))
     }
 
     async fn update_policies(
         &self,
         policies: Vec<Policy>,
-        schema: Option<Schema>
+        schema: Option<Schema>,
+        validation: ValidationSettings,
     ) -> Result<Vec<Policy>, Box<dyn Error>> {
         info!("Updating policies");
-        let mut lock = self.write().await;
-        let mut new_policies: HashMap<String, cedar_policy::Policy> = HashMap::new();
-        for policy in policies {
-            match new_policies.get(&policy.id) {
-                Some(_) => return Err(PolicySetError::AlreadyDefined.into()),
-                None => {
-                    let policy: cedar_policy::Policy = match policy.borrow().try_into() {
-                        Ok(p) => p,
-                        Err(err) => return Err(err.into()),
-                    };
-                    Policies::validate_policy(&policy, &schema)?;
-
-                    new_policies.insert(policy.id().to_string(), policy)
-                }
-            };
-        }
-        lock.0 = new_policies;
-        lock.update_policy_set();
-        Ok(Vec::from_iter(
-            lock.0.values().cloned().map(|p| Policy::from(p)),
-        ))
+        let _writer = self.writer().await;
+        let (new_policies, policy_set) = self.replacing(policies, &schema, validation)?;
+        let policies = Vec::from_iter(new_policies.values().cloned().map(Policy::from));
+
+        self.store(Policies(new_policies, policy_set)).await;
+        Ok(policies)
+    }
+
+    async fn stage_policies(
+        &self,
+        policies: Vec<Policy>,
+        schema: Option<Schema>,
+        validation: ValidationSettings,
+    ) -> Result<(Vec<Policy>, Box<dyn StagedChange + '_>), Box<dyn Error>> {
+        info!("Staging policies");
+        let (new_policies, policy_set) = self.replacing(policies, &schema, validation)?;
+        let policies = Vec::from_iter(new_policies.values().cloned().map(Policy::from));
+        let staged = StagedSwap::new(&self.policies, Some(&self.writer), move |stored: &mut Policies| {
+            *stored = Policies(new_policies, policy_set);
+        });
+        Ok((policies, Box::new(staged)))
     }
 
     async fn update_policy(
@@ -175,33 +538,137 @@ impl PolicyStore for MemoryPolicyStore {
         id: String,
         policy_update: PolicyUpdate,
         schema: Option<Schema>,
+        validation: ValidationSettings,
     ) -> Result<Policy, Box<dyn Error>> {
         info!("Updating policy {}", id);
-        let mut lock = self.write().await;
-        let policy = Policy::from_policy_update(id.clone(), policy_update);
-        let policy: cedar_policy::Policy = match policy.borrow().try_into() {
-            Ok(p) => p,
-            Err(err) => return Err(err.into()),
+        let _writer = self.writer().await;
+        let mut policies = self.read().await.clone();
+        let existing = policies.0.get(&id).cloned();
+        if existing.is_none() {
+            self.check_quota(policies.0.len() + 1)?;
+        }
+        let policy = Policy::updated(existing.map(Policy::from), id.clone(), policy_update);
+        let mut stored = StoredPolicy::parse(&policy)?;
+        Policies::validate_policy(&mut stored, &schema, validation)?;
+
+        let stored = Arc::new(stored);
+        policies.put(id, stored.clone())?;
+        self.store(policies).await;
+        Ok(Policy::from(stored))
+    }
+
+    async fn set_policy_enabled(&self, id: &str, enabled: bool) -> Result<Policy, Box<dyn Error>> {
+        info!("{} policy {}", if enabled { "Enabling" } else { "Disabling" }, id);
+        let _writer = self.writer().await;
+        let mut policies = self.read().await.clone();
+        let mut stored = match policies.0.get(id) {
+            Some(stored) => StoredPolicy::clone(stored),
+            None => return Err(PolicyStoreError::PolicyNotFoundError(id.to_owned()).into()),
         };
-        Policies::validate_policy(&policy, &schema)?;
+        stored.enabled = enabled;
+
+        let stored = Arc::new(stored);
+        policies.put(id.to_owned(), stored.clone())?;
+        self.store(policies).await;
+        Ok(Policy::from(stored))
+    }
+
+    async fn refresh_policy_schedule(&self) {
+        let now = Utc::now();
+        if self.read().await.due_transitions(now).is_empty() {
+            return;
+        }
+        let _writer = self.writer().await;
+        let mut policies = self.read().await.clone();
+        let transitions = policies.due_transitions(now);
+        let expired: Vec<PolicyId> = transitions
+            .iter()
+            .filter(|(_, active)| !active)
+            .map(|(id, _)| PolicyId::from_str(id).unwrap())
+            .collect();
+        policies.1.remove(&expired);
+        for (id, active) in transitions {
+            let stored = &policies.0[&id];
+            if active {
+                info!("Activating policy {}, scheduled from {:?}", id, stored.schedule.not_before);
+                let policy = stored.policy.clone();
+                if let Err(err) = policies.1.add(policy) {
+                    error!("Unable to activate policy {}: {}", id, err);
+                }
+            } else {
+                info!("Deactivating policy {}, scheduled until {:?}", id, stored.schedule.not_after);
+            }
+        }
+        self.store(policies).await;
+    }
 
-        *lock
-            .0
-            .entry(String::from(id))
-            .or_insert_with(|| policy.clone()) = policy.clone();
-        lock.update_policy_set();
-        Ok(Policy::from(policy))
+    async fn get_policy_transitions(&self) -> Vec<PolicyTransition> {
+        info!("Getting upcoming policy transitions");
+        let now = Utc::now();
+        let lock = self.read().await;
+        let mut transitions = Vec::new();
+        for (id, stored) in lock.0.iter().filter(|(_, stored)| stored.enabled) {
+            if let Some(not_before) = stored.schedule.not_before.filter(|at| *at > now) {
+                transitions.push(PolicyTransition {
+                    policy_id: id.clone(),
+                    kind: PolicyTransitionKind::Activation,
+                    at: not_before,
+                });
+            }
+            if let Some(not_after) = stored.schedule.not_after.filter(|at| *at > now) {
+                transitions.push(PolicyTransition {
+                    policy_id: id.clone(),
+                    kind: PolicyTransitionKind::Expiration,
+                    at: not_after,
+                });
+            }
+        }
+        transitions.sort_by(|a, b| a.at.cmp(&b.at).then_with(|| a.policy_id.cmp(&b.policy_id)));
+        transitions
     }
 
     async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
         info!("Deleting policy {}", id);
-        let mut lock = self.write().await;
-        match lock.0.remove(id) {
+        let _writer = self.writer().await;
+        let mut policies = self.read().await.clone();
+        match policies.take(id) {
             Some(policy) => {
-                lock.update_policy_set();
+                self.store(policies).await;
                 Ok(Policy::from(policy))
             }
             None => Err(common::EmptyError.into()),
         }
     }
+
+    async fn apply_policy_operations(
+        &self,
+        operations: Vec<PolicyOperation>,
+        schema: Option<Schema>,
+        validation: ValidationSettings,
+    ) -> Result<Vec<PolicyOperationResult>, Box<dyn Error>> {
+        info!("Applying {} policy operations", operations.len());
+        let _writer = self.writer().await;
+        // Work on a copy so a failing operation leaves the stored policies untouched.
+        let mut new_policies = self.read().await.policy_map();
+        let mut results = self.operate(&mut new_policies, operations)?;
+        self.check_quota(new_policies.len())?;
+        let policy_set = Policies::validate_policies(&mut new_policies, &schema, validation)?;
+        // Report the validation errors of policies accepted in warn mode.
+        for result in results.iter_mut() {
+            if let (PolicyOperationStatus::Created | PolicyOperationStatus::Updated, Some(stored)) =
+                (&result.status, new_policies.get(&result.id))
+            {
+                result.policy = Policy::from(stored.clone());
+            }
+        }
+
+        self.store(Policies(new_policies, policy_set)).await;
+        Ok(results)
+    }
+
+    async fn candidate_policy_operations(&self, operations: &[PolicyOperation]) -> Result<PolicySet, Box<dyn Error>> {
+        let mut new_policies = self.read().await.policy_map();
+        self.operate(&mut new_policies, operations.to_vec())?;
+        Ok(PolicySet::clone(&Policies::active_policy_set(&new_policies, Utc::now())?.policy_set()))
+    }
 }
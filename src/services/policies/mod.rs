@@ -1,34 +1,73 @@
 use std::error::Error;
+use std::sync::Arc;
 
 use async_trait::async_trait;
-use cedar_policy::{PolicySet, Schema};
+use cedar_policy::{Entities, EntityUid, PolicySet, Request, Schema};
 
-use crate::schemas::policies::{Policy, PolicyUpdate};
+use crate::services::staged::StagedChange;
+use crate::schemas::policies::{
+    Policy, PolicyFilter, PolicyOperation, PolicyOperationResult, PolicyTransition, PolicyUpdate,
+    ValidationSettings,
+};
 
-pub(crate) mod errors;
+pub mod analysis;
+pub mod errors;
+pub mod ids;
+pub mod impact;
+pub mod index;
 pub mod memory;
+pub mod schedule;
+pub mod search;
 pub mod load_from_file;
 
 #[async_trait]
 pub trait PolicyStore: Send + Sync {
     async fn policy_set(&self) -> PolicySet;
+    /// The policies whose scope could match the request, given the ancestors in `entities`.
+    async fn matching_policy_set(&self, request: &Request, entities: &Entities) -> Arc<PolicySet>;
+    /// The entities named in the conditions of the active policies.
+    async fn condition_entities(&self) -> Vec<EntityUid>;
     async fn get_policies(&self) -> Vec<Policy>;
+    async fn find_policies(&self, filter: &PolicyFilter) -> Vec<Policy>;
+    async fn get_invalid_policies(&self) -> Vec<Policy>;
     async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>>;
     async fn create_policy(
         &self,
         policy: &Policy,
-        schema: Option<Schema>
+        schema: Option<Schema>,
+        validation: ValidationSettings,
     ) -> Result<Policy, Box<dyn Error>>;
     async fn update_policies(
         &self,
         policies: Vec<Policy>,
-        schema: Option<Schema>
+        schema: Option<Schema>,
+        validation: ValidationSettings,
     ) -> Result<Vec<Policy>, Box<dyn Error>>;
+    /// Check the policies replacing the stored ones, the staged change stores them.
+    async fn stage_policies(
+        &self,
+        policies: Vec<Policy>,
+        schema: Option<Schema>,
+        validation: ValidationSettings,
+    ) -> Result<(Vec<Policy>, Box<dyn StagedChange + '_>), Box<dyn Error>>;
     async fn update_policy(
         &self,
         id: String,
         policy: PolicyUpdate,
         schema: Option<Schema>,
+        validation: ValidationSettings,
     ) -> Result<Policy, Box<dyn Error>>;
+    async fn set_policy_enabled(&self, id: &str, enabled: bool) -> Result<Policy, Box<dyn Error>>;
+    /// Bring the active policy set in line with the policy schedules.
+    async fn refresh_policy_schedule(&self);
+    async fn get_policy_transitions(&self) -> Vec<PolicyTransition>;
     async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>>;
+    async fn apply_policy_operations(
+        &self,
+        operations: Vec<PolicyOperation>,
+        schema: Option<Schema>,
+        validation: ValidationSettings,
+    ) -> Result<Vec<PolicyOperationResult>, Box<dyn Error>>;
+    /// The active policy set resulting from applying the operations, without storing them.
+    async fn candidate_policy_operations(&self, operations: &[PolicyOperation]) -> Result<PolicySet, Box<dyn Error>>;
 }
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::info;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::tokio::time::{interval, MissedTickBehavior};
+use rocket::{tokio, Orbit, Rocket};
+
+use crate::services::policies::PolicyStore;
+use crate::services::tenants::TenantStore;
+
+/// How often the policy schedules are checked.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps the active policy sets of the agent and of every tenant in line with the policy schedules.
+pub struct PolicyScheduleFairing;
+
+async fn refresh(policy_store: &Arc<dyn PolicyStore>, tenant_store: Option<&Arc<dyn TenantStore>>) {
+    policy_store.refresh_policy_schedule().await;
+    if let Some(tenant_store) = tenant_store {
+        for tenant in tenant_store.get_tenant_stores().await {
+            tenant.policy_store.refresh_policy_schedule().await;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Fairing for PolicyScheduleFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Policy Schedule",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let policy_store = match rocket.state::<Arc<dyn PolicyStore>>() {
+            Some(policy_store) => policy_store.clone(),
+            None => return,
+        };
+        let tenant_store = rocket.state::<Arc<dyn TenantStore>>().cloned();
+        let mut shutdown = rocket.shutdown();
+
+        info!("Starting policy schedule task");
+        tokio::spawn(async move {
+            let mut ticks = interval(REFRESH_INTERVAL);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => refresh(&policy_store, tenant_store.as_ref()).await,
+                    _ = &mut shutdown => break,
+                }
+            }
+            info!("Stopped policy schedule task");
+        });
+    }
+}
@@ -0,0 +1,265 @@
+use std::ops::Range;
+use std::str::FromStr;
+
+use cedar_policy::{EntityId, EntityTypeName, EntityUid};
+use cedar_policy_core::ast::{Expr, ExprKind, Literal};
+use cedar_policy_core::parser::{self, text_to_cst};
+use log::debug;
+
+use crate::schemas::policies::{
+    Policy, PolicyEffect, PolicyReference, PolicyReferenceKind, PolicySearch, PolicySearchMatch, SourceSpan,
+};
+use crate::services::policies::errors::PolicyStoreError;
+
+/// Something a policy refers to, found by walking its parsed form.
+enum Target {
+    Entity(EntityUid),
+    /// An attribute read with `.` or tested with `has`, with the variable it's read from if any.
+    Attribute { var: Option<String>, attr: String },
+}
+
+struct Reference {
+    target: Target,
+    /// Byte range in the policy content.
+    range: Option<Range<usize>>,
+}
+
+impl Reference {
+    fn into_policy_reference(self, content: &str) -> PolicyReference {
+        let (kind, value) = match self.target {
+            Target::Entity(uid) if is_action(&uid) => (PolicyReferenceKind::Action, uid.to_string()),
+            Target::Entity(uid) => (PolicyReferenceKind::Entity, uid.to_string()),
+            Target::Attribute { attr, .. } => (PolicyReferenceKind::Attribute, attr),
+        };
+        PolicyReference {
+            kind,
+            value,
+            span: self.range.map(|range| SourceSpan::new(content, range.start, range.end)),
+        }
+    }
+}
+
+fn is_action(uid: &EntityUid) -> bool {
+    uid.type_name().basename() == "Action"
+}
+
+/// The search with its entity uids parsed.
+struct Query {
+    entity: Option<EntityUid>,
+    entity_type: Option<String>,
+    action: Option<EntityUid>,
+    /// The attribute name, with the variable it has to be read from when given as `var.attr`.
+    attribute: Option<(Option<String>, String)>,
+    effect: Option<PolicyEffect>,
+}
+
+fn parse_uid(value: &str) -> Result<EntityUid, PolicyStoreError> {
+    EntityUid::from_str(value)
+        .map_err(|err| PolicyStoreError::InvalidSearch(format!("invalid entity uid {}: {}", value, err)))
+}
+
+/// Actions may be given by their id alone, e.g. `delete` for `Action::"delete"`.
+fn parse_action(value: &str) -> Result<EntityUid, PolicyStoreError> {
+    if value.contains("::") {
+        return parse_uid(value);
+    }
+    let type_name = EntityTypeName::from_str("Action").unwrap();
+    Ok(EntityUid::from_type_name_and_id(type_name, EntityId::from_str(value).unwrap()))
+}
+
+impl TryFrom<&PolicySearch> for Query {
+    type Error = PolicyStoreError;
+
+    fn try_from(search: &PolicySearch) -> Result<Self, Self::Error> {
+        let attribute = search.attribute.as_ref().map(|attribute| match attribute.split_once('.') {
+            Some((var, attr)) => (Some(var.to_string()), attr.to_string()),
+            None => (None, attribute.clone()),
+        });
+        Ok(Query {
+            entity: search.entity.as_deref().map(parse_uid).transpose()?,
+            entity_type: search.entity_type.clone(),
+            action: search.action.as_deref().map(parse_action).transpose()?,
+            attribute,
+            effect: search.effect,
+        })
+    }
+}
+
+impl Query {
+    fn matches_entity(&self, uid: &EntityUid) -> bool {
+        self.entity.as_ref() == Some(uid)
+    }
+
+    fn matches_entity_type(&self, uid: &EntityUid) -> bool {
+        self.entity_type.as_deref() == Some(uid.type_name().to_string().as_str())
+    }
+
+    fn matches_action(&self, uid: &EntityUid) -> bool {
+        self.action.as_ref() == Some(uid)
+    }
+
+    fn matches_attribute(&self, var: &Option<String>, attr: &str) -> bool {
+        match &self.attribute {
+            Some((None, name)) => name == attr,
+            Some((Some(expected), name)) => name == attr && var.as_ref() == Some(expected),
+            None => false,
+        }
+    }
+
+    fn matches(&self, reference: &Reference) -> bool {
+        match &reference.target {
+            Target::Entity(uid) => {
+                self.matches_entity(uid) || self.matches_entity_type(uid) || self.matches_action(uid)
+            }
+            Target::Attribute { var, attr } => self.matches_attribute(var, attr),
+        }
+    }
+
+    /// Every given criterion has to be met by at least one of the references.
+    fn covered_by(&self, references: &[Reference]) -> bool {
+        let entities = || {
+            references.iter().filter_map(|reference| match &reference.target {
+                Target::Entity(uid) => Some(uid),
+                Target::Attribute { .. } => None,
+            })
+        };
+        (self.entity.is_none() || entities().any(|uid| self.matches_entity(uid)))
+            && (self.entity_type.is_none() || entities().any(|uid| self.matches_entity_type(uid)))
+            && (self.action.is_none() || entities().any(|uid| self.matches_action(uid)))
+            && (self.attribute.is_none()
+                || references.iter().any(|reference| match &reference.target {
+                    Target::Attribute { var, attr } => self.matches_attribute(var, attr),
+                    Target::Entity(_) => false,
+                }))
+    }
+}
+
+/// Policy text placed before a scope constraint to parse it as a condition.
+const SCOPE_PREFIX: &str = "permit(principal, action, resource) when { ";
+
+/// Collect the entity literals and attribute accesses of an expression.
+/// `shift` maps the offsets of the parsed text to offsets in the policy content.
+fn walk_expression(expression: &Expr, shift: impl Fn(usize) -> usize, references: &mut Vec<Reference>) {
+    for expression in expression.subexpressions() {
+        let target = match expression.expr_kind() {
+            ExprKind::Lit(Literal::EntityUID(uid)) => match EntityUid::from_str(&uid.to_string()) {
+                Ok(uid) => Target::Entity(uid),
+                Err(_) => continue,
+            },
+            ExprKind::GetAttr { expr, attr } | ExprKind::HasAttr { expr, attr } => Target::Attribute {
+                var: match expr.expr_kind() {
+                    ExprKind::Var(var) => Some(var.to_string()),
+                    _ => None,
+                },
+                attr: attr.to_string(),
+            },
+            _ => continue,
+        };
+        let range = expression.source_info().as_ref().map(|info| shift(info.0.start)..shift(info.0.end));
+        references.push(Reference { target, range });
+    }
+}
+
+/// Collect the entities of the principal, action and resource scopes.
+/// Scope constraints carry no source info, so each one is parsed again as a condition to locate its entities.
+fn walk_scopes(content: &str, references: &mut Vec<Reference>) {
+    let policy = match text_to_cst::parse_policy(content) {
+        Ok(cst) => cst.node,
+        Err(_) => return,
+    };
+    let variables = policy.iter().flat_map(|policy| &policy.variables);
+    for (_, constraint) in variables.filter_map(|variable| variable.node.as_ref()?.ineq.as_ref()) {
+        let range = constraint.info.0.clone();
+        let text = format!("{}{} }};", SCOPE_PREFIX, &content[range.clone()]);
+        if let Ok(parsed) = parser::parse_policy(None, &text) {
+            let shift = |offset: usize| offset + range.start - SCOPE_PREFIX.len();
+            walk_expression(parsed.non_head_constraints(), shift, references);
+        }
+    }
+}
+
+/// The entity literals and attribute accesses in the conditions of the policy, in the order they appear.
+fn condition_references(content: &str) -> Vec<Reference> {
+    let mut references = Vec::new();
+    match parser::parse_policy(None, content) {
+        Ok(parsed) => walk_expression(parsed.non_head_constraints(), |offset| offset, &mut references),
+        Err(err) => debug!("Unable to parse policy for its references: {}", err),
+    }
+    references.sort_by_key(|reference| reference.range.as_ref().map(|range| range.start));
+    references
+}
+
+/// Every entity and attribute referenced by the policy, in the order they appear.
+fn references(content: &str) -> Vec<Reference> {
+    let mut references = Vec::new();
+    walk_scopes(content, &mut references);
+    references.extend(condition_references(content));
+    references
+}
+
+/// The entities named in the conditions of the policy, evaluating it may read their attributes and ancestors.
+pub fn condition_entities(policy: &cedar_policy::Policy) -> Vec<EntityUid> {
+    let entities = condition_references(&policy.to_string()).into_iter().filter_map(|reference| {
+        match reference.target {
+            Target::Entity(uid) => Some(uid),
+            Target::Attribute { .. } => None,
+        }
+    });
+    entities.collect()
+}
+
+/// Find the policies referencing the searched entities, actions and attributes.
+/// The policies are searched structurally, a name inside a string literal or a comment is not a reference.
+pub fn search_policies(
+    policies: &[Policy],
+    search: &PolicySearch,
+) -> Result<Vec<PolicySearchMatch>, PolicyStoreError> {
+    let query = Query::try_from(search)?;
+    let mut matches = Vec::new();
+    for policy in policies {
+        let parsed: cedar_policy::Policy = match policy.try_into() {
+            Ok(parsed) => parsed,
+            Err(_) => continue,
+        };
+        if matches!(query.effect, Some(effect) if effect != PolicyEffect::from(parsed.effect())) {
+            continue;
+        }
+        let references = references(&policy.content);
+        if !query.covered_by(&references) {
+            continue;
+        }
+        matches.push(PolicySearchMatch {
+            id: policy.id.clone(),
+            references: references
+                .into_iter()
+                .filter(|reference| query.matches(reference))
+                .map(|reference| reference.into_policy_reference(&policy.content))
+                .collect(),
+        });
+    }
+    matches.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(matches)
+}
+
+/// Find the policies referencing entities for which `exists` is false, actions aside.
+pub fn missing_entity_references(
+    policies: &[Policy],
+    exists: impl Fn(&EntityUid) -> bool,
+) -> Vec<PolicySearchMatch> {
+    let mut matches = Vec::new();
+    for policy in policies {
+        let missing: Vec<PolicyReference> = references(&policy.content)
+            .into_iter()
+            .filter(|reference| matches!(&reference.target, Target::Entity(uid) if !is_action(uid) && !exists(uid)))
+            .map(|reference| reference.into_policy_reference(&policy.content))
+            .collect();
+        if !missing.is_empty() {
+            matches.push(PolicySearchMatch {
+                id: policy.id.clone(),
+                references: missing,
+            });
+        }
+    }
+    matches.sort_by(|a, b| a.id.cmp(&b.id));
+    matches
+}
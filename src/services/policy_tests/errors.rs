@@ -0,0 +1,12 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum PolicyTestStoreError {
+    /// Test with the given id was not found.
+    #[error("Unable to find test with id {0}")]
+    TestNotFound(String),
+    /// Test with the given id already exists.
+    #[error("Test with id {0} already exists")]
+    TestAlreadyExists(String),
+}
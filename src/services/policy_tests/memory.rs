@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use async_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use async_trait::async_trait;
+use log::{debug, info};
+
+use crate::schemas::policy_tests::PolicyTest;
+use crate::services::policy_tests::errors::PolicyTestStoreError;
+use crate::services::policy_tests::PolicyTestStore;
+
+pub struct MemoryPolicyTestStore {
+    tests: RwLock<BTreeMap<String, PolicyTest>>,
+}
+
+impl MemoryPolicyTestStore {
+    pub fn new() -> Self {
+        Self {
+            tests: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    async fn read(&self) -> RwLockReadGuard<'_, BTreeMap<String, PolicyTest>> {
+        debug!("Trying to acquire read lock on policy tests");
+        self.tests.read().await
+    }
+
+    async fn write(&self) -> RwLockWriteGuard<'_, BTreeMap<String, PolicyTest>> {
+        debug!("Trying to acquire write lock on policy tests");
+        self.tests.write().await
+    }
+}
+
+impl Default for MemoryPolicyTestStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl PolicyTestStore for MemoryPolicyTestStore {
+    async fn get_tests(&self) -> Vec<PolicyTest> {
+        info!("Getting policy tests");
+        let lock = self.read().await;
+        lock.values().cloned().collect()
+    }
+
+    async fn get_test(&self, id: &str) -> Result<PolicyTest, Box<dyn Error>> {
+        info!("Getting policy test {}", id);
+        let lock = self.read().await;
+        match lock.get(id) {
+            Some(test) => Ok(test.clone()),
+            None => Err(PolicyTestStoreError::TestNotFound(id.to_owned()).into()),
+        }
+    }
+
+    async fn create_test(&self, test: PolicyTest) -> Result<PolicyTest, Box<dyn Error>> {
+        info!("Creating policy test {}", test.id);
+        let mut lock = self.write().await;
+        if lock.contains_key(&test.id) {
+            return Err(PolicyTestStoreError::TestAlreadyExists(test.id).into());
+        }
+        lock.insert(test.id.clone(), test.clone());
+        Ok(test)
+    }
+
+    async fn update_test(&self, id: String, test: PolicyTest) -> Result<PolicyTest, Box<dyn Error>> {
+        info!("Updating policy test {}", id);
+        let mut lock = self.write().await;
+        let test = PolicyTest { id: id.clone(), ..test };
+        lock.insert(id, test.clone());
+        Ok(test)
+    }
+
+    async fn delete_test(&self, id: &str) -> Result<PolicyTest, Box<dyn Error>> {
+        info!("Deleting policy test {}", id);
+        let mut lock = self.write().await;
+        match lock.remove(id) {
+            Some(test) => Ok(test),
+            None => Err(PolicyTestStoreError::TestNotFound(id.to_owned()).into()),
+        }
+    }
+}
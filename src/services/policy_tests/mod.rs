@@ -0,0 +1,30 @@
+use std::error::Error;
+
+use async_lock::{Mutex, MutexGuard};
+use async_trait::async_trait;
+
+use crate::schemas::policy_tests::PolicyTest;
+
+pub mod errors;
+pub mod memory;
+pub mod runner;
+
+/// Serializes the changes checked against the tests, so that no other change is stored between checking a change
+/// and storing it.
+#[derive(Default)]
+pub struct TestGate(Mutex<()>);
+
+impl TestGate {
+    pub async fn hold(&self) -> MutexGuard<'_, ()> {
+        self.0.lock().await
+    }
+}
+
+#[async_trait]
+pub trait PolicyTestStore: Send + Sync {
+    async fn get_tests(&self) -> Vec<PolicyTest>;
+    async fn get_test(&self, id: &str) -> Result<PolicyTest, Box<dyn Error>>;
+    async fn create_test(&self, test: PolicyTest) -> Result<PolicyTest, Box<dyn Error>>;
+    async fn update_test(&self, id: String, test: PolicyTest) -> Result<PolicyTest, Box<dyn Error>>;
+    async fn delete_test(&self, id: &str) -> Result<PolicyTest, Box<dyn Error>>;
+}
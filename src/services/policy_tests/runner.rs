@@ -0,0 +1,99 @@
+use std::collections::BTreeSet;
+
+use cedar_policy::{Authorizer, Entities, EvaluationError, PolicySet};
+use cedar_policy_core::authorizer::Decision;
+use log::info;
+
+use crate::schemas::authorization::{AuthorizationRequest, DecisionRef};
+use crate::schemas::policy_tests::{PolicyTest, PolicyTestReport, PolicyTestResult};
+use crate::services::policies::PolicyStore;
+use crate::services::policy_tests::PolicyTestStore;
+
+fn run_test(
+    authorizer: &Authorizer,
+    test: &PolicyTest,
+    policy_set: &PolicySet,
+    entities: &Entities,
+) -> PolicyTestResult {
+    let mut result = PolicyTestResult {
+        id: test.id.clone(),
+        passed: false,
+        expected_decision: test.decision,
+        expected_reasons: test.reasons.clone(),
+        decision: None,
+        reasons: Vec::new(),
+        errors: Vec::new(),
+    };
+    let query: AuthorizationRequest = match test.request.clone().try_into() {
+        Ok(query) => query,
+        Err(err) => {
+            result.errors.push(err.to_string());
+            return result;
+        }
+    };
+    let (request, entities) = match query.get_request_entities(entities.clone()) {
+        Ok(request) => request,
+        Err(err) => {
+            result.errors.push(err.to_string());
+            return result;
+        }
+    };
+
+    let response = authorizer.is_authorized(&request, policy_set, &entities);
+    let decision = match response.decision() {
+        Decision::Allow => DecisionRef::Allow,
+        Decision::Deny => DecisionRef::Deny,
+    };
+    let reasons: BTreeSet<String> = response.diagnostics().reason().map(|r| r.to_string()).collect();
+    result.errors = response
+        .diagnostics()
+        .errors()
+        .map(|e| match e {
+            EvaluationError::StringMessage(e) => e,
+        })
+        .collect();
+    result.passed = decision == test.decision
+        && match &test.reasons {
+            Some(expected) => expected.iter().cloned().collect::<BTreeSet<String>>() == reasons,
+            None => true,
+        };
+    result.decision = Some(decision);
+    result.reasons = reasons.into_iter().collect();
+    result
+}
+
+/// Evaluate every test against the given policies and entities.
+pub fn run_tests(tests: &[PolicyTest], policy_set: &PolicySet, entities: &Entities) -> PolicyTestReport {
+    info!("Running {} policy tests", tests.len());
+    let authorizer = Authorizer::new();
+    let results: Vec<PolicyTestResult> = tests
+        .iter()
+        .map(|test| run_test(&authorizer, test, policy_set, entities))
+        .collect();
+    let passed = results.iter().filter(|result| result.passed).count();
+    PolicyTestReport {
+        passed,
+        failed: results.len() - passed,
+        results,
+    }
+}
+
+/// Checks the entities of a change a store builds itself, returning the failing tests.
+/// The tests and policies are taken up front, `None` when there are no tests.
+pub async fn entities_check(
+    test_store: &dyn PolicyTestStore,
+    policy_store: &dyn PolicyStore,
+) -> Option<impl Fn(&Entities) -> Result<(), Vec<PolicyTestResult>> + Send + Sync> {
+    let tests = test_store.get_tests().await;
+    if tests.is_empty() {
+        return None;
+    }
+    let policy_set = policy_store.policy_set().await;
+    Some(move |entities: &Entities| {
+        let failures = run_tests(&tests, &policy_set, entities).failures();
+        match failures.is_empty() {
+            true => Ok(()),
+            false => Err(failures),
+        }
+    })
+}
@@ -2,6 +2,7 @@ use std::error::Error;
 use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use log::{error, info};
@@ -14,7 +15,7 @@ use crate::services::SchemaStore;
 
 pub struct InitSchemaFairing;
 
-pub(crate) async fn init(conf: &config::Config, schema_store: &Box<dyn SchemaStore>) {
+pub(crate) async fn init(conf: &config::Config, schema_store: &Arc<dyn SchemaStore>) {
     if conf.schema.is_none() {
         return;
     }
@@ -76,7 +77,7 @@ impl Fairing for InitSchemaFairing {
         let config = rocket.state::<config::Config>();
 
         if config.is_some() {
-            init(config.unwrap(), rocket.state::<Box<dyn SchemaStore>>().unwrap()).await;
+            init(config.unwrap(), rocket.state::<Arc<dyn SchemaStore>>().unwrap()).await;
         }
 
         Ok(rocket)
@@ -4,22 +4,30 @@ use async_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use async_trait::async_trait;
 use cedar_policy::Schema as CedarSchema;
 use cedar_policy::SchemaError;
+use cedar_policy_validator::ValidatorSchema;
 use log::{debug, error, info};
+use ref_cast::RefCast;
 
 use crate::schemas::schema::Schema as InternalSchema;
 use crate::services::schema::SchemaStore;
+use crate::services::staged::{StagedChange, StagedSwap};
 
-pub struct Schema(CedarSchema, InternalSchema);
+/// The parsed schema, the cedar form policies are validated with wraps it.
+pub struct Schema(ValidatorSchema, InternalSchema);
 
 impl Schema {
     fn empty() -> Self {
         Self {
-            0: CedarSchema::from_str("{}").unwrap(),
+            0: ValidatorSchema::from_str("{}").unwrap(),
             1: InternalSchema::empty()
         }
     }
 
     fn cedar_schema(&self) -> CedarSchema {
+        CedarSchema::ref_cast(&self.0).clone()
+    }
+
+    fn validator_schema(&self) -> ValidatorSchema {
         self.0.clone()
     }
 
@@ -27,9 +35,9 @@ impl Schema {
         self.1.clone()
     }
 
-    fn new(cedar_schema: CedarSchema, internal_schema: InternalSchema) -> Self {
+    fn new(validator_schema: ValidatorSchema, internal_schema: InternalSchema) -> Self {
         Self {
-            0: cedar_schema,
+            0: validator_schema,
             1: internal_schema
         }
     }
@@ -68,6 +76,15 @@ impl SchemaStore for MemorySchemaStore {
         }
     }
 
+    async fn get_validator_schema(&self) -> Option<ValidatorSchema> {
+        let lock = self.read().await;
+        if lock.internal_schema().is_empty() {
+            None
+        } else {
+            Some(lock.validator_schema())
+        }
+    }
+
     async fn get_internal_schema(&self) -> InternalSchema {
         info!("Getting stored schema");
         let lock = self.read().await;
@@ -81,14 +98,14 @@ impl SchemaStore for MemorySchemaStore {
         info!("Updating stored schema");
         let mut lock = self.write().await;
         let internal_schema: InternalSchema = schema.clone();
-        let cedar_schema: CedarSchema = match schema.try_into() {
+        let validator_schema: ValidatorSchema = match schema.try_into() {
             Ok(schema) => schema,
             Err(err) => {
                 error!("Failed to parse schema");
                 return Err(err);
             }
         };
-        *lock = Schema::new(cedar_schema, internal_schema.clone());
+        *lock = Schema::new(validator_schema, internal_schema.clone());
         Ok(internal_schema)
     }
 
@@ -97,4 +114,13 @@ impl SchemaStore for MemorySchemaStore {
         let mut lock = self.write().await;
         *lock = Schema::empty();
     }
+
+    async fn stage_schema(&self, schema: InternalSchema) -> Result<Box<dyn StagedChange + '_>, SchemaError> {
+        info!("Staging schema");
+        let staged = match schema.is_empty() {
+            true => Schema::empty(),
+            false => Schema::new(schema.clone().try_into()?, schema),
+        };
+        Ok(Box::new(StagedSwap::new(&self.schema, None, move |stored: &mut Schema| *stored = staged)))
+    }
 }
@@ -1,8 +1,10 @@
 use async_trait::async_trait;
 use cedar_policy::Schema as CedarSchema;
 use cedar_policy::SchemaError;
+use cedar_policy_validator::ValidatorSchema;
 
 use crate::schemas::schema::Schema as InternalSchema;
+use crate::services::staged::StagedChange;
 
 pub mod memory;
 pub mod load_from_file;
@@ -10,6 +12,8 @@ pub mod load_from_file;
 #[async_trait]
 pub trait SchemaStore: Send + Sync {
     async fn get_cedar_schema(&self) -> Option<CedarSchema>;
+    /// The schema in the form entities are parsed against.
+    async fn get_validator_schema(&self) -> Option<ValidatorSchema>;
 
     async fn get_internal_schema(&self) -> InternalSchema;
     async fn update_schema(
@@ -17,4 +21,6 @@ pub trait SchemaStore: Send + Sync {
         schema: InternalSchema
     ) -> Result<InternalSchema, SchemaError>;
     async fn delete_schema(&self);
+    /// Check the schema replacing the stored one, an empty schema deletes it. The staged change stores it.
+    async fn stage_schema(&self, schema: InternalSchema) -> Result<Box<dyn StagedChange + '_>, SchemaError>;
 }
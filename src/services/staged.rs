@@ -0,0 +1,52 @@
+use async_lock::{Mutex, MutexGuard, RwLock, RwLockWriteGuard};
+use async_trait::async_trait;
+use log::debug;
+
+/// A change a store checked up front, stored later together with changes to other stores.
+#[async_trait]
+pub trait StagedChange: Send {
+    /// Wait for the locks of the store, other writers and readers wait until the change is dropped.
+    async fn lock(&mut self);
+    /// Store the change, `lock` has to be called first. It can't fail anymore.
+    fn apply(&mut self);
+}
+
+/// Stores a change into the locked value.
+type Apply<'a, T> = Box<dyn FnOnce(&mut T) + Send + 'a>;
+
+/// A change swapped into a value behind a `RwLock`, taking the lock serializing its writers first if any.
+pub struct StagedSwap<'a, T> {
+    value: &'a RwLock<T>,
+    writer: Option<&'a Mutex<()>>,
+    apply: Option<Apply<'a, T>>,
+    guards: Option<(Option<MutexGuard<'a, ()>>, RwLockWriteGuard<'a, T>)>,
+}
+
+impl<'a, T> StagedSwap<'a, T> {
+    pub fn new(value: &'a RwLock<T>, writer: Option<&'a Mutex<()>>, apply: impl FnOnce(&mut T) + Send + 'a) -> Self {
+        Self {
+            value,
+            writer,
+            apply: Some(Box::new(apply)),
+            guards: None,
+        }
+    }
+}
+
+#[async_trait]
+impl<'a, T: Send + Sync> StagedChange for StagedSwap<'a, T> {
+    async fn lock(&mut self) {
+        debug!("Trying to acquire the locks of a staged change");
+        let writer = match self.writer {
+            Some(writer) => Some(writer.lock().await),
+            None => None,
+        };
+        self.guards = Some((writer, self.value.write().await));
+    }
+
+    fn apply(&mut self) {
+        if let (Some((_, value)), Some(apply)) = (self.guards.as_mut(), self.apply.take()) {
+            apply(value);
+        }
+    }
+}
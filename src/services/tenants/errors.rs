@@ -0,0 +1,15 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum TenantStoreError {
+    /// Tenant with the given name was not found.
+    #[error("Unable to find tenant {0}")]
+    TenantNotFound(String),
+    /// Tenant with the given name already exists.
+    #[error("Tenant {0} already exists")]
+    TenantAlreadyExists(String),
+    /// Tenant names are used as a path segment.
+    #[error("Invalid tenant name {0:?}, only letters, digits, '-' and '_' are allowed")]
+    InvalidTenantName(String),
+}
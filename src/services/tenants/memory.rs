@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+
+use async_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use async_trait::async_trait;
+use log::{debug, info};
+
+use crate::schemas::data::IntegrityMode;
+use crate::schemas::tenants::{Tenant, TenantCreate};
+use crate::services::data::memory::MemoryDataStore;
+use crate::services::policies::memory::MemoryPolicyStore;
+use crate::services::policy_tests::memory::MemoryPolicyTestStore;
+use crate::services::policy_tests::TestGate;
+use crate::services::schema::memory::MemorySchemaStore;
+use crate::services::tenants::errors::TenantStoreError;
+use crate::services::tenants::{TenantStore, TenantStores};
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub struct MemoryTenantStore {
+    tenants: RwLock<HashMap<String, Arc<TenantStores>>>,
+    policy_id_pattern: Option<String>,
+    integrity: IntegrityMode,
+}
+
+impl MemoryTenantStore {
+    pub fn new() -> Self {
+        Self {
+            tenants: RwLock::new(HashMap::new()),
+            policy_id_pattern: None,
+            integrity: IntegrityMode::default(),
+        }
+    }
+
+    /// The pattern tenant policy stores generate missing policy ids from.
+    pub fn with_policy_id_pattern(mut self, policy_id_pattern: Option<String>) -> Self {
+        self.policy_id_pattern = policy_id_pattern;
+        self
+    }
+
+    /// Whether tenant data stores reject changes leaving dangling entity references.
+    pub fn with_integrity(mut self, integrity: IntegrityMode) -> Self {
+        self.integrity = integrity;
+        self
+    }
+
+    async fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Arc<TenantStores>>> {
+        debug!("Trying to acquire read lock on tenants");
+        self.tenants.read().await
+    }
+
+    async fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Arc<TenantStores>>> {
+        debug!("Trying to acquire write lock on tenants");
+        self.tenants.write().await
+    }
+}
+
+impl Default for MemoryTenantStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl TenantStore for MemoryTenantStore {
+    async fn get_tenants(&self) -> Vec<Tenant> {
+        info!("Getting tenants");
+        let stores: Vec<Arc<TenantStores>> = self.read().await.values().cloned().collect();
+        let mut tenants = Vec::with_capacity(stores.len());
+        for tenant in stores {
+            tenants.push(tenant.tenant().await);
+        }
+        tenants.sort_by(|a, b| a.name.cmp(&b.name));
+        tenants
+    }
+
+    async fn get_tenant_stores(&self) -> Vec<Arc<TenantStores>> {
+        self.read().await.values().cloned().collect()
+    }
+
+    async fn get_tenant(&self, name: &str) -> Result<Arc<TenantStores>, Box<dyn Error>> {
+        let lock = self.read().await;
+        match lock.get(name) {
+            Some(tenant) => Ok(tenant.clone()),
+            None => Err(TenantStoreError::TenantNotFound(name.to_owned()).into()),
+        }
+    }
+
+    async fn create_tenant(&self, tenant: TenantCreate) -> Result<Tenant, Box<dyn Error>> {
+        info!("Creating tenant {}", tenant.name);
+        if !is_valid_name(&tenant.name) {
+            return Err(TenantStoreError::InvalidTenantName(tenant.name).into());
+        }
+        let mut lock = self.write().await;
+        if lock.contains_key(&tenant.name) {
+            return Err(TenantStoreError::TenantAlreadyExists(tenant.name).into());
+        }
+        let stores = Arc::new(TenantStores {
+            name: tenant.name.clone(),
+            policy_store: Box::new(
+                MemoryPolicyStore::new()
+                    .with_max_policies(tenant.quotas.max_policies)
+                    .with_id_pattern(self.policy_id_pattern.clone()),
+            ),
+            data_store: Box::new(
+                MemoryDataStore::new()
+                    .with_max_entities(tenant.quotas.max_entities)
+                    .with_integrity(self.integrity),
+            ),
+            schema_store: Box::new(MemorySchemaStore::new()),
+            test_store: Box::new(MemoryPolicyTestStore::new()),
+            test_gate: TestGate::default(),
+            quotas: tenant.quotas,
+            api_key: tenant.api_key,
+        });
+        lock.insert(tenant.name, stores.clone());
+        Ok(stores.tenant().await)
+    }
+
+    async fn delete_tenant(&self, name: &str) -> Result<Tenant, Box<dyn Error>> {
+        info!("Deleting tenant {}", name);
+        let removed = self.write().await.remove(name);
+        match removed {
+            Some(tenant) => Ok(tenant.tenant().await),
+            None => Err(TenantStoreError::TenantNotFound(name.to_owned()).into()),
+        }
+    }
+}
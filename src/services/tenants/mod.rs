@@ -0,0 +1,45 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::schemas::tenants::{Tenant, TenantCreate, TenantQuotas};
+use crate::services::data::DataStore;
+use crate::services::policies::PolicyStore;
+use crate::services::policy_tests::{PolicyTestStore, TestGate};
+use crate::services::schema::SchemaStore;
+
+pub mod errors;
+pub mod memory;
+
+/// The isolated stores of a single tenant.
+pub struct TenantStores {
+    pub name: String,
+    pub quotas: TenantQuotas,
+    pub api_key: Option<String>,
+    pub policy_store: Box<dyn PolicyStore>,
+    pub data_store: Box<dyn DataStore>,
+    pub schema_store: Box<dyn SchemaStore>,
+    pub test_store: Box<dyn PolicyTestStore>,
+    pub test_gate: TestGate,
+}
+
+impl TenantStores {
+    pub async fn tenant(&self) -> Tenant {
+        Tenant {
+            name: self.name.clone(),
+            quotas: self.quotas.clone(),
+            policies: self.policy_store.get_policies().await.len(),
+            entities: self.data_store.count_entities().await,
+        }
+    }
+}
+
+#[async_trait]
+pub trait TenantStore: Send + Sync {
+    async fn get_tenants(&self) -> Vec<Tenant>;
+    async fn get_tenant_stores(&self) -> Vec<Arc<TenantStores>>;
+    async fn get_tenant(&self, name: &str) -> Result<Arc<TenantStores>, Box<dyn Error>>;
+    async fn create_tenant(&self, tenant: TenantCreate) -> Result<Tenant, Box<dyn Error>>;
+    async fn delete_tenant(&self, name: &str) -> Result<Tenant, Box<dyn Error>>;
+}
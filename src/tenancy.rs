@@ -0,0 +1,245 @@
+use std::io;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use rocket::data::{self, FromData, Limits, ToByteUnit};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::Status;
+use rocket::request::{local_cache, FromRequest, Outcome};
+use rocket::serde::json::{self, serde_json, Json};
+use rocket::serde::Deserialize;
+use rocket::{Data, Request};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::RequestBody;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use rocket_okapi::request::{OpenApiFromData, OpenApiFromRequest, RequestHeaderInput};
+
+use crate::services::data::DataStore;
+use crate::services::policies::PolicyStore;
+use crate::services::policy_tests::{PolicyTestStore, TestGate};
+use crate::services::schema::SchemaStore;
+use crate::services::tenants::{TenantStore, TenantStores};
+
+const TENANTS_PATH: &str = "tenants";
+
+/// The tenant addressed by the request, if any.
+pub(crate) struct RequestTenant(pub Option<String>);
+
+impl RequestTenant {
+    pub fn of<'r>(request: &'r Request<'_>) -> &'r RequestTenant {
+        request.local_cache(|| RequestTenant(None))
+    }
+}
+
+/// The payload limit of the addressed tenant, left by the `Stores` guard for the body guards.
+struct PayloadLimit(Option<u64>);
+
+/// Serve `/v1/tenants/<tenant>/...` with the regular `/v1/...` routes,
+/// remembering the tenant so the routes use its stores.
+pub(crate) struct TenantPrefix;
+
+#[async_trait]
+impl Fairing for TenantPrefix {
+    fn info(&self) -> Info {
+        Info {
+            name: "TenantPrefix",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let path = req.uri().path().as_str().to_owned();
+        let mut segments = path.trim_start_matches('/').splitn(4, '/');
+        let (tenant, rest) = match (segments.next(), segments.next(), segments.next(), segments.next()) {
+            (Some("v1"), Some(TENANTS_PATH), Some(tenant), Some(rest)) => (tenant, rest),
+            _ => return,
+        };
+        // Tenant management stays out of reach of the tenant routes.
+        if rest.is_empty() || rest == TENANTS_PATH || rest.starts_with("tenants/") {
+            return;
+        }
+        let uri = match req.uri().query() {
+            Some(query) => format!("/v1/{}?{}", rest, query.as_str()),
+            None => format!("/v1/{}", rest),
+        };
+        if let Ok(origin) = Origin::parse_owned(uri) {
+            req.local_cache(|| RequestTenant(Some(tenant.to_owned())));
+            req.set_uri(origin);
+        }
+    }
+}
+
+/// The stores a request operates on, the agent stores or those of the addressed tenant.
+pub enum Stores<'r> {
+    Agent {
+        policy_store: &'r dyn PolicyStore,
+        data_store: &'r dyn DataStore,
+        schema_store: &'r dyn SchemaStore,
+        test_store: &'r dyn PolicyTestStore,
+        test_gate: &'r TestGate,
+    },
+    Tenant(Arc<TenantStores>),
+}
+
+impl Stores<'_> {
+    pub fn policy_store(&self) -> &dyn PolicyStore {
+        match self {
+            Stores::Agent { policy_store, .. } => *policy_store,
+            Stores::Tenant(tenant) => tenant.policy_store.as_ref(),
+        }
+    }
+
+    pub fn data_store(&self) -> &dyn DataStore {
+        match self {
+            Stores::Agent { data_store, .. } => *data_store,
+            Stores::Tenant(tenant) => tenant.data_store.as_ref(),
+        }
+    }
+
+    pub fn schema_store(&self) -> &dyn SchemaStore {
+        match self {
+            Stores::Agent { schema_store, .. } => *schema_store,
+            Stores::Tenant(tenant) => tenant.schema_store.as_ref(),
+        }
+    }
+
+    pub fn test_store(&self) -> &dyn PolicyTestStore {
+        match self {
+            Stores::Agent { test_store, .. } => *test_store,
+            Stores::Tenant(tenant) => tenant.test_store.as_ref(),
+        }
+    }
+
+    pub fn test_gate(&self) -> &TestGate {
+        match self {
+            Stores::Agent { test_gate, .. } => test_gate,
+            Stores::Tenant(tenant) => &tenant.test_gate,
+        }
+    }
+
+    /// The size of the request bodies the tenant accepts, `None` when it isn't limited.
+    pub fn payload_limit(&self) -> Option<u64> {
+        match self {
+            Stores::Agent { .. } => None,
+            Stores::Tenant(tenant) => tenant.quotas.max_payload_bytes,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Stores<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let rocket = request.rocket();
+        let name = match &RequestTenant::of(request).0 {
+            Some(name) => name,
+            None => {
+                return match (
+                    rocket.state::<Arc<dyn PolicyStore>>(),
+                    rocket.state::<Arc<dyn DataStore>>(),
+                    rocket.state::<Arc<dyn SchemaStore>>(),
+                    rocket.state::<Arc<dyn PolicyTestStore>>(),
+                    rocket.state::<Arc<TestGate>>(),
+                ) {
+                    (Some(policy_store), Some(data_store), Some(schema_store), Some(test_store), Some(test_gate)) => {
+                        Outcome::Success(Stores::Agent {
+                            policy_store: policy_store.as_ref(),
+                            data_store: data_store.as_ref(),
+                            schema_store: schema_store.as_ref(),
+                            test_store: test_store.as_ref(),
+                            test_gate: test_gate.as_ref(),
+                        })
+                    }
+                    _ => Outcome::Error((Status::InternalServerError, ())),
+                };
+            }
+        };
+
+        let tenant = match rocket.state::<Arc<dyn TenantStore>>() {
+            Some(tenant_store) => match tenant_store.get_tenant(name).await {
+                Ok(tenant) => tenant,
+                Err(_) => return Outcome::Error((Status::NotFound, ())),
+            },
+            None => return Outcome::Error((Status::NotFound, ())),
+        };
+        // Bodies announcing a larger size are rejected right away,
+        // the others are cut off at the limit as they are read.
+        let payload = request
+            .headers()
+            .get_one("Content-Length")
+            .and_then(|length| length.parse::<u64>().ok());
+        if let (Some(payload), Some(max)) = (payload, tenant.quotas.max_payload_bytes) {
+            if payload > max {
+                return Outcome::Error((Status::PayloadTooLarge, ()));
+            }
+        }
+        request.local_cache(|| PayloadLimit(tenant.quotas.max_payload_bytes));
+        Outcome::Success(Stores::Tenant(tenant))
+    }
+}
+
+impl<'a> OpenApiFromRequest<'a> for Stores<'a> {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+/// A JSON body read up to the payload limit of the addressed tenant, within the `json` limit.
+/// Routes taking one have to take `Stores` as well, it determines the tenant.
+#[derive(Clone)]
+pub struct TenantJson<T>(pub T);
+
+impl<T> TenantJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for TenantJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: Deserialize<'r>> FromData<'r> for TenantJson<T> {
+    type Error = json::Error<'r>;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let mut limit = req.limits().get("json").unwrap_or(Limits::JSON);
+        if let PayloadLimit(Some(max)) = req.local_cache(|| PayloadLimit(None)) {
+            limit = limit.min(max.bytes());
+        }
+        let string = match data.open(limit).into_string().await {
+            Ok(string) if string.is_complete() => string.into_inner(),
+            Ok(_) => {
+                let exceeded = io::Error::new(io::ErrorKind::UnexpectedEof, "data limit exceeded");
+                return data::Outcome::Error((Status::PayloadTooLarge, json::Error::Io(exceeded)));
+            }
+            Err(err) => return data::Outcome::Error((Status::BadRequest, json::Error::Io(err))),
+        };
+        let string = local_cache!(req, string);
+        match serde_json::from_str(string) {
+            Ok(value) => data::Outcome::Success(TenantJson(value)),
+            Err(err) if err.classify() == serde_json::error::Category::Data => {
+                data::Outcome::Error((Status::UnprocessableEntity, json::Error::Parse(string, err)))
+            }
+            Err(err) => data::Outcome::Error((Status::BadRequest, json::Error::Parse(string, err))),
+        }
+    }
+}
+
+impl<'r, T: JsonSchema + Deserialize<'r>> OpenApiFromData<'r> for TenantJson<T> {
+    fn request_body(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<RequestBody> {
+        Json::<T>::request_body(gen)
+    }
+}
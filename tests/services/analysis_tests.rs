@@ -0,0 +1,226 @@
+use cedar_agent::policies::analysis::analyze_policies;
+use cedar_agent::policies::impact::{
+    analyze_impact, corpus, parse_policies, parse_policy_set, proposed_policies, read_proposal_lines,
+};
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::schema::memory::MemorySchemaStore;
+use cedar_agent::schemas::analysis::{
+    FindingKind, FindingSeverity, ImpactRequest, PolicyAnalysis, ProposalMode,
+};
+use cedar_agent::schemas::authorization::AuthorizationCall;
+use cedar_agent::schemas::policies::{Policy, ValidationSettings};
+use cedar_agent::{PolicyStore, SchemaStore};
+use cedar_policy::{Entities, ValidationMode};
+
+use crate::services::utils;
+
+fn policy(id: &str, content: &str) -> Policy {
+    Policy {
+        id: id.to_string(),
+        content: content.to_string(),
+        ..Default::default()
+    }
+}
+
+fn find(analysis: &PolicyAnalysis, kind: FindingKind, policies: Vec<&str>) -> Option<FindingSeverity> {
+    analysis
+        .findings
+        .iter()
+        .find(|f| f.kind == kind && f.policies == policies)
+        .map(|f| f.severity)
+}
+
+#[tokio::test]
+async fn analysis_tests() {
+    let policy_store = MemoryPolicyStore::new();
+    policy_store
+        .update_policies(
+            vec![
+                utils::schema_invalid_policy(Some("unknown".to_string())),
+                policy(
+                    "unreachable",
+                    r#"permit(principal == ResourceType::"document", action == Action::"document:get", resource == ResourceType::"document");"#,
+                ),
+                policy(
+                    "dup-a",
+                    r#"permit(principal in Role::"Editor", action == Action::"document:get", resource == ResourceType::"document");"#,
+                ),
+                policy(
+                    "dup-b",
+                    r#"permit(principal in Role::"Editor", action == Action::"document:get", resource == ResourceType::"document");"#,
+                ),
+                policy(
+                    "scope-dup",
+                    r#"permit(principal in Role::"Editor", action == Action::"document:get", resource == ResourceType::"document") when { principal.jobLevel > 3 };"#,
+                ),
+                policy(
+                    "shadowed",
+                    r#"permit(principal == User::"editor-1@domain.com", action == Action::"document:list", resource);"#,
+                ),
+                policy(
+                    "block",
+                    r#"forbid(principal == User::"editor-1@domain.com", action in [Action::"document:get"], resource);"#,
+                ),
+            ],
+            None,
+            ValidationSettings::default()
+        )
+        .await
+        .unwrap();
+    let policy_set = policy_store.policy_set().await;
+
+    // Without a schema only the structural checks run.
+    let analysis = analyze_policies(&policy_set, &None, ValidationMode::Strict);
+    assert_eq!(analysis.policies, 7);
+    assert!(!analysis.schema_checked);
+    assert_eq!(
+        find(&analysis, FindingKind::DuplicatePolicy, vec!["dup-b", "dup-a"]),
+        Some(FindingSeverity::Warning)
+    );
+    assert_eq!(
+        find(&analysis, FindingKind::DuplicateScope, vec!["scope-dup", "dup-a"]),
+        Some(FindingSeverity::Info)
+    );
+    assert!(analysis.findings.iter().all(|f| f.kind != FindingKind::UnknownReference));
+    // The action hierarchy is only known from the schema.
+    assert_eq!(find(&analysis, FindingKind::ShadowedPermit, vec!["shadowed", "block"]), None);
+
+    let schema_store = MemorySchemaStore::new();
+    schema_store.update_schema(utils::schema()).await.unwrap();
+    let analysis = analyze_policies(&policy_set, &schema_store.get_cedar_schema().await, ValidationMode::Strict);
+    assert!(analysis.schema_checked);
+    assert_eq!(
+        find(&analysis, FindingKind::UnknownReference, vec!["unknown"]),
+        Some(FindingSeverity::Error)
+    );
+    assert_eq!(
+        find(&analysis, FindingKind::UnreachableScope, vec!["unreachable"]),
+        Some(FindingSeverity::Warning)
+    );
+    assert_eq!(
+        find(&analysis, FindingKind::ShadowedPermit, vec!["shadowed", "block"]),
+        Some(FindingSeverity::Warning)
+    );
+    // Findings are ordered by severity.
+    assert_eq!(analysis.findings[0].severity, FindingSeverity::Error);
+
+    // Unknown references are type errors in either mode.
+    let analysis = analyze_policies(&policy_set, &schema_store.get_cedar_schema().await, ValidationMode::Permissive);
+    assert_eq!(
+        find(&analysis, FindingKind::UnknownReference, vec!["unknown"]),
+        Some(FindingSeverity::Error)
+    );
+}
+
+#[tokio::test]
+async fn stored_analysis_tests() {
+    let policy_store = MemoryPolicyStore::new();
+    policy_store
+        .update_policies(
+            vec![
+                policy("get", r#"permit(principal, action == Action::"document:get", resource);"#),
+                Policy {
+                    enabled: false,
+                    ..policy("no-get", r#"forbid(principal, action in [Action::"document:get"], resource);"#)
+                },
+            ],
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    let schema_store = MemorySchemaStore::new();
+    schema_store.update_schema(utils::schema()).await.unwrap();
+
+    // A forbid on the same action shadows the permit, disabled policies are analyzed too.
+    let policy_set = parse_policies(&policy_store.get_policies().await).unwrap();
+    let analysis = analyze_policies(&policy_set, &schema_store.get_cedar_schema().await, ValidationMode::Strict);
+    assert_eq!(analysis.policies, 2);
+    assert_eq!(
+        find(&analysis, FindingKind::ShadowedPermit, vec!["get", "no-get"]),
+        Some(FindingSeverity::Warning)
+    );
+    assert_eq!(policy_store.policy_set().await.policies().count(), 1);
+}
+
+fn call(principal: &str) -> AuthorizationCall {
+    AuthorizationCall::new(
+        Some(format!("User::\"{}\"", principal)),
+        Some("Action::\"delete\"".to_string()),
+        Some("Document::\"cedar-agent.pdf\"".to_string()),
+        None,
+        None,
+        None,
+        None,
+    )
+}
+
+#[tokio::test]
+async fn impact_tests() {
+    let policy_store = MemoryPolicyStore::new();
+    policy_store
+        .update_policies(
+            vec![policy("admin", "permit(principal == User::\"admin@domain.com\",action,resource);")],
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+
+    let proposal = ImpactRequest {
+        policies: vec![
+            policy("no-delete", "forbid(principal == User::\"admin@domain.com\",action == Action::\"delete\",resource);"),
+            policy("guest", "permit(principal == User::\"guest\",action,resource);"),
+        ],
+        mode: ProposalMode::Partial,
+        removed: vec![],
+        requests: vec![call("admin@domain.com"), call("nobody")],
+    };
+    // The requests following the proposal are given as JSON lines.
+    let body = format!(
+        "{}\n{}\n\nnot json\n",
+        rocket::serde::json::to_string(&proposal).unwrap(),
+        rocket::serde::json::to_string(&call("guest")).unwrap()
+    );
+    let (proposal, lines) = read_proposal_lines(body.as_bytes(), 1024).await.unwrap();
+    let proposed = proposed_policies(policy_store.get_policies().await, &proposal, None);
+    let proposed = parse_policy_set(&proposed).unwrap();
+    let current = policy_store.policy_set().await;
+    let report = analyze_impact(&current, &proposed, &Entities::empty(), corpus(&proposal, lines));
+
+    assert_eq!(report.summary.evaluated, 3);
+    assert_eq!(report.summary.unchanged, 1);
+    assert_eq!(report.summary.changed, 2);
+    assert_eq!((report.summary.allow_to_deny, report.summary.deny_to_allow), (1, 1));
+    assert_eq!(report.changes[0].index, 0);
+    assert_eq!(report.changes[0].current.reasons, vec!["admin".to_string()]);
+    assert_eq!(report.changes[0].proposed.reasons, vec!["no-delete".to_string()]);
+    assert_eq!(report.changes[1].index, 2);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].index, 3);
+    assert!(report.errors[0].message.starts_with("line 4"));
+    assert!(read_proposal_lines(body.as_bytes(), 64).await.is_err());
+    assert!(read_proposal_lines("\n".as_bytes(), 64).await.is_err());
+    // Analyzing a proposal leaves the store untouched.
+    assert_eq!(policy_store.get_policies().await.len(), 1);
+
+    // Policies without an id get one generated like the store would, so they don't collide.
+    let unnamed = ImpactRequest {
+        policies: vec![
+            policy("", "@id(\"guest\") permit(principal == User::\"guest\",action,resource);"),
+            policy("", "@id(\"guest\") forbid(principal == User::\"nobody\",action,resource);"),
+        ],
+        ..proposal.clone()
+    };
+    let proposed = proposed_policies(policy_store.get_policies().await, &unnamed, Some("team-{id}"));
+    let ids: Vec<&str> = proposed.iter().map(|p| p.id.as_str()).collect();
+    assert_eq!(ids, vec!["admin", "team-guest", "team-guest-2"]);
+    assert_eq!(parse_policy_set(&proposed).unwrap().policies().count(), 3);
+
+    let full = ImpactRequest {
+        mode: ProposalMode::Full,
+        policies: vec![policy("broken", "permit(")],
+        ..proposal
+    };
+    assert!(parse_policy_set(&proposed_policies(policy_store.get_policies().await, &full, None)).is_err());
+}
@@ -0,0 +1,133 @@
+use cedar_agent::bundle::errors::BundleError;
+use cedar_agent::bundle::{export_bundle, import_bundle};
+use cedar_agent::bundle::load_from_file::load_bundle_from_file;
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::schema::memory::MemorySchemaStore;
+use cedar_agent::schemas::bundle::Bundle;
+use cedar_agent::schemas::policies::ValidationSettings;
+use cedar_agent::schemas::schema::Schema;
+use cedar_agent::{DataStore, PolicyStore, SchemaStore};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::time::timeout;
+
+use crate::services::utils;
+
+fn bundle() -> Bundle {
+    Bundle {
+        schema: utils::schema(),
+        policies: vec![utils::schema_valid_policy(Some("editors".to_string()))],
+        entities: utils::entities(),
+    }
+}
+
+#[tokio::test]
+async fn import_tests() {
+    let policy_store = MemoryPolicyStore::new();
+    let data_store = MemoryDataStore::new();
+    let schema_store = MemorySchemaStore::new();
+    let import = |bundle| {
+        import_bundle(&policy_store, &data_store, &schema_store, bundle, ValidationSettings::default())
+    };
+
+    let imported = import(bundle()).await.unwrap();
+    assert_eq!(imported.policies.len(), 1);
+    assert_eq!(imported.entities.len(), 8);
+    assert!(!imported.schema.is_empty());
+
+    // Policies failing validation against the bundled schema leave every store untouched.
+    let invalid = Bundle {
+        policies: vec![utils::schema_invalid_policy(Some("invalid".to_string()))],
+        entities: Default::default(),
+        ..bundle()
+    };
+    assert!(matches!(import(invalid).await, Err(BundleError::InvalidPolicies(_))));
+    let invalid = Bundle {
+        entities: utils::parse_error_entities(),
+        ..bundle()
+    };
+    assert!(matches!(import(invalid).await, Err(BundleError::InvalidEntities(_))));
+    let invalid = Bundle {
+        schema: utils::parse_error_schema(),
+        ..bundle()
+    };
+    assert!(matches!(import(invalid).await, Err(BundleError::InvalidSchema(_))));
+
+    let exported = export_bundle(&policy_store, &data_store, &schema_store).await;
+    assert_eq!(exported.policies.len(), 1);
+    assert_eq!(exported.policies[0].id, "editors");
+    assert_eq!(exported.entities.len(), 8);
+    assert!(!exported.schema.is_empty());
+
+    // An empty bundle clears the stores.
+    let empty = Bundle {
+        schema: Schema::empty(),
+        policies: vec![],
+        entities: Default::default(),
+    };
+    import(empty).await.unwrap();
+    assert!(schema_store.get_cedar_schema().await.is_none());
+    assert_eq!(policy_store.get_policies().await.len(), 0);
+    assert_eq!(data_store.get_entities().await.len(), 0);
+}
+
+#[tokio::test]
+async fn failed_import_tests() {
+    let policy_store = MemoryPolicyStore::new();
+    let data_store = MemoryDataStore::new().with_max_entities(Some(2));
+    let schema_store = MemorySchemaStore::new();
+    policy_store
+        .update_policies(vec![utils::approve_all_policy(None)], None, ValidationSettings::default())
+        .await
+        .unwrap();
+
+    // The entities, checked last, exceed the quota: no store changed, nothing has to be restored.
+    let result = import_bundle(&policy_store, &data_store, &schema_store, bundle(), ValidationSettings::default()).await;
+    assert!(matches!(result, Err(BundleError::QuotaExceeded(_))));
+    let policies = policy_store.get_policies().await;
+    assert_eq!(policies.len(), 1);
+    assert_eq!(policies[0].id, "test");
+    assert_eq!(data_store.get_entities().await.len(), 0);
+    assert!(schema_store.get_cedar_schema().await.is_none());
+}
+
+#[tokio::test]
+async fn staged_import_tests() {
+    let policy_store = MemoryPolicyStore::new();
+    let data_store = MemoryDataStore::new();
+    let schema_store = MemorySchemaStore::new();
+    let bundle = bundle();
+    let mut schema = schema_store.stage_schema(bundle.schema.clone()).await.unwrap();
+    let (_, mut policies) = policy_store
+        .stage_policies(bundle.policies, None, ValidationSettings::default())
+        .await
+        .unwrap();
+    let (_, mut entities) = data_store.stage_entities(bundle.entities, None).await.unwrap();
+    assert_eq!(policy_store.get_policies().await.len(), 0);
+
+    // Readers wait while the stores are locked, they see every part stored once the locks are released.
+    schema.lock().await;
+    policies.lock().await;
+    entities.lock().await;
+    let waiting = Duration::from_millis(50);
+    assert!(timeout(waiting, policy_store.get_policies()).await.is_err());
+    assert!(timeout(waiting, data_store.get_entities()).await.is_err());
+    assert!(timeout(waiting, schema_store.get_cedar_schema()).await.is_err());
+    schema.apply();
+    policies.apply();
+    entities.apply();
+    drop((schema, policies, entities));
+    assert_eq!(policy_store.get_policies().await.len(), 1);
+    assert_eq!(data_store.get_entities().await.len(), 8);
+    assert!(schema_store.get_cedar_schema().await.is_some());
+}
+
+#[tokio::test]
+async fn test_load_bundle_from_file() {
+    let bundle = load_bundle_from_file(PathBuf::from("./examples/bundle.json")).await.unwrap();
+    assert_eq!(bundle.policies.len(), 3);
+    assert_eq!(bundle.entities.len(), 12);
+    assert!(!bundle.schema.is_empty());
+}
@@ -4,11 +4,14 @@ use crate::services::utils;
 use std::error::Error;
 
 use cedar_agent::data::load_from_file::load_entities_from_file;
+use cedar_agent::data::hierarchy::{to_dot, to_mermaid};
 use cedar_agent::data::memory::MemoryDataStore;
 use cedar_agent::schemas::authorization::AuthorizationCall;
 use cedar_agent::schemas::authorization::AuthorizationRequest;
+use cedar_agent::schemas::data as schemas;
 use cedar_agent::DataStore;
-use cedar_policy::Entities;
+use cedar_policy::{Entities, EntityUid};
+use cedar_policy_validator::ValidatorSchema;
 
 #[tokio::test]
 async fn memory_tests() {
@@ -18,6 +21,7 @@ async fn memory_tests() {
     assert_eq!(entities.len(), 0);
     let updated_entities = store.update_entities(utils::entities(), None).await.unwrap();
     assert_eq!(updated_entities.len(), 8);
+    assert_eq!(store.count_entities().await, 8);
 
     let error_entities = store.update_entities(utils::parse_error_entities(), None).await;
     assert!(error_entities.is_err());
@@ -26,6 +30,458 @@ async fn memory_tests() {
     assert_eq!(entities.len(), 0);
 }
 
+fn entity(uid: &str, parents: &[&str]) -> schemas::Entities {
+    let parents: Vec<_> = parents
+        .iter()
+        .map(|p| rocket::serde::json::json!({"type": "Role", "id": p}))
+        .collect();
+    rocket::serde::json::from_value(rocket::serde::json::json!([
+        {"uid": {"type": "Role", "id": uid}, "attrs": {}, "parents": parents}
+    ]))
+    .unwrap()
+}
+
+fn role(id: &str) -> EntityUid {
+    format!("Role::\"{}\"", id).parse().unwrap()
+}
+
+#[tokio::test]
+async fn entity_tests() {
+    let store = MemoryDataStore::new().with_max_entities(Some(4));
+    store.upsert_entities(entity("alice", &["eng"]), None).await.unwrap();
+    store.upsert_entities(entity("eng", &["acme"]), None).await.unwrap();
+    store.upsert_entities(entity("acme", &[]), None).await.unwrap();
+    let entities = store.entities().await;
+    assert!(entities.is_ancestor_of(&role("acme"), &role("alice")));
+    assert!(store.get_entity(&role("alice")).await.is_some());
+    assert!(store.get_entity(&role("bob")).await.is_none());
+
+    // Ancestors inherited through a parent follow the changes of its parents.
+    store.upsert_entities(entity("eng", &["other"]), None).await.unwrap();
+    let entities = store.entities().await;
+    assert!(!entities.is_ancestor_of(&role("acme"), &role("alice")));
+    assert!(entities.is_ancestor_of(&role("other"), &role("alice")));
+    assert_eq!(store.get_entities().await.len(), 3);
+
+    assert_eq!(store.remove_entities(&[role("eng"), role("eng"), role("bob")]).await.unwrap(), 1);
+    let entities = store.entities().await;
+    assert!(entities.is_ancestor_of(&role("eng"), &role("alice")));
+    assert!(!entities.is_ancestor_of(&role("other"), &role("alice")));
+
+    // Cycles and quota violations leave the stored entities untouched.
+    assert!(store.upsert_entities(entity("acme", &["alice"]), None).await.is_ok());
+    assert!(store.upsert_entities(entity("alice", &["acme"]), None).await.is_err());
+    let more: Vec<schemas::Entity> = ["a", "b", "c"]
+        .iter()
+        .flat_map(|id| entity(id, &[]).iter().cloned().collect::<Vec<_>>())
+        .collect();
+    let more: schemas::Entities = more.into();
+    assert!(store.upsert_entities(more, None).await.is_err());
+    assert_eq!(store.get_entities().await.len(), 2);
+
+    let candidate = store.candidate_entities(&entity("bob", &[]), &[role("alice")], None).await.unwrap();
+    assert!(candidate.get(&role("bob")).is_some());
+    assert!(candidate.get(&role("alice")).is_none());
+    assert!(store.get_entity(&role("bob")).await.is_none());
+}
+
+#[tokio::test]
+async fn entity_schema_tests() {
+    let store = MemoryDataStore::new();
+    let schema = std::fs::read_to_string("./examples/schema.json").unwrap();
+    let schema = ValidatorSchema::from_json_value(rocket::serde::json::from_str(&schema).unwrap()).unwrap();
+    store.upsert_entities(entity("admin", &[]), Some(schema.clone())).await.unwrap();
+    let invalid: schemas::Entities = rocket::serde::json::from_value(rocket::serde::json::json!([
+        {"uid": {"type": "Role", "id": "viewer"}, "attrs": {"level": 1}, "parents": []}
+    ]))
+    .unwrap();
+    assert!(store.upsert_entities(invalid, Some(schema)).await.is_err());
+    assert_eq!(store.get_entities().await.len(), 1);
+
+    // References implicit in the schema form are resolved, actions keep the parents they declare.
+    let schema = ValidatorSchema::from_json_value(rocket::serde::json::json!({"": {
+        "entityTypes": {"User": {"shape": {"type": "Record", "attributes": {
+            "manager": {"type": "Entity", "name": "User", "required": false}
+        }}}},
+        "actions": {
+            "any": {},
+            "all": {"memberOf": [{"id": "any"}]},
+            "read": {"memberOf": [{"id": "all"}]}
+        }
+    }}))
+    .unwrap();
+    let entities: schemas::Entities = rocket::serde::json::from_value(rocket::serde::json::json!([
+        {"uid": {"type": "User", "id": "alice"}, "attrs": {"manager": {"type": "User", "id": "bob"}}, "parents": []},
+        {"uid": {"type": "Action", "id": "read"}, "attrs": {}, "parents": [{"type": "Action", "id": "all"}]},
+        {"uid": {"type": "Action", "id": "all"}, "attrs": {}, "parents": [{"type": "Action", "id": "any"}]},
+        {"uid": {"type": "Action", "id": "any"}, "attrs": {}, "parents": []}
+    ]))
+    .unwrap();
+    store.update_entities(entities, Some(schema.clone())).await.unwrap();
+    let report = store.check_integrity(Some(schema)).await;
+    assert_eq!(report.dangling_attributes.len(), 1);
+    let read = store.entity_ancestors(&r#"Action::"read""#.parse().unwrap()).await.unwrap();
+    assert_eq!(read.direct, vec![r#"Action::"all""#]);
+    assert_eq!(read.transitive.len(), 2);
+}
+
+fn operations(operations: rocket::serde::json::Value) -> Vec<schemas::EntityOperation> {
+    rocket::serde::json::from_value(operations).unwrap()
+}
+
+#[tokio::test]
+async fn patch_tests() {
+    let store = MemoryDataStore::new();
+    store.upsert_entities(entity("alice", &["eng"]), None).await.unwrap();
+    store.upsert_entities(entity("eng", &[]), None).await.unwrap();
+
+    let revision = store
+        .apply_entity_operations(
+            operations(rocket::serde::json::json!([
+                {"op": "add", "entity": {"uid": {"type": "Role", "id": "acme"}, "attrs": {}, "parents": []}},
+                {"op": "add_parent", "uid": {"type": "Role", "id": "eng"}, "parent": {"type": "Role", "id": "acme"}},
+                {"op": "set_attribute", "uid": {"type": "Role", "id": "alice"}, "attribute": "level", "value": 3},
+                {"op": "set_attribute", "uid": {"type": "Role", "id": "alice"}, "attribute": "team", "value": "x"},
+                {"op": "unset_attribute", "uid": {"type": "Role", "id": "alice"}, "attribute": "team"}
+            ])),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(revision.revision, 3);
+    let entities = store.entities().await;
+    assert!(entities.is_ancestor_of(&role("acme"), &role("alice")));
+    let alice = entities.get(&role("alice")).unwrap();
+    assert!(alice.attr("level").is_some());
+    assert!(alice.attr("team").is_none());
+
+    // A failing operation leaves the stored entities untouched.
+    let result = store
+        .apply_entity_operations(
+            operations(rocket::serde::json::json!([
+                {"op": "remove_parent", "uid": {"type": "Role", "id": "eng"}, "parent": {"type": "Role", "id": "acme"}},
+                {"op": "remove", "uid": {"type": "Role", "id": "bob"}}
+            ])),
+            None,
+        )
+        .await;
+    assert!(result.unwrap_err().to_string().contains("operation 1"));
+    assert!(store.entities().await.is_ancestor_of(&role("acme"), &role("alice")));
+
+    let removals = operations(rocket::serde::json::json!([
+        {"op": "remove_parent", "uid": {"type": "Role", "id": "eng"}, "parent": {"type": "Role", "id": "acme"}},
+        {"op": "remove", "uid": {"type": "Role", "id": "acme"}},
+        {"op": "replace", "entity": {"uid": {"type": "Role", "id": "alice"}, "attrs": {}, "parents": []}}
+    ]));
+    // The candidate has the operations applied, the stored entities don't.
+    let candidate = store.candidate_operations(&removals, None).await.unwrap();
+    assert!(candidate.get(&role("acme")).is_none());
+    assert!(store.get_entity(&role("acme")).await.is_some());
+
+    let revision = store.apply_entity_operations(removals, None).await.unwrap();
+    assert_eq!(revision.revision, 4);
+    let entities = store.entities().await;
+    assert!(entities.get(&role("acme")).is_none());
+    assert!(!entities.is_ancestor_of(&role("eng"), &role("alice")));
+}
+
+fn query_uids(page: &schemas::EntityPage) -> Vec<String> {
+    let entities = rocket::serde::json::to_value(&page.entities).unwrap();
+    entities
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entity| entity["uid"]["id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn query_tests() {
+    let store = MemoryDataStore::new();
+    let entities: schemas::Entities = rocket::serde::json::from_value(rocket::serde::json::json!([
+        {"uid": {"type": "User", "id": "alice"}, "attrs": {"dept": "sales", "level": 3}, "parents": [{"type": "Group", "id": "eng"}]},
+        {"uid": {"type": "User", "id": "bob"}, "attrs": {"dept": "sales"}, "parents": [{"type": "Group", "id": "ops"}]},
+        {"uid": {"type": "User", "id": "carol"}, "attrs": {"dept": "it", "level": 3}, "parents": []},
+        {"uid": {"type": "Group", "id": "eng"}, "attrs": {}, "parents": [{"type": "Group", "id": "all"}]},
+        {"uid": {"type": "Group", "id": "ops"}, "attrs": {}, "parents": [{"type": "Group", "id": "all"}]}
+    ]))
+    .unwrap();
+    store.update_entities(entities, None).await.unwrap();
+
+    let query = |query: rocket::serde::json::Value| -> schemas::EntityQuery {
+        rocket::serde::json::from_value(query).unwrap()
+    };
+    let page = store.query_entities(&query(rocket::serde::json::json!({"type": "User"}))).await.unwrap();
+    assert_eq!(query_uids(&page), vec!["alice", "bob", "carol"]);
+
+    let page = store
+        .query_entities(&query(rocket::serde::json::json!({"ancestor": "Group::\"all\"", "type": "User"})))
+        .await
+        .unwrap();
+    assert_eq!(query_uids(&page), vec!["alice", "bob"]);
+
+    let page = store
+        .query_entities(&query(rocket::serde::json::json!({"attribute": "dept", "value": "sales"})))
+        .await
+        .unwrap();
+    assert_eq!(query_uids(&page), vec!["alice", "bob"]);
+    let page = store
+        .query_entities(&query(rocket::serde::json::json!({"attribute": "level", "value": "3", "fields": "level"})))
+        .await
+        .unwrap();
+    assert_eq!(query_uids(&page), vec!["alice", "carol"]);
+    let entities = rocket::serde::json::to_value(&page.entities).unwrap();
+    assert!(entities[0]["attrs"].get("dept").is_none());
+    assert_eq!(entities[0]["attrs"]["level"], 3);
+    let page = store
+        .query_entities(&query(rocket::serde::json::json!({"attribute": "level"})))
+        .await
+        .unwrap();
+    assert_eq!(query_uids(&page), vec!["alice", "carol"]);
+
+    // Pages follow each other without gaps.
+    let page = store.query_entities(&query(rocket::serde::json::json!({"limit": 2}))).await.unwrap();
+    assert_eq!(page.entities.len(), 2);
+    let cursor = page.next_cursor.clone().unwrap();
+    let next = store
+        .query_entities(&query(rocket::serde::json::json!({"limit": 2, "cursor": cursor})))
+        .await
+        .unwrap();
+    assert_eq!(next.entities.len(), 2);
+    let last = store
+        .query_entities(&query(rocket::serde::json::json!({"limit": 2, "cursor": next.next_cursor.unwrap()})))
+        .await
+        .unwrap();
+    assert_eq!(last.entities.len(), 1);
+    assert!(last.next_cursor.is_none());
+    let page = store
+        .query_entities(&query(rocket::serde::json::json!({"type": "User", "limit": 2, "cursor": cursor})))
+        .await
+        .unwrap();
+    assert_eq!(query_uids(&page), vec!["alice", "bob"]);
+
+    assert!(store.query_entities(&query(rocket::serde::json::json!({"value": "3"}))).await.is_err());
+    assert!(store.query_entities(&query(rocket::serde::json::json!({"ancestor": "all"}))).await.is_err());
+}
+
+#[tokio::test]
+async fn hierarchy_tests() {
+    let store = MemoryDataStore::new();
+    let entities = ["alice:eng,admins", "bob:eng", "eng:all", "admins:all", "all:"]
+        .iter()
+        .flat_map(|spec| {
+            let (id, parents) = spec.split_once(':').unwrap();
+            let parents: Vec<&str> = parents.split(',').filter(|p| !p.is_empty()).collect();
+            entity(id, &parents).iter().cloned().collect::<Vec<_>>()
+        })
+        .collect::<Vec<schemas::Entity>>();
+    store.update_entities(entities.into(), None).await.unwrap();
+
+    let ancestors = store.entity_ancestors(&role("alice")).await.unwrap();
+    assert_eq!(ancestors.direct, vec![r#"Role::"admins""#, r#"Role::"eng""#]);
+    assert_eq!(ancestors.transitive, vec![r#"Role::"admins""#, r#"Role::"all""#, r#"Role::"eng""#]);
+    let descendants = store.entity_descendants(&role("all")).await.unwrap();
+    assert_eq!(descendants.direct, vec![r#"Role::"admins""#, r#"Role::"eng""#]);
+    assert_eq!(descendants.transitive.len(), 4);
+    assert!(store.entity_ancestors(&role("nobody")).await.is_none());
+
+    let paths = store.entity_paths(&role("alice"), &role("all")).await.unwrap();
+    assert_eq!(
+        paths.paths,
+        vec![
+            vec![r#"Role::"alice""#, r#"Role::"admins""#, r#"Role::"all""#],
+            vec![r#"Role::"alice""#, r#"Role::"eng""#, r#"Role::"all""#],
+        ]
+    );
+    let paths = store.entity_paths(&role("bob"), &role("admins")).await.unwrap();
+    assert!(paths.paths.is_empty());
+
+    // The graph of eng leaves out alice's other parent.
+    let graph = store.entity_graph(&role("eng")).await.unwrap();
+    assert_eq!(graph.nodes.len(), 4);
+    assert_eq!(graph.edges.len(), 3);
+    let dot = to_dot(&graph);
+    assert!(dot.contains(r#""Role::\"alice\"" -> "Role::\"eng\"";"#));
+    assert!(!dot.contains("admins"));
+    let mermaid = to_mermaid(&graph);
+    assert!(mermaid.starts_with("flowchart BT"));
+    assert!(mermaid.contains(r#"["Role::#quot;alice#quot;"]"#));
+}
+
+#[tokio::test]
+async fn closure_tests() {
+    let store = MemoryDataStore::new();
+    let chain: Vec<schemas::Entity> = (0..50)
+        .flat_map(|level| {
+            let parent = format!("level{}", level + 1);
+            entity(&format!("level{}", level), &[&parent]).iter().cloned().collect::<Vec<_>>()
+        })
+        .collect();
+    store.update_entities(chain.into(), None).await.unwrap();
+    let entities = store.entities().await;
+    assert!(entities.is_ancestor_of(&role("level50"), &role("level0")));
+
+    // Ancestors gained through an entity that only was a dangling parent reach its descendants.
+    store.upsert_entities(entity("level50", &["root"]), None).await.unwrap();
+    assert!(store.entities().await.is_ancestor_of(&role("root"), &role("level0")));
+    let descendants = store.entity_descendants(&role("root")).await;
+    assert!(descendants.is_none());
+    let descendants = store.entity_descendants(&role("level50")).await.unwrap();
+    assert_eq!(descendants.transitive.len(), 50);
+
+    // Cutting the chain in the middle only changes the entities below the cut.
+    store
+        .apply_entity_operations(
+            operations(rocket::serde::json::json!([
+                {"op": "remove_parent", "uid": {"type": "Role", "id": "level20"}, "parent": {"type": "Role", "id": "level21"}},
+            ])),
+            None,
+        )
+        .await
+        .unwrap();
+    let entities = store.entities().await;
+    assert!(!entities.is_ancestor_of(&role("root"), &role("level0")));
+    assert!(entities.is_ancestor_of(&role("level20"), &role("level0")));
+    assert!(entities.is_ancestor_of(&role("root"), &role("level21")));
+    let ancestors = store.entity_ancestors(&role("level0")).await.unwrap();
+    assert_eq!(ancestors.transitive.len(), 20);
+    let page = store
+        .query_entities(&schemas::EntityQuery {
+            ancestor: Some(r#"Role::"level30""#.to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(page.entities.len(), 9);
+
+    // Removing an entity removes the ancestors inherited through it.
+    store.remove_entities(&[role("level10")]).await.unwrap();
+    let ancestors = store.entity_ancestors(&role("level0")).await.unwrap();
+    assert_eq!(ancestors.transitive.len(), 10);
+}
+
+#[tokio::test]
+async fn integrity_tests() {
+    let managed: schemas::Entities = rocket::serde::json::from_value(rocket::serde::json::json!([
+        {"uid": {"type": "Role", "id": "docs"}, "attrs": {
+            "owner": {"__entity": {"type": "Role", "id": "bob"}},
+            "editors": [{"__entity": {"type": "Role", "id": "eng"}}],
+        }, "parents": []}
+    ]))
+    .unwrap();
+
+    let store = MemoryDataStore::new().with_integrity(schemas::IntegrityMode::Reject);
+    assert!(store.update_entities(entity("alice", &["eng"]), None).await.is_err());
+    store.update_entities(entity("eng", &[]), None).await.unwrap();
+    store.upsert_entities(entity("alice", &["eng"]), None).await.unwrap();
+    assert!(store.upsert_entities(managed.clone(), None).await.is_err());
+    // Removing a parent still referenced is rejected as well.
+    assert!(store.remove_entities(&[role("eng")]).await.is_err());
+    assert!(store.upsert_entities(entity("eng", &["alice"]), None).await.is_err());
+    assert!(store.check_integrity(None).await.is_empty());
+
+    let store = MemoryDataStore::new();
+    store.update_entities(entity("alice", &["eng"]), None).await.unwrap();
+    store.upsert_entities(managed, None).await.unwrap();
+    let report = store.check_integrity(None).await;
+    let dangling: Vec<(&str, &str)> = report
+        .dangling_parents
+        .iter()
+        .map(|dangling| (dangling.entity.as_str(), dangling.reference.as_str()))
+        .collect();
+    assert_eq!(dangling, vec![(r#"Role::"alice""#, r#"Role::"eng""#)]);
+    let attributes: Vec<Option<&str>> =
+        report.dangling_attributes.iter().map(|dangling| dangling.attribute.as_deref()).collect();
+    assert_eq!(attributes, vec![Some("owner"), Some("editors")]);
+    // Cycles are rejected whatever the mode.
+    assert!(store.upsert_entities(entity("eng", &["alice"]), None).await.is_err());
+    assert!(report.cycles.is_empty());
+}
+
+#[tokio::test]
+async fn reachable_tests() {
+    let store = MemoryDataStore::new();
+    let referring: schemas::Entities = rocket::serde::json::from_value(rocket::serde::json::json!([
+        {"uid": {"type": "Role", "id": "docs"}, "parents": [],
+         "attrs": {"owner": {"__entity": {"type": "Role", "id": "bob"}}}},
+        {"uid": {"type": "Role", "id": "bob"}, "parents": [],
+         "attrs": {"manager": {"__entity": {"type": "Role", "id": "carol"}}}},
+        {"uid": {"type": "Role", "id": "carol"}, "attrs": {}, "parents": []},
+        {"uid": {"type": "Role", "id": "dave"}, "attrs": {}, "parents": []}
+    ]))
+    .unwrap();
+    store.update_entities(referring, None).await.unwrap();
+    store.upsert_entities(entity("alice", &["eng"]), None).await.unwrap();
+    store.upsert_entities(entity("eng", &["acme"]), None).await.unwrap();
+
+    // The roots bring the entities their attributes refer to, and their ancestors without the entities holding them.
+    let entities = store.reachable_entities(&[role("docs"), role("alice"), role("unknown")]).await;
+    for uid in ["docs", "bob", "carol", "alice"] {
+        assert!(entities.get(&role(uid)).is_some());
+    }
+    assert!(entities.get(&role("dave")).is_none());
+    assert!(entities.get(&role("eng")).is_none());
+    assert!(entities.is_ancestor_of(&role("acme"), &role("alice")));
+
+    // Requests reach the entities of their scope, those their context refers to and the parents of the entities
+    // they bring, unless they bring all of their entities.
+    let call = AuthorizationCall::new(
+        Some(r#"Role::"alice""#.to_string()),
+        None,
+        None,
+        Some(rocket::serde::json::json!({"owner": {"__entity": {"type": "Role", "id": "bob"}}})),
+        None,
+        Some(rocket::serde::json::json!([
+            {"uid": {"type": "Role", "id": "temp"}, "attrs": {}, "parents": [{"type": "Role", "id": "eng"}]}
+        ])),
+        None,
+    );
+    let request: AuthorizationRequest = call.try_into().unwrap();
+    let roots = request.entity_roots().unwrap();
+    assert!([role("alice"), role("bob"), role("eng")].iter().all(|uid| roots.contains(uid)));
+    assert!(make_authz_call("[]".to_string()).unwrap().entity_roots().is_none());
+}
+
+fn expiring(uid: &str, parents: &[&str], expiry: rocket::serde::json::Value) -> schemas::Entities {
+    let mut entity = rocket::serde::json::to_value(entity(uid, parents)).unwrap();
+    let object = entity[0].as_object_mut().unwrap();
+    object.extend(expiry.as_object().unwrap().clone());
+    rocket::serde::json::from_value(entity).unwrap()
+}
+
+#[tokio::test]
+async fn expiry_tests() {
+    use rocket::serde::json::json;
+
+    let store = MemoryDataStore::new();
+    store.update_entities(entity("alice", &["session"]), None).await.unwrap();
+    store.upsert_entities(expiring("session", &["eng"], json!({"ttl": 3600})), None).await.unwrap();
+    store.upsert_entities(entity("eng", &[]), None).await.unwrap();
+    let session = store.get_entity(&role("session")).await.unwrap();
+    let session = rocket::serde::json::to_value(session).unwrap();
+    assert!(session["expires_at"].is_string() && session.get("ttl").is_none());
+    assert!(store.entities().await.is_ancestor_of(&role("eng"), &role("alice")));
+
+    // Expired entities are left out before being swept, with the ancestors inherited through them.
+    let expired = json!({"expires_at": "2020-01-01T00:00:00Z"});
+    store.upsert_entities(expiring("session", &["eng"], expired), None).await.unwrap();
+    let entities = store.entities().await;
+    assert!(entities.get(&role("session")).is_none());
+    assert!(!entities.is_ancestor_of(&role("eng"), &role("alice")));
+    assert!(store.get_entity(&role("session")).await.is_none());
+    assert_eq!(store.get_entities().await.len(), 2);
+    assert_eq!(store.remove_expired_entities().await, 0);
+
+    let expired = json!({"expires_at": "2020-01-01T00:00:00Z"});
+    store.upsert_entities(expiring("eng", &[], expired), None).await.unwrap();
+    assert_eq!(store.remove_expired_entities().await, 1);
+    assert!(store.get_entity(&role("eng")).await.is_none());
+
+    assert!(store.upsert_entities(expiring("bob", &[], json!({"ttl": -1})), None).await.is_err());
+    let both = json!({"ttl": 60, "expires_at": "2030-01-01T00:00:00Z"});
+    assert!(store.upsert_entities(expiring("bob", &[], both), None).await.is_err());
+}
+
 #[tokio::test]
 async fn test_load_entities_from_file() {
     let entities = load_entities_from_file(PathBuf::from("./examples/data.json"))
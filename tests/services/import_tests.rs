@@ -0,0 +1,138 @@
+use cedar_agent::data::errors::DataImportError;
+use cedar_agent::data::import::{to_ndjson, CsvReader, EntityReader, NdjsonReader};
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::schemas::data as schemas;
+use cedar_agent::DataStore;
+use cedar_policy::EntityUid;
+use rocket::futures::StreamExt;
+use rocket::serde::json::{from_value, json, to_value};
+
+fn uid(uid: &str) -> EntityUid {
+    uid.parse().unwrap()
+}
+
+fn mapping() -> schemas::CsvMapping {
+    from_value(json!({
+        "type": "User",
+        "uid_column": "email",
+        "attributes": [
+            {"column": "name"},
+            {"column": "age", "type": "long"},
+            {"column": "active", "type": "boolean"},
+            {"column": "manager", "type": "entity"},
+            {"column": "ip", "name": "address", "type": "ip"}
+        ],
+        "parents_column": "groups"
+    }))
+    .unwrap()
+}
+
+async fn import(
+    data_store: &MemoryDataStore,
+    mut reader: impl EntityReader,
+    replace: bool,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    data_store.import_entities(&mut reader, replace, None, None).await
+}
+
+#[tokio::test]
+async fn ndjson_import_tests() {
+    let data_store = MemoryDataStore::new();
+    let ndjson = concat!(
+        "{\"uid\": {\"type\": \"Group\", \"id\": \"eng\"}, \"attrs\": {}, \"parents\": []}\n",
+        "\n",
+        "{\"uid\": {\"type\": \"User\", \"id\": \"alice\"}, \"attrs\": {\"age\": 30}, ",
+        "\"parents\": [{\"type\": \"Group\", \"id\": \"eng\"}]}",
+    );
+    assert_eq!(import(&data_store, NdjsonReader::new(ndjson.as_bytes()), true).await.unwrap(), 2);
+    let alice = data_store.get_entity(&uid("User::\"alice\"")).await.unwrap();
+    assert_eq!(to_value(&alice).unwrap()["attrs"]["age"], json!(30));
+
+    // Imports upsert unless they replace all the stored entities.
+    let bob = "{\"uid\": {\"type\": \"User\", \"id\": \"bob\"}, \"attrs\": {}, \"parents\": []}\n";
+    import(&data_store, NdjsonReader::new(bob.as_bytes()), false).await.unwrap();
+    assert_eq!(data_store.get_entities().await.len(), 3);
+    import(&data_store, NdjsonReader::new(bob.as_bytes()), true).await.unwrap();
+    assert_eq!(data_store.get_entities().await.len(), 1);
+
+    let invalid = format!("{}{{\"uid\": \n", bob);
+    let err = import(&data_store, NdjsonReader::new(invalid.as_bytes()), true).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<DataImportError>(), Some(DataImportError::InvalidLine(2, _))));
+    let twice = format!("{}{}", bob, bob);
+    let err = import(&data_store, NdjsonReader::new(twice.as_bytes()), true).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<DataImportError>(), Some(DataImportError::Duplicate(_))));
+    let reader = NdjsonReader::new(bob.as_bytes()).with_limit(10);
+    let err = import(&data_store, reader, true).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<DataImportError>(), Some(DataImportError::TooLarge(10))));
+    // Failed imports leave the stored entities alone.
+    assert!(data_store.get_entity(&uid("User::\"bob\"")).await.is_some());
+}
+
+#[tokio::test]
+async fn checked_import_tests() {
+    let data_store = MemoryDataStore::new();
+    let bob = "{\"uid\": {\"type\": \"User\", \"id\": \"bob\"}, \"attrs\": {}, \"parents\": []}\n";
+    let reject = |entities: &cedar_policy::Entities| match entities.get(&uid("User::\"bob\"")) {
+        Some(_) => Err("bob isn't allowed".into()),
+        None => Ok(()),
+    };
+    let mut reader = NdjsonReader::new(bob.as_bytes());
+    let err = data_store.import_entities(&mut reader, false, None, Some(&reject)).await.unwrap_err();
+    assert_eq!(err.to_string(), "bob isn't allowed");
+    assert_eq!(data_store.count_entities().await, 0);
+}
+
+#[tokio::test]
+async fn csv_import_tests() {
+    let data_store = MemoryDataStore::new();
+    let csv = concat!(
+        "\u{feff}email,name,age,active,manager,ip,groups\n",
+        "alice@example.com,\"Smith, Alice\",30,true,,10.0.0.1,\"Group::\"\"eng\"\" | Group::\"\"admins\"\"\"\n",
+        "bob@example.com,Bob,,false,\"User::\"\"alice@example.com\"\"\",,\n",
+    );
+    let reader = CsvReader::new(csv.as_bytes(), mapping()).unwrap();
+    assert_eq!(import(&data_store, reader, true).await.unwrap(), 2);
+
+    let alice = to_value(data_store.get_entity(&uid("User::\"alice@example.com\"")).await.unwrap()).unwrap();
+    assert_eq!(alice["attrs"]["name"], json!("Smith, Alice"));
+    assert_eq!(alice["attrs"]["age"], json!(30));
+    assert_eq!(alice["attrs"]["active"], json!(true));
+    assert_eq!(alice["attrs"]["address"]["__extn"]["fn"], json!("ip"));
+    assert_eq!(alice["parents"].as_array().unwrap().len(), 2);
+    let bob = to_value(data_store.get_entity(&uid("User::\"bob@example.com\"")).await.unwrap()).unwrap();
+    assert!(bob["attrs"].get("age").is_none());
+    assert_eq!(bob["attrs"]["manager"]["__entity"]["id"], json!("alice@example.com"));
+
+    let invalid = "email,name,age,active,manager,ip,groups\ncarol@example.com,Carol,old,,,,\n";
+    let err = import(&data_store, CsvReader::new(invalid.as_bytes(), mapping()).unwrap(), true).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<DataImportError>(), Some(DataImportError::InvalidRow(2, _))));
+    let missing = "email,name\ncarol@example.com,Carol\n";
+    let err = import(&data_store, CsvReader::new(missing.as_bytes(), mapping()).unwrap(), true).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<DataImportError>(), Some(DataImportError::InvalidMapping(_))));
+    let mut mapping = mapping();
+    mapping.entity_type = "not a type".to_string();
+    assert!(CsvReader::new(csv.as_bytes(), mapping).is_err());
+    assert_eq!(data_store.get_entities().await.len(), 2);
+}
+
+#[tokio::test]
+async fn export_tests() {
+    let data_store = MemoryDataStore::new();
+    let ndjson: String = (0..2500)
+        .map(|id| json!({"uid": {"type": "User", "id": format!("{:04}", id)}, "attrs": {}, "parents": []}).to_string() + "\n")
+        .collect();
+    import(&data_store, NdjsonReader::new(ndjson.as_bytes()), true).await.unwrap();
+
+    let chunks: Vec<schemas::Entities> = data_store.export_entities().await.collect().await;
+    assert_eq!(chunks.len(), 3);
+    let exported: Vec<u8> = chunks.iter().flat_map(to_ndjson).collect();
+    // The export imports back into the same entities, in uid order.
+    let reimported = MemoryDataStore::new();
+    assert_eq!(import(&reimported, NdjsonReader::new(exported.as_slice()), true).await.unwrap(), 2500);
+    let first = exported.split(|byte| *byte == b'\n').next().unwrap();
+    let first: schemas::Entity = rocket::serde::json::from_slice(first).unwrap();
+    assert_eq!(first.uid(), Some(uid("User::\"0000\"")));
+    assert!(data_store.export_entities().await.next().await.is_some());
+    data_store.delete_entities().await;
+    assert!(data_store.export_entities().await.next().await.is_none());
+}
@@ -1,4 +1,11 @@
+mod analysis_tests;
+mod bundle_tests;
 mod data_tests;
+mod import_tests;
 mod policies_tests;
+mod policy_tests_tests;
 mod utils;
 mod schema_tests;
+mod search_tests;
+mod sources_tests;
+mod tenants_tests;
@@ -1,12 +1,22 @@
+use std::collections::HashSet;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::path::PathBuf;
+use std::time::Duration;
 
-use cedar_policy::PolicyId;
+use cedar_policy::{Authorizer, Context, EntityUid, PolicyId, Request};
+use rocket::serde::json::json;
+use chrono::Utc;
 
 use crate::services::utils::*;
 
 use cedar_agent::policies::memory::MemoryPolicyStore;
-use cedar_agent::schemas::policies::PolicyUpdate;
+use cedar_agent::schema::memory::MemorySchemaStore;
+use cedar_agent::schemas::policies::{
+    Policy, PolicyEffect, PolicyFilter, PolicyMetadata, PolicyOperation, PolicyOperationStatus,
+    PolicySchedule, PolicyTransitionKind, PolicyUpdate, ValidationSettings,
+};
+use cedar_agent::SchemaStore;
 use cedar_agent::PolicyStore;
 use cedar_agent::policies::load_from_file::load_policies_from_file;
 
@@ -15,19 +25,19 @@ async fn memory_tests() {
     let store = MemoryPolicyStore::new();
 
     let policies = store
-        .update_policies(vec![approve_all_policy(None)], None)
+        .update_policies(vec![approve_all_policy(None)], None, ValidationSettings::default())
         .await
         .unwrap();
     assert_eq!(policies.len(), 1);
     let duplicate_policies = store
-        .update_policies(vec![approve_all_policy(None), approve_all_policy(None)], None)
+        .update_policies(vec![approve_all_policy(None), approve_all_policy(None)], None, ValidationSettings::default())
         .await;
     assert!(duplicate_policies.is_err());
-    let error_policies = store.update_policies(vec![parse_error_policy()], None).await;
+    let error_policies = store.update_policies(vec![parse_error_policy()], None, ValidationSettings::default()).await;
     assert!(error_policies.is_err());
 
     let created_policy = store
-        .create_policy(&approve_admin_policy(Some("admin".to_string())), None)
+        .create_policy(&approve_admin_policy(Some("admin".to_string())), None, ValidationSettings::default())
         .await
         .unwrap();
     assert_eq!(created_policy.id, "admin".to_string());
@@ -36,10 +46,10 @@ async fn memory_tests() {
     assert_eq!(policy.content, created_policy.content);
 
     let error_policy = store
-        .create_policy(&approve_admin_policy(Some("admin".to_string())), None)
+        .create_policy(&approve_admin_policy(Some("admin".to_string())), None, ValidationSettings::default())
         .await;
     assert!(error_policy.is_err());
-    let error_policy = store.create_policy(&parse_error_policy(), None).await;
+    let error_policy = store.create_policy(&parse_error_policy(), None, ValidationSettings::default()).await;
     assert!(error_policy.is_err());
 
     let policies = store.get_policies().await;
@@ -50,8 +60,11 @@ async fn memory_tests() {
             "test".to_string(),
             PolicyUpdate {
                 content: approve_admin_policy(None).content,
+                metadata: None,
+                schedule: None,
             },
-            None
+            None,
+            ValidationSettings::default()
         )
         .await
         .unwrap();
@@ -66,8 +79,11 @@ async fn memory_tests() {
             "test".to_string(),
             PolicyUpdate {
                 content: parse_error_policy().content,
+                metadata: None,
+                schedule: None,
             },
-            None
+            None,
+            ValidationSettings::default()
         )
         .await;
     assert!(error_policy.is_err());
@@ -88,6 +104,505 @@ async fn memory_tests() {
         .is_none());
 }
 
+#[tokio::test]
+async fn transaction_tests() {
+    let store = MemoryPolicyStore::new();
+    store
+        .update_policies(
+            vec![approve_all_policy(Some("all".to_string())), approve_admin_policy(Some("admin".to_string()))],
+            None,
+            ValidationSettings::default()
+        )
+        .await
+        .unwrap();
+
+    let results = store
+        .apply_policy_operations(
+            vec![
+                PolicyOperation::Create(approve_admin_policy(Some("new".to_string()))),
+                PolicyOperation::Upsert(approve_all_policy(Some("admin".to_string())).into()),
+                PolicyOperation::Upsert(approve_all_policy(Some("other".to_string())).into()),
+                PolicyOperation::Delete { id: "all".to_string() },
+            ],
+            None,
+            ValidationSettings::default()
+        )
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0].status, PolicyOperationStatus::Created);
+    assert_eq!(results[1].status, PolicyOperationStatus::Updated);
+    assert_eq!(results[2].status, PolicyOperationStatus::Created);
+    assert_eq!(results[3].status, PolicyOperationStatus::Deleted);
+    assert_eq!(results[3].id, "all".to_string());
+
+    let policies = store.get_policies().await;
+    assert_eq!(policies.len(), 3);
+    assert!(store.get_policy("all").await.is_err());
+
+    // The candidate set has the operations applied, the stored policies don't.
+    let candidate = store
+        .candidate_policy_operations(&[PolicyOperation::Delete { id: "admin".to_string() }])
+        .await
+        .unwrap();
+    assert!(candidate.policy(&PolicyId::from_str("admin").unwrap()).is_none());
+    assert!(candidate.policy(&PolicyId::from_str("new").unwrap()).is_some());
+    assert!(store.get_policy("admin").await.is_ok());
+
+    // A failing operation rolls back every operation before it.
+    let failed = store
+        .apply_policy_operations(
+            vec![
+                PolicyOperation::Delete { id: "new".to_string() },
+                PolicyOperation::Delete { id: "missing".to_string() },
+            ],
+            None,
+            ValidationSettings::default()
+        )
+        .await;
+    assert!(failed.is_err());
+    assert!(store.get_policy("new").await.is_ok());
+
+    let failed = store
+        .apply_policy_operations(
+            vec![PolicyOperation::Create(approve_all_policy(Some("new".to_string())))],
+            None,
+            ValidationSettings::default()
+        )
+        .await;
+    assert!(failed.is_err());
+    let failed = store
+        .apply_policy_operations(
+            vec![PolicyOperation::Upsert(parse_error_policy().into())],
+            None,
+            ValidationSettings::default(),
+        )
+        .await;
+    assert!(failed.is_err());
+
+    // The resulting set is validated against the schema as a whole.
+    let schema_store = MemorySchemaStore::new();
+    schema_store.update_schema(schema()).await.unwrap();
+    let failed = store
+        .apply_policy_operations(
+            vec![
+                PolicyOperation::Upsert(schema_valid_policy(Some("valid".to_string())).into()),
+                PolicyOperation::Upsert(schema_invalid_policy(Some("invalid".to_string())).into()),
+            ],
+            schema_store.get_cedar_schema().await,
+            ValidationSettings::default()
+        )
+        .await;
+    assert!(failed.is_err());
+    assert!(store.get_policy("valid").await.is_err());
+    assert_eq!(store.policy_set().await.policies().count(), 3);
+}
+
+#[tokio::test]
+async fn metadata_tests() {
+    let store = MemoryPolicyStore::new();
+    let mut tagged = annotated_forbid_policy(Some("tagged".to_string()));
+    tagged.metadata = PolicyMetadata {
+        owner: Some("security".to_string()),
+        description: Some("Blocks suspended users".to_string()),
+        ticket: Some("https://tracker.example.com/SEC-1".to_string()),
+        tags: vec!["incident".to_string(), "users".to_string()],
+    };
+    store
+        .update_policies(vec![tagged, approve_admin_policy(Some("admin".to_string()))], None, ValidationSettings::default())
+        .await
+        .unwrap();
+
+    let policy = store.get_policy("tagged").await.unwrap();
+    assert_eq!(policy.metadata.owner, Some("security".to_string()));
+    assert_eq!(policy.metadata.tags.len(), 2);
+    assert_eq!(policy.annotations.get("id"), Some(&"block-suspended".to_string()));
+
+    // Updating the content alone keeps the stored metadata.
+    let updated = store
+        .update_policy(
+            "tagged".to_string(),
+            PolicyUpdate {
+                content: annotated_forbid_policy(None).content,
+                metadata: None,
+                schedule: None,
+            },
+            None,
+            ValidationSettings::default()
+        )
+        .await
+        .unwrap();
+    assert_eq!(updated.metadata.owner, Some("security".to_string()));
+
+    let filter = |filter: PolicyFilter| filtered_ids(&store, filter);
+    assert_eq!(filter(PolicyFilter::default()).await, vec!["admin", "tagged"]);
+    assert_eq!(
+        filter(PolicyFilter { tag: Some("incident".to_string()), ..Default::default() }).await,
+        vec!["tagged"]
+    );
+    assert!(filter(PolicyFilter { owner: Some("nobody".to_string()), ..Default::default() })
+        .await
+        .is_empty());
+    assert_eq!(
+        filter(PolicyFilter { effect: Some(PolicyEffect::Permit), ..Default::default() }).await,
+        vec!["admin"]
+    );
+    assert_eq!(
+        filter(PolicyFilter { principal_type: Some("User".to_string()), ..Default::default() }).await,
+        vec!["admin"]
+    );
+    assert_eq!(
+        filter(PolicyFilter { resource_type: Some("Document".to_string()), ..Default::default() }).await,
+        vec!["tagged"]
+    );
+    assert_eq!(
+        filter(PolicyFilter {
+            annotation_key: Some("id".to_string()),
+            annotation_value: Some("block-suspended".to_string()),
+            ..Default::default()
+        })
+        .await,
+        vec!["tagged"]
+    );
+    assert!(filter(PolicyFilter {
+        annotation_key: Some("id".to_string()),
+        annotation_value: Some("other".to_string()),
+        ..Default::default()
+    })
+    .await
+    .is_empty());
+}
+
+#[tokio::test]
+async fn enabled_tests() {
+    let store = MemoryPolicyStore::new();
+    let mut disabled = approve_admin_policy(Some("disabled".to_string()));
+    disabled.enabled = false;
+    store
+        .update_policies(
+            vec![approve_all_policy(Some("all".to_string())), disabled],
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    // Disabled policies are listed but not evaluated.
+    assert_eq!(store.get_policies().await.len(), 2);
+    assert!(!store.get_policy("disabled").await.unwrap().enabled);
+    assert_eq!(store.policy_set().await.policies().count(), 1);
+
+    let policy = store.set_policy_enabled("all", false).await.unwrap();
+    assert!(!policy.enabled);
+    assert_eq!(store.policy_set().await.policies().count(), 0);
+
+    // Updating the content keeps the policy disabled, with an update or an upsert.
+    store
+        .update_policy(
+            "all".to_string(),
+            PolicyUpdate {
+                content: approve_all_policy(None).content,
+                metadata: None,
+                schedule: None,
+            },
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    assert!(!store.get_policy("all").await.unwrap().enabled);
+    let upsert = json!({"id": "all", "content": approve_all_policy(None).content});
+    store
+        .apply_policy_operations(
+            vec![PolicyOperation::Upsert(rocket::serde::json::from_value(upsert).unwrap())],
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    assert!(!store.get_policy("all").await.unwrap().enabled);
+
+    store.set_policy_enabled("all", true).await.unwrap();
+    store.set_policy_enabled("disabled", true).await.unwrap();
+    assert_eq!(store.policy_set().await.policies().count(), 2);
+    assert!(store.set_policy_enabled("missing", true).await.is_err());
+}
+
+#[tokio::test]
+async fn schedule_tests() {
+    let store = MemoryPolicyStore::new();
+    let now = Utc::now();
+    let mut expired = approve_all_policy(Some("expired".to_string()));
+    expired.schedule.not_after = Some(now - chrono::Duration::hours(1));
+    let mut upcoming = approve_admin_policy(Some("upcoming".to_string()));
+    upcoming.schedule.not_before = Some(now + chrono::Duration::hours(1));
+    let mut expiring = annotated_forbid_policy(Some("expiring".to_string()));
+    expiring.schedule.not_after = Some(now + chrono::Duration::milliseconds(200));
+    store
+        .update_policies(vec![expired, upcoming, expiring], None, ValidationSettings::default())
+        .await
+        .unwrap();
+    // Only the policy inside of its window is evaluated.
+    assert_eq!(store.get_policies().await.len(), 3);
+    let policy_set = store.policy_set().await;
+    assert_eq!(policy_set.policies().count(), 1);
+    assert!(policy_set.policy(&PolicyId::from_str("expiring").unwrap()).is_some());
+
+    let transitions = store.get_policy_transitions().await;
+    assert_eq!(transitions.len(), 2);
+    assert_eq!(transitions[0].policy_id, "expiring");
+    assert_eq!(transitions[0].kind, PolicyTransitionKind::Expiration);
+    assert_eq!(transitions[1].policy_id, "upcoming");
+    assert_eq!(transitions[1].kind, PolicyTransitionKind::Activation);
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    store.refresh_policy_schedule().await;
+    assert_eq!(store.policy_set().await.policies().count(), 0);
+    assert_eq!(store.get_policy_transitions().await.len(), 1);
+
+    // Updating the content keeps the schedule.
+    store
+        .update_policy(
+            "upcoming".to_string(),
+            PolicyUpdate {
+                content: approve_all_policy(None).content,
+                metadata: None,
+                schedule: None,
+            },
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    assert!(store.get_policy("upcoming").await.unwrap().schedule.not_before.is_some());
+
+    // So does an upsert leaving the bounds out, a bound set to `null` is removed.
+    let upsert = |upsert| PolicyOperation::Upsert(rocket::serde::json::from_value(upsert).unwrap());
+    let content = approve_all_policy(None).content;
+    store
+        .apply_policy_operations(
+            vec![
+                upsert(json!({"id": "upcoming", "content": content})),
+                upsert(json!({"id": "expired", "content": content, "not_after": null})),
+            ],
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    assert!(store.get_policy("upcoming").await.unwrap().schedule.not_before.is_some());
+    assert_eq!(store.get_policy("expired").await.unwrap().schedule, PolicySchedule::default());
+
+    let mut invalid = approve_all_policy(Some("invalid".to_string()));
+    invalid.schedule = PolicySchedule {
+        not_before: Some(now),
+        not_after: Some(now - chrono::Duration::hours(1)),
+    };
+    assert!(store.create_policy(&invalid, None, ValidationSettings::default()).await.is_err());
+}
+
+#[tokio::test]
+async fn policy_set_tests() {
+    let store = MemoryPolicyStore::new();
+    store
+        .update_policies(
+            vec![approve_all_policy(Some("all".to_string())), approve_admin_policy(Some("admin".to_string()))],
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    let all = PolicyId::from_str("all").unwrap();
+
+    // Replacing an active policy swaps it in the evaluated set.
+    store
+        .update_policy(
+            "all".to_string(),
+            PolicyUpdate {
+                content: annotated_forbid_policy(None).content,
+                metadata: None,
+                schedule: None,
+            },
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    let policy_set = store.policy_set().await;
+    assert_eq!(policy_set.policies().count(), 2);
+    assert_eq!(policy_set.policy(&all).unwrap().effect(), cedar_policy::Effect::Forbid);
+
+    store.set_policy_enabled("all", false).await.unwrap();
+    store.delete_policy("admin").await.unwrap();
+    assert_eq!(store.policy_set().await.policies().count(), 0);
+
+    store.set_policy_enabled("all", true).await.unwrap();
+    store
+        .create_policy(&approve_admin_policy(Some("admin".to_string())), None, ValidationSettings::default())
+        .await
+        .unwrap();
+    let policy_set = store.policy_set().await;
+    assert_eq!(policy_set.policies().count(), 2);
+    assert_eq!(policy_set.policy(&all).unwrap().effect(), cedar_policy::Effect::Forbid);
+}
+
+#[tokio::test]
+async fn index_tests() {
+    let store = MemoryPolicyStore::new();
+    let policy = |id: &str, content: &str| Policy {
+        id: id.to_string(),
+        content: content.to_string(),
+        ..Default::default()
+    };
+    store
+        .update_policies(
+            vec![
+                policy("editors", r#"permit(principal in Role::"Editor", action == Action::"document:get", resource);"#),
+                policy("updates", r#"permit(principal, action in [Action::"document:update"], resource == ResourceType::"document");"#),
+                policy("admin", r#"permit(principal == User::"admin@domain.com", action, resource);"#),
+                policy("everyone", r#"forbid(principal, action, resource) when { context.blocked };"#),
+            ],
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    let entities = entities().convert_to_cedar_entities(&None).unwrap();
+    let authorizer = Authorizer::new();
+
+    let cases = [
+        (r#"User::"editor-1@domain.com""#, r#"Action::"document:get""#, r#"ResourceType::"document""#, vec!["editors", "everyone"]),
+        (r#"User::"editor-1@domain.com""#, r#"Action::"document:delete""#, r#"ResourceType::"document""#, vec!["everyone"]),
+        (r#"User::"editor-1@domain.com""#, r#"Action::"document:create""#, r#"ResourceType::"document""#, vec!["everyone", "updates"]),
+        (r#"User::"admin@domain.com""#, r#"Action::"document:list""#, r#"ResourceType::"other""#, vec!["admin", "everyone"]),
+    ];
+    for (principal, action, resource, expected) in cases {
+        let request = Request::new(
+            Some(EntityUid::from_str(principal).unwrap()),
+            Some(EntityUid::from_str(action).unwrap()),
+            Some(EntityUid::from_str(resource).unwrap()),
+            Context::from_json_value(json!({"blocked": false}), None).unwrap(),
+        );
+        let matching = store.matching_policy_set(&request, &entities).await;
+        let mut ids: Vec<String> = matching.policies().map(|policy| policy.id().to_string()).collect();
+        ids.sort();
+        assert_eq!(ids, expected);
+
+        // The pre-filtered set decides exactly like the full set.
+        let full = authorizer.is_authorized(&request, &store.policy_set().await, &entities);
+        let indexed = authorizer.is_authorized(&request, &matching, &entities);
+        assert_eq!(full.decision(), indexed.decision());
+        assert_eq!(
+            full.diagnostics().reason().collect::<HashSet<_>>(),
+            indexed.diagnostics().reason().collect::<HashSet<_>>()
+        );
+    }
+
+    // Unspecified request entities are not filtered.
+    let request = Request::new(None, None, None, Context::empty());
+    assert_eq!(store.matching_policy_set(&request, &entities).await.policies().count(), 4);
+
+    // Matched sets are shared until the policies change.
+    let matched = store.matching_policy_set(&request, &entities).await;
+    assert!(Arc::ptr_eq(&matched, &store.matching_policy_set(&request, &entities).await));
+    store
+        .update_policies(
+            vec![policy("guest", r#"permit(principal == User::"guest", action, resource);"#)],
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    let changed = store.matching_policy_set(&request, &entities).await;
+    assert!(!Arc::ptr_eq(&matched, &changed));
+    assert_eq!(changed.policies().count(), 1);
+}
+
+#[tokio::test]
+async fn generated_id_tests() {
+    let store = MemoryPolicyStore::new().with_id_pattern(Some("team-{id}".to_string()));
+    let unnamed = |content: &str| Policy {
+        content: content.to_string(),
+        ..Default::default()
+    };
+
+    // Without an `@id` annotation a UUID is generated.
+    let created = store
+        .create_policy(&approve_admin_policy(Some(String::new())), None, ValidationSettings::default())
+        .await
+        .unwrap();
+    assert_eq!(created.id.len(), 36);
+    assert_eq!(&created.id[14..15], "4");
+    assert!(store.get_policy(&created.id).await.is_ok());
+
+    let annotated = unnamed("@id(\"Block Suspended!\")\nforbid(principal, action, resource);");
+    let created = store.create_policy(&annotated, None, ValidationSettings::default()).await.unwrap();
+    assert_eq!(created.id, "team-block-suspended");
+    let created = store.create_policy(&annotated, None, ValidationSettings::default()).await.unwrap();
+    assert_eq!(created.id, "team-block-suspended-2");
+    assert!(store
+        .policy_set()
+        .await
+        .policy(&PolicyId::from_str("team-block-suspended-2").unwrap())
+        .is_some());
+
+    // Bulk replace doesn't hand out ids submitted explicitly.
+    let explicit = Policy {
+        id: "team-block-suspended".to_string(),
+        ..approve_all_policy(None)
+    };
+    let mut ids: Vec<String> = store
+        .update_policies(
+            vec![annotated.clone(), explicit, unnamed("permit(principal, action, resource);")],
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|policy| policy.id)
+        .collect();
+    ids.sort();
+    assert_eq!(ids.len(), 3);
+    assert_eq!(ids[1..], ["team-block-suspended".to_string(), "team-block-suspended-2".to_string()]);
+    assert_eq!(ids[0].len(), 36);
+
+    // Transactions generate ids too, without handing out the ids other operations name.
+    let results = store
+        .apply_policy_operations(
+            vec![
+                PolicyOperation::Create(annotated.clone()),
+                PolicyOperation::Upsert(unnamed("permit(principal, action, resource);").into()),
+                PolicyOperation::Delete { id: "team-block-suspended-3".to_string() },
+            ],
+            None,
+            ValidationSettings::default(),
+        )
+        .await;
+    assert!(results.unwrap_err().to_string().contains("operation 2"));
+    let results = store
+        .apply_policy_operations(
+            vec![
+                PolicyOperation::Create(annotated),
+                PolicyOperation::Upsert(unnamed("permit(principal, action, resource);").into()),
+            ],
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(results[0].id, "team-block-suspended-3");
+    assert_eq!(results[0].policy.id, "team-block-suspended-3");
+    assert_eq!(results[1].status, PolicyOperationStatus::Created);
+    assert_eq!(results[1].id.len(), 36);
+    assert!(store.get_policy("").await.is_err());
+}
+
+async fn filtered_ids(store: &MemoryPolicyStore, filter: PolicyFilter) -> Vec<String> {
+    let mut ids: Vec<String> = store.find_policies(&filter).await.into_iter().map(|p| p.id).collect();
+    ids.sort();
+    ids
+}
+
 #[tokio::test]
 async fn test_load_policies_from_file() {
     let policies = load_policies_from_file(PathBuf::from("./examples/policies.json")).await.unwrap();
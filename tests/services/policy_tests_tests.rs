@@ -0,0 +1,76 @@
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::policy_tests::memory::MemoryPolicyTestStore;
+use cedar_agent::policy_tests::runner::run_tests;
+use cedar_agent::schemas::authorization::{AuthorizationCall, DecisionRef};
+use cedar_agent::schemas::policies::ValidationSettings;
+use cedar_agent::schemas::policy_tests::PolicyTest;
+use cedar_agent::{DataStore, PolicyStore, PolicyTestStore};
+
+use crate::services::utils;
+
+fn admin_test(id: &str, decision: DecisionRef, reasons: Option<Vec<String>>) -> PolicyTest {
+    PolicyTest {
+        id: id.to_string(),
+        description: None,
+        request: AuthorizationCall::new(
+            Some("User::\"admin@domain.com\"".to_string()),
+            Some("Action::\"delete\"".to_string()),
+            Some("Document::\"cedar-agent.pdf\"".to_string()),
+            None,
+            None,
+            None,
+            None,
+        ),
+        decision,
+        reasons,
+    }
+}
+
+#[tokio::test]
+async fn memory_tests() {
+    let store = MemoryPolicyTestStore::new();
+    assert!(store.get_tests().await.is_empty());
+
+    store.create_test(admin_test("allowed", DecisionRef::Allow, None)).await.unwrap();
+    assert!(store.create_test(admin_test("allowed", DecisionRef::Allow, None)).await.is_err());
+    let updated = store
+        .update_test("allowed".to_string(), admin_test("other", DecisionRef::Deny, None))
+        .await
+        .unwrap();
+    assert_eq!(updated.id, "allowed");
+    assert_eq!(store.get_test("allowed").await.unwrap().decision, DecisionRef::Deny);
+
+    store.delete_test("allowed").await.unwrap();
+    assert!(store.get_test("allowed").await.is_err());
+    assert!(store.delete_test("allowed").await.is_err());
+}
+
+#[tokio::test]
+async fn runner_tests() {
+    let policy_store = MemoryPolicyStore::new();
+    let data_store = MemoryDataStore::new();
+    policy_store
+        .update_policies(
+            vec![utils::approve_admin_policy(Some("admin".to_string()))],
+            None,
+            ValidationSettings::default(),
+        )
+        .await
+        .unwrap();
+    data_store.update_entities(utils::entities(), None).await.unwrap();
+
+    let tests = vec![
+        admin_test("allowed", DecisionRef::Allow, Some(vec!["admin".to_string()])),
+        admin_test("denied", DecisionRef::Deny, None),
+        admin_test("wrong-reason", DecisionRef::Allow, Some(vec!["other".to_string()])),
+    ];
+    let report = run_tests(&tests, &policy_store.policy_set().await, &data_store.entities().await);
+    assert_eq!((report.passed, report.failed), (1, 2));
+
+    let failures = report.failures();
+    let denied = failures.iter().find(|r| r.id == "denied").unwrap();
+    assert_eq!(denied.decision, Some(DecisionRef::Allow));
+    assert_eq!(denied.reasons, vec!["admin".to_string()]);
+    assert!(failures.iter().any(|r| r.id == "wrong-reason"));
+}
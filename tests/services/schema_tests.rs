@@ -4,6 +4,11 @@ use cedar_agent::schema::load_from_file::load_schema_from_file;
 use cedar_agent::schema::memory::MemorySchemaStore;
 use cedar_agent::policies::memory::MemoryPolicyStore;
 use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::policies::errors::PolicyStoreError;
+use cedar_agent::schemas::policies::{
+    Policy, PolicyErrorKind, PolicyUpdate, PolicyValidationMode, ValidationLevel,
+    ValidationSettings,
+};
 use cedar_agent::{SchemaStore, PolicyStore, DataStore};
 
 use crate::services::utils;
@@ -42,7 +47,8 @@ async fn test_validate_policy() {
     let valid_policies = policy_store
         .update_policies(
             vec![utils::schema_valid_policy(Some("valid".to_string()))],
-            schema_store.get_cedar_schema().await
+            schema_store.get_cedar_schema().await,
+            ValidationSettings::default()
         ).await;
     assert!(!valid_policies.is_err());
 
@@ -53,11 +59,127 @@ async fn test_validate_policy() {
     let invalid_policies = policy_store
         .update_policies(
             vec![utils::schema_invalid_policy(Some("invalid".to_string()))],
-            schema_store.get_cedar_schema().await
+            schema_store.get_cedar_schema().await,
+            ValidationSettings::default()
         ).await;
     assert!(invalid_policies.is_err());
 }
 
+#[tokio::test]
+async fn test_structured_policy_errors() {
+    let policy_store = MemoryPolicyStore::new();
+    let schema_store = MemorySchemaStore::new();
+    schema_store.update_schema(utils::schema()).await.unwrap();
+
+    let mut parse_error = utils::parse_error_policy();
+    parse_error.content = "permit(principal,action,resource)\nwhen { principal.".to_string();
+    let result = policy_store
+        .update_policies(
+            vec![
+                utils::schema_valid_policy(Some("valid".to_string())),
+                utils::schema_invalid_policy(Some("invalid".to_string())),
+                parse_error,
+                Policy {
+                    id: "type-error".to_string(),
+                    content: "permit(principal,action,resource)\nwhen { principal.jobLevel == \"high\" };".to_string(),
+                    ..Default::default()
+                },
+            ],
+            schema_store.get_cedar_schema().await,
+            ValidationSettings::default()
+        ).await;
+    let err = result.unwrap_err();
+    let errors = match err.downcast_ref::<PolicyStoreError>() {
+        Some(PolicyStoreError::PolicyInvalid(_, errors)) => errors,
+        _ => panic!("expected structured policy errors, got {}", err),
+    };
+
+    // Every failing policy is reported, not only the first one.
+    let parse = errors.iter().find(|e| e.policy_id == "error").unwrap();
+    assert_eq!(parse.kind, PolicyErrorKind::ParseError);
+    let span = parse.span.as_ref().unwrap();
+    assert_eq!(span.line, 2);
+    let invalid = errors.iter().find(|e| e.policy_id == "invalid").unwrap();
+    assert_eq!(invalid.kind, PolicyErrorKind::UnrecognizedEntityType);
+    let type_error = errors.iter().find(|e| e.policy_id == "type-error").unwrap();
+    assert_eq!(type_error.kind, PolicyErrorKind::TypeError);
+    let span = type_error.span.as_ref().unwrap();
+    assert_eq!((span.line, span.column), (2, 8));
+    assert!(span.start < span.end);
+    assert!(errors.iter().all(|e| e.policy_id != "valid"));
+    assert!(policy_store.get_policies().await.is_empty());
+
+    let err = policy_store
+        .create_policy(
+            &utils::schema_invalid_policy(Some("invalid".to_string())),
+            schema_store.get_cedar_schema().await,
+            ValidationSettings::default()
+        ).await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PolicyStoreError>(),
+        Some(PolicyStoreError::PolicyInvalid(id, _)) if id == "invalid"
+    ));
+}
+
+#[tokio::test]
+async fn test_validation_levels() {
+    let policy_store = MemoryPolicyStore::new();
+    let schema_store = MemorySchemaStore::new();
+    schema_store.update_schema(utils::schema()).await.unwrap();
+    let warn = ValidationSettings::new(ValidationLevel::Warn, PolicyValidationMode::Strict);
+
+    // Unset values fall back to the configured ones, then to enforce.
+    let settings = ValidationSettings::default().or(warn);
+    assert_eq!(settings.level(), ValidationLevel::Warn);
+    assert_eq!(ValidationSettings::default().level(), ValidationLevel::Enforce);
+
+    let policies = policy_store
+        .update_policies(
+            vec![
+                utils::schema_valid_policy(Some("valid".to_string())),
+                utils::schema_invalid_policy(Some("invalid".to_string())),
+            ],
+            schema_store.get_cedar_schema().await,
+            warn,
+        ).await
+        .unwrap();
+    assert_eq!(policies.len(), 2);
+    let invalid = policies.iter().find(|p| p.id == "invalid").unwrap();
+    assert_eq!(invalid.validation_errors[0].kind, PolicyErrorKind::UnrecognizedEntityType);
+    assert!(policies.iter().find(|p| p.id == "valid").unwrap().validation_errors.is_empty());
+
+    let flagged = policy_store.get_invalid_policies().await;
+    assert_eq!(flagged.len(), 1);
+    assert_eq!(flagged[0].id, "invalid");
+
+    // Fixing the policy removes it from the invalid policies.
+    policy_store
+        .update_policy(
+            "invalid".to_string(),
+            PolicyUpdate {
+                content: utils::schema_valid_policy(None).content,
+                metadata: None,
+                schedule: None,
+            },
+            schema_store.get_cedar_schema().await,
+            warn,
+        ).await
+        .unwrap();
+    assert!(policy_store.get_invalid_policies().await.is_empty());
+
+    let off = ValidationSettings::new(ValidationLevel::Off, PolicyValidationMode::Strict);
+    let created = policy_store
+        .create_policy(
+            &utils::schema_invalid_policy(Some("unchecked".to_string())),
+            schema_store.get_cedar_schema().await,
+            off,
+        ).await
+        .unwrap();
+    assert!(created.validation_errors.is_empty());
+    assert!(policy_store.get_invalid_policies().await.is_empty());
+}
+
 #[tokio::test]
 async fn test_validate_entities() {
     let data_store = MemoryDataStore::new();
@@ -67,7 +189,7 @@ async fn test_validate_entities() {
     let valid_entities = data_store
         .update_entities(
             utils::entities(),
-            schema_store.get_cedar_schema().await
+            schema_store.get_validator_schema().await
         ).await;
     assert!(!valid_entities.is_err());
     assert_eq!(valid_entities.unwrap().len(), 8);
@@ -75,7 +197,7 @@ async fn test_validate_entities() {
     let invalid_entities = data_store
         .update_entities(
             utils::parse_error_entities(),
-            schema_store.get_cedar_schema().await
+            schema_store.get_validator_schema().await
         ).await;
     assert!(invalid_entities.is_err());
 }
@@ -0,0 +1,197 @@
+use cedar_agent::policies::index::IndexedPolicySet;
+use cedar_agent::policies::search::{condition_entities, missing_entity_references, search_policies};
+use cedar_agent::schemas::policies::{
+    Policy, PolicyEffect, PolicyReference, PolicyReferenceKind, PolicySearch, SourceSpan,
+};
+
+fn policy(id: &str, content: &str) -> Policy {
+    Policy {
+        id: id.to_string(),
+        content: content.to_string(),
+        ..Default::default()
+    }
+}
+
+fn policies() -> Vec<Policy> {
+    vec![
+        policy(
+            "alice-read",
+            r#"permit(principal == User::"alice", action == Action::"read", resource);"#,
+        ),
+        policy(
+            "owners-delete",
+            r#"permit(principal, action in [Action::"update", Action::"delete"], resource)
+               when { resource.owner == principal };"#,
+        ),
+        policy(
+            "block-alice",
+            r#"forbid(principal, action, resource)
+               when { principal == User::"alice" && context has owner };"#,
+        ),
+        // Mentions alice only inside a string literal.
+        policy(
+            "alice-string",
+            r#"permit(principal, action, resource) when { principal.name == "User::\"alice\"" };"#,
+        ),
+    ]
+}
+
+/// The text of the policy content a reference spans.
+fn spanned(index: usize, reference: &PolicyReference) -> String {
+    let span = reference.span.as_ref().unwrap();
+    policies()[index].content[span.start..span.end].to_string()
+}
+
+fn ids(search: PolicySearch) -> Vec<String> {
+    search_policies(&policies(), &search)
+        .unwrap()
+        .into_iter()
+        .map(|m| m.id)
+        .collect()
+}
+
+#[test]
+fn entity_search_tests() {
+    let search = PolicySearch {
+        entity: Some(r#"User::"alice""#.to_string()),
+        ..Default::default()
+    };
+    let matches = search_policies(&policies(), &search).unwrap();
+    assert_eq!(matches.len(), 2);
+    assert_eq!(matches[0].id, "alice-read");
+    assert_eq!(
+        matches[0].references,
+        vec![PolicyReference {
+            kind: PolicyReferenceKind::Entity,
+            value: r#"User::"alice""#.to_string(),
+            span: Some(SourceSpan {
+                start: 20,
+                end: 33,
+                line: 1,
+                column: 21,
+                end_line: 1,
+                end_column: 34,
+            }),
+        }]
+    );
+    assert_eq!(matches[1].id, "block-alice");
+    assert_eq!(matches[1].references.len(), 1);
+    let span = matches[1].references[0].span.as_ref().unwrap();
+    assert_eq!((span.line, spanned(2, &matches[1].references[0]).as_str()), (2, r#"User::"alice""#));
+
+    let search = PolicySearch {
+        entity_type: Some("User".to_string()),
+        effect: Some(PolicyEffect::Forbid),
+        ..Default::default()
+    };
+    assert_eq!(ids(search), vec!["block-alice"]);
+
+    let search = PolicySearch {
+        entity: Some("not a uid".to_string()),
+        ..Default::default()
+    };
+    assert!(search_policies(&policies(), &search).is_err());
+}
+
+#[test]
+fn action_search_tests() {
+    let search = PolicySearch {
+        action: Some(r#"Action::"delete""#.to_string()),
+        ..Default::default()
+    };
+    let matches = search_policies(&policies(), &search).unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(
+        matches[0].references,
+        vec![PolicyReference {
+            kind: PolicyReferenceKind::Action,
+            value: r#"Action::"delete""#.to_string(),
+            span: Some(SourceSpan {
+                start: 47,
+                end: 63,
+                line: 1,
+                column: 48,
+                end_line: 1,
+                end_column: 64,
+            }),
+        }]
+    );
+
+    let search = PolicySearch {
+        action: Some("read".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ids(search), vec!["alice-read"]);
+}
+
+#[test]
+fn attribute_search_tests() {
+    let search = PolicySearch {
+        attribute: Some("owner".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ids(search), vec!["block-alice", "owners-delete"]);
+
+    let search = PolicySearch {
+        attribute: Some("resource.owner".to_string()),
+        ..Default::default()
+    };
+    let matches = search_policies(&policies(), &search).unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].id, "owners-delete");
+    assert_eq!(matches[0].references[0].kind, PolicyReferenceKind::Attribute);
+    assert_eq!(spanned(1, &matches[0].references[0]), "resource.owner");
+
+    // Every criterion has to match.
+    let search = PolicySearch {
+        attribute: Some("owner".to_string()),
+        action: Some("delete".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ids(search), vec!["owners-delete"]);
+}
+
+#[test]
+fn missing_entity_tests() {
+    let missing = missing_entity_references(&policies(), |uid| uid.to_string() != r#"User::"alice""#);
+    let ids: Vec<&str> = missing.iter().map(|found| found.id.as_str()).collect();
+    assert_eq!(ids, vec!["alice-read", "block-alice"]);
+    // Actions are not looked up in the data.
+    assert_eq!(missing[0].references.len(), 1);
+    assert_eq!(spanned(0, &missing[0].references[0]), r#"User::"alice""#);
+    assert!(missing_entity_references(&policies(), |_| true).is_empty());
+}
+
+#[test]
+fn condition_entity_tests() {
+    let uids = |policy: &Policy| {
+        let parsed: cedar_policy::Policy = policy.try_into().unwrap();
+        condition_entities(&parsed).iter().map(|uid| uid.to_string()).collect::<Vec<String>>()
+    };
+    // Entities of the scope are matched against the request, they aren't read.
+    assert!(uids(&policies()[0]).is_empty());
+    assert_eq!(uids(&policies()[2]), vec![r#"User::"alice""#]);
+}
+
+#[test]
+fn indexed_policy_set_remove_tests() {
+    let parsed: Vec<cedar_policy::Policy> = policies().iter().map(|policy| policy.try_into().unwrap()).collect();
+    let mut indexed = IndexedPolicySet::default();
+    for policy in &parsed {
+        indexed.add(policy.clone()).unwrap();
+    }
+    assert!(indexed.add(parsed[0].clone()).is_err());
+
+    let id = parsed[2].id().clone();
+    // A copy shares the policies, removing from the index leaves it unchanged.
+    let copy = indexed.clone();
+    assert!(copy.policy_set().policy(&id).is_some());
+    indexed.remove(&[id.clone()]);
+    assert!(!indexed.contains(&id));
+    assert!(copy.contains(&id));
+    assert!(copy.policy_set().policy(&id).is_some());
+    assert!(indexed.policy_set().policy(&id).is_none());
+    assert_eq!(indexed.policies().count(), parsed.len() - 1);
+    // The entities only the removed policy named are dropped.
+    assert_eq!(indexed.condition_entities().count(), 0);
+}
@@ -0,0 +1,215 @@
+use std::sync::{Arc, Mutex};
+
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::data::sources::{parse_path, select, DataSources, PathStep, SourceGate};
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::policy_tests::memory::MemoryPolicyTestStore;
+use cedar_agent::policy_tests::TestGate;
+use cedar_agent::schema::memory::MemorySchemaStore;
+use cedar_agent::schemas::authorization::{AuthorizationCall, DecisionRef};
+use cedar_agent::schemas::data as schemas;
+use cedar_agent::schemas::policies::{Policy, ValidationSettings};
+use cedar_agent::schemas::policy_tests::PolicyTest;
+use cedar_agent::{DataStore, PolicyStore, PolicyTestStore};
+use cedar_policy::EntityUid;
+use rocket::serde::json::{json, Value};
+use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+use rocket::tokio::net::TcpListener;
+
+/// What the HTTP stand-in serves, and the requests it answered with 304.
+struct Served {
+    status: u16,
+    etag: String,
+    body: Value,
+    not_modified: usize,
+}
+
+/// Serve the entities over HTTP, honouring If-None-Match.
+async fn serve(served: Arc<Mutex<Served>>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/entities", listener.local_addr().unwrap());
+    rocket::tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 8192];
+            let read = socket.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+            let response = {
+                let mut served = served.lock().unwrap();
+                let etag = format!("\"{}\"", served.etag);
+                if request.contains(&format!("if-none-match: {}", etag)) {
+                    served.not_modified += 1;
+                    format!("HTTP/1.1 304 Not Modified\r\nETag: {}\r\nConnection: close\r\n\r\n", etag)
+                } else {
+                    let body = served.body.to_string();
+                    format!(
+                        "HTTP/1.1 {} Status\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        served.status,
+                        etag,
+                        body.len(),
+                        body
+                    )
+                }
+            };
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    url
+}
+
+fn user(id: &str) -> Value {
+    json!({"uid": {"type": "User", "id": id}, "attrs": {}, "parents": []})
+}
+
+fn uid(id: &str) -> EntityUid {
+    format!("User::\"{}\"", id).parse().unwrap()
+}
+
+fn source(name: &str, location: Value, path: Option<&str>) -> schemas::DataSourceConfig {
+    let mut config = json!({"name": name, "interval": 1, "path": path});
+    config.as_object_mut().unwrap().extend(location.as_object().unwrap().clone());
+    rocket::serde::json::from_value(config).unwrap()
+}
+
+#[test]
+fn path_tests() {
+    assert_eq!(parse_path(".").unwrap(), vec![]);
+    assert_eq!(
+        parse_path(".data.entities[1]").unwrap(),
+        vec![
+            PathStep::Key("data".to_string()),
+            PathStep::Key("entities".to_string()),
+            PathStep::Index(1)
+        ]
+    );
+    assert_eq!(parse_path(".[\"odd key\"]").unwrap(), vec![PathStep::Key("odd key".to_string())]);
+    assert!(parse_path("data").is_err());
+    assert!(parse_path(".data..entities").is_err());
+    assert!(parse_path(".items[-1]").is_err());
+
+    let document = json!({"data": {"entities": [1, 2]}});
+    assert_eq!(select(document.clone(), &parse_path(".data.entities[1]").unwrap()), Some(json!(2)));
+    assert_eq!(select(document, &parse_path(".data.users").unwrap()), None);
+}
+
+#[tokio::test]
+async fn source_tests() {
+    let served = Arc::new(Mutex::new(Served {
+        status: 200,
+        etag: "v1".to_string(),
+        body: json!({"data": {"entities": [user("alice"), user("bob")]}}),
+        not_modified: 0,
+    }));
+    let url = serve(served.clone()).await;
+    let file = std::env::temp_dir().join(format!("cedar-agent-source-{}.json", std::process::id()));
+    std::fs::write(&file, json!([user("carol")]).to_string()).unwrap();
+
+    let sources = DataSources::new(vec![
+        source("hr", json!({"url": url}), Some(".data.entities")),
+        source("devices", json!({"file": file}), None),
+    ])
+    .unwrap();
+    let data_store = MemoryDataStore::new();
+    let schema_store = MemorySchemaStore::new();
+    let refresh = || async {
+        for source in sources.sources() {
+            source.refresh(sources.client(), &data_store, &schema_store, None).await;
+        }
+    };
+    refresh().await;
+    assert_eq!(data_store.get_entities().await.len(), 3);
+    let status = sources.status().await;
+    assert_eq!(status[0].entities, 2);
+    assert!(status.iter().all(|status| status.last_error.is_none() && status.last_update.is_some()));
+
+    // Unchanged sources aren't stored again.
+    data_store.upsert_entities(rocket::serde::json::from_value(json!([user("dave")])).unwrap(), None).await.unwrap();
+    refresh().await;
+    assert_eq!(served.lock().unwrap().not_modified, 1);
+    assert_eq!(sources.status().await[0].last_update, status[0].last_update);
+
+    // A refresh only replaces the entities of its own source.
+    {
+        let mut served = served.lock().unwrap();
+        served.etag = "v2".to_string();
+        served.body = json!({"data": {"entities": [user("alice")]}});
+    }
+    refresh().await;
+    assert!(data_store.get_entity(&uid("bob")).await.is_none());
+    for kept in ["alice", "carol", "dave"] {
+        assert!(data_store.get_entity(&uid(kept)).await.is_some());
+    }
+
+    // Failures are reported and leave the stored entities alone.
+    {
+        let mut served = served.lock().unwrap();
+        served.status = 500;
+        served.etag = "v3".to_string();
+    }
+    refresh().await;
+    let status = sources.status().await;
+    assert!(status[0].last_error.as_deref().is_some_and(|error| error.contains("500")));
+    assert_eq!(status[0].entities, 1);
+    assert!(data_store.get_entity(&uid("alice")).await.is_some());
+
+    // Entities deleted as a whole are fetched again even though the source is unchanged.
+    served.lock().unwrap().status = 200;
+    data_store.delete_entities().await;
+    refresh().await;
+    assert_eq!(data_store.get_entities().await.len(), 2);
+    std::fs::remove_file(&file).unwrap();
+
+    assert!(DataSources::new(vec![source("hr", json!({}), None)]).is_err());
+    assert!(DataSources::new(vec![source("hr", json!({"url": "ftp://example.com"}), None)]).is_err());
+}
+
+#[tokio::test]
+async fn gated_source_tests() {
+    let policy_store = Arc::new(MemoryPolicyStore::new());
+    let admins = Policy {
+        id: "admins".to_string(),
+        content: "permit(principal in Group::\"admins\", action, resource);".to_string(),
+        ..Default::default()
+    };
+    policy_store.update_policies(vec![admins], None, ValidationSettings::default()).await.unwrap();
+    let test_store = Arc::new(MemoryPolicyTestStore::new());
+    let test = PolicyTest {
+        id: "alice-denied".to_string(),
+        description: None,
+        request: AuthorizationCall::new(
+            Some("User::\"alice\"".to_string()),
+            Some("Action::\"read\"".to_string()),
+            Some("Document::\"cedar-agent.pdf\"".to_string()),
+            None,
+            None,
+            None,
+            None,
+        ),
+        decision: DecisionRef::Deny,
+        reasons: None,
+    };
+    test_store.create_test(test).await.unwrap();
+    let gate = SourceGate {
+        test_gate: Arc::new(TestGate::default()),
+        test_store,
+        policy_store,
+    };
+
+    let file = std::env::temp_dir().join(format!("cedar-agent-gated-source-{}.json", std::process::id()));
+    let mut alice = user("alice");
+    alice["parents"] = json!([{"type": "Group", "id": "admins"}]);
+    std::fs::write(&file, json!([alice]).to_string()).unwrap();
+    let sources = DataSources::new(vec![source("hr", json!({"file": file}), None)]).unwrap();
+    let data_store = MemoryDataStore::new();
+    let schema_store = MemorySchemaStore::new();
+    let source = &sources.sources()[0];
+    source.refresh(sources.client(), &data_store, &schema_store, Some(&gate)).await;
+    let status = sources.status().await;
+    assert!(status[0].last_error.as_deref().is_some_and(|error| error.contains("alice-denied")));
+    assert_eq!(data_store.count_entities().await, 0);
+
+    std::fs::write(&file, json!([user("alice")]).to_string()).unwrap();
+    source.refresh(sources.client(), &data_store, &schema_store, Some(&gate)).await;
+    assert_eq!(data_store.count_entities().await, 1);
+    std::fs::remove_file(&file).unwrap();
+}
@@ -0,0 +1,98 @@
+use cedar_agent::policies::errors::PolicyStoreError;
+use cedar_agent::schemas::policies::ValidationSettings;
+use cedar_agent::schemas::tenants::{TenantCreate, TenantQuotas};
+use cedar_agent::tenants::errors::TenantStoreError;
+use cedar_agent::tenants::memory::MemoryTenantStore;
+use cedar_agent::TenantStore;
+
+use crate::services::utils;
+
+fn tenant(name: &str, quotas: TenantQuotas) -> TenantCreate {
+    TenantCreate {
+        name: name.to_string(),
+        quotas,
+        api_key: Some(format!("{}-key", name)),
+    }
+}
+
+#[tokio::test]
+async fn memory_tests() {
+    let store = MemoryTenantStore::new();
+    assert!(store.get_tenants().await.is_empty());
+
+    store.create_tenant(tenant("acme", TenantQuotas::default())).await.unwrap();
+    store.create_tenant(tenant("globex", TenantQuotas::default())).await.unwrap();
+    let duplicate = store.create_tenant(tenant("acme", TenantQuotas::default())).await;
+    assert!(matches!(
+        duplicate.unwrap_err().downcast_ref::<TenantStoreError>(),
+        Some(TenantStoreError::TenantAlreadyExists(_))
+    ));
+    let invalid = store.create_tenant(tenant("acme/admin", TenantQuotas::default())).await;
+    assert!(matches!(
+        invalid.unwrap_err().downcast_ref::<TenantStoreError>(),
+        Some(TenantStoreError::InvalidTenantName(_))
+    ));
+
+    // Every tenant has its own stores.
+    let acme = store.get_tenant("acme").await.unwrap();
+    assert_eq!(acme.api_key, Some("acme-key".to_string()));
+    acme.policy_store
+        .update_policies(vec![utils::approve_all_policy(None)], None, ValidationSettings::default())
+        .await
+        .unwrap();
+    acme.data_store.update_entities(utils::entities(), None).await.unwrap();
+    let globex = store.get_tenant("globex").await.unwrap();
+    assert!(globex.policy_store.get_policies().await.is_empty());
+    assert_eq!(globex.data_store.get_entities().await.len(), 0);
+
+    let tenants = store.get_tenants().await;
+    assert_eq!(tenants.len(), 2);
+    assert_eq!((tenants[0].name.as_str(), tenants[0].policies, tenants[0].entities), ("acme", 1, 8));
+
+    let deleted = store.delete_tenant("acme").await.unwrap();
+    assert_eq!(deleted.policies, 1);
+    assert!(store.get_tenant("acme").await.is_err());
+    assert!(store.delete_tenant("acme").await.is_err());
+}
+
+#[tokio::test]
+async fn quota_tests() {
+    let store = MemoryTenantStore::new();
+    let quotas = TenantQuotas {
+        max_policies: Some(1),
+        max_entities: Some(2),
+        max_payload_bytes: None,
+    };
+    store.create_tenant(tenant("small", quotas)).await.unwrap();
+    let small = store.get_tenant("small").await.unwrap();
+
+    small.policy_store
+        .create_policy(&utils::approve_all_policy(Some("all".to_string())), None, ValidationSettings::default())
+        .await
+        .unwrap();
+    let over = small.policy_store
+        .create_policy(&utils::approve_admin_policy(Some("admin".to_string())), None, ValidationSettings::default())
+        .await;
+    assert!(matches!(
+        over.unwrap_err().downcast_ref::<PolicyStoreError>(),
+        Some(PolicyStoreError::QuotaExceeded(1))
+    ));
+    let over = small.policy_store
+        .update_policies(
+            vec![utils::approve_all_policy(None), utils::approve_admin_policy(None)],
+            None,
+            ValidationSettings::default(),
+        )
+        .await;
+    assert!(over.is_err());
+    assert_eq!(small.policy_store.get_policies().await.len(), 1);
+
+    // Replacing the stored policy stays within the quota.
+    small.policy_store
+        .update_policies(vec![utils::approve_admin_policy(None)], None, ValidationSettings::default())
+        .await
+        .unwrap();
+
+    assert!(small.data_store.update_entities(utils::entities(), None).await.is_err());
+    assert_eq!(small.data_store.get_entities().await.len(), 0);
+}
@@ -15,6 +15,7 @@ pub(crate) fn parse_error_policy() -> Policy {
     Policy {
         id: "error".to_string(),
         content: "error".to_string(),
+        ..Default::default()
     }
 }
 
@@ -23,6 +24,7 @@ pub(crate) fn approve_all_policy(id: Option<String>) -> Policy {
     Policy {
         id,
         content: "permit(principal,action,resource);".to_string(),
+        ..Default::default()
     }
 }
 
@@ -31,6 +33,16 @@ pub(crate) fn approve_admin_policy(id: Option<String>) -> Policy {
     Policy {
         id,
         content: "permit(principal == User::\"admin@domain.com\",action,resource);".to_string(),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn annotated_forbid_policy(id: Option<String>) -> Policy {
+    let id = id.unwrap_or_else(|| "test".to_string());
+    Policy {
+        id,
+        content: "@id(\"block-suspended\")\nforbid(principal,action,resource in Document::\"private\") when { principal.suspended };".to_string(),
+        ..Default::default()
     }
 }
 
@@ -39,6 +51,7 @@ pub(crate) fn schema_valid_policy(id: Option<String>) -> Policy {
     Policy {
         id,
         content: "permit(principal in Role::\"Editor\",action,resource == ResourceType::\"document\");".to_string(),
+        ..Default::default()
     }
 }
 
@@ -47,6 +60,7 @@ pub(crate) fn schema_invalid_policy(id: Option<String>) -> Policy {
     Policy {
         id,
         content: "permit(principal in Role::\"Editor\",action,resource == Document::\"document\");".to_string(),
+        ..Default::default()
     }
 }
 